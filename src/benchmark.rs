@@ -0,0 +1,298 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::eval::Env;
+use crate::unwind::Unwind;
+
+/// How benchmarks are run.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Runs before measuring, to let caches settle.
+    pub warmup: usize,
+    /// Measured runs.
+    pub iterations: usize,
+    /// Run the suites at full size, instead of the quick size used by tests.
+    pub full: bool,
+    /// Run only benchmarks whose name contains one of these, or all if empty.
+    pub filters: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            warmup: 1,
+            iterations: 10,
+            full: false,
+            filters: Vec::new(),
+        }
+    }
+}
+
+/// Summary of the times of a benchmark, in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub iterations: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+}
+
+impl Stats {
+    pub fn from_times(times: &[Duration]) -> Stats {
+        let mut secs: Vec<f64> = times.iter().map(Duration::as_secs_f64).collect();
+        secs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let n = secs.len();
+        if n == 0 {
+            return Stats {
+                iterations: 0,
+                mean: 0.0,
+                median: 0.0,
+                stddev: 0.0,
+            };
+        }
+        let mean = secs.iter().sum::<f64>() / n as f64;
+        let median = if n % 2 == 1 {
+            secs[n / 2]
+        } else {
+            (secs[n / 2 - 1] + secs[n / 2]) / 2.0
+        };
+        // Sample standard deviation: a single run has none.
+        let stddev = if n > 1 {
+            (secs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        Stats {
+            iterations: n,
+            mean,
+            median,
+            stddev,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkResult {
+    /// Canonical path of the suite, relative to the current directory if it
+    /// is inside it, so that baselines match however the file was named.
+    pub file: PathBuf,
+    pub name: String,
+    pub stats: Stats,
+}
+
+/// Runs the benchmarks of the suites in files. A suite is a module with a
+/// Benchmarks class whose #suite: returns the benchmarks to run, each with
+/// a #name and a #block: the block is timed. #suite: is given True when
+/// running at full size, and False for a quick run.
+pub fn run_benchmarks(
+    env: &Env,
+    files: &[PathBuf],
+    options: &Options,
+) -> Result<Vec<BenchmarkResult>, Unwind> {
+    let mut results = Vec::new();
+    for file in files {
+        let path = match fs::canonicalize(file) {
+            Ok(path) => path,
+            Err(err) => return Unwind::error(&format!("Cannot load {}: {}", file.display(), err)),
+        };
+        let module = env.load_module(path.clone())?;
+        let file = relative_path(&path);
+        let suite = module.find_global_or_unwind("Benchmarks")?.send(
+            "suite:",
+            &[env.foo.make_boolean(options.full)],
+            env,
+        )?;
+        for benchmark in suite.as_vec(|vec| Ok(vec.to_vec()))? {
+            let name = benchmark.send("name", &[], env)?.as_str()?.to_string();
+            if !options.filters.is_empty()
+                && !options.filters.iter().any(|filter| name.contains(filter.as_str()))
+            {
+                continue;
+            }
+            let block = benchmark.send("block", &[], env)?;
+            for _ in 0..options.warmup {
+                block.send("value", &[], env)?;
+            }
+            let mut times = Vec::with_capacity(options.iterations);
+            for _ in 0..options.iterations {
+                let start = Instant::now();
+                block.send("value", &[], env)?;
+                times.push(start.elapsed());
+            }
+            results.push(BenchmarkResult {
+                file: file.clone(),
+                name,
+                stats: Stats::from_times(&times),
+            });
+        }
+    }
+    Ok(results)
+}
+
+/// Path relative to the current directory, if it is inside it.
+fn relative_path(path: &Path) -> PathBuf {
+    let relative = std::env::current_dir()
+        .and_then(fs::canonicalize)
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok().map(Path::to_path_buf));
+    relative.unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Writes results as a table, with the change from baseline if there is
+/// one. Benchmarks whose median got slower by more than threshold, as a
+/// fraction of the baseline median, are flagged as regressions.
+pub fn write_report(
+    results: &[BenchmarkResult],
+    baseline: Option<&[BenchmarkResult]>,
+    threshold: f64,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let width = results.iter().map(|result| result.name.len()).max().unwrap_or(0).max(9);
+    write!(
+        out,
+        "{:<width$} {:>10} {:>10} {:>10}",
+        "benchmark",
+        "mean",
+        "median",
+        "stddev",
+        width = width
+    )?;
+    if baseline.is_some() {
+        write!(out, " {:>10} {:>8}", "baseline", "change")?;
+    }
+    writeln!(out)?;
+    for result in results {
+        let stats = &result.stats;
+        write!(
+            out,
+            "{:<width$} {:>10} {:>10} {:>10}",
+            result.name,
+            seconds(stats.mean),
+            seconds(stats.median),
+            seconds(stats.stddev),
+            width = width
+        )?;
+        if let Some(baseline) = baseline {
+            match compare(result, baseline) {
+                Some((old, change)) => {
+                    write!(out, " {:>10} {:>+7.1}%", seconds(old.stats.median), change * 100.0)?;
+                    if change > threshold {
+                        write!(out, "  REGRESSION")?;
+                    }
+                }
+                None => write!(out, " {:>10} {:>8}", "-", "new")?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Results whose median got slower than in baseline by more than
+/// threshold, as a fraction of the baseline median.
+pub fn regressions<'a>(
+    results: &'a [BenchmarkResult],
+    baseline: &[BenchmarkResult],
+    threshold: f64,
+) -> Vec<&'a BenchmarkResult> {
+    results
+        .iter()
+        .filter(|result| match compare(result, baseline) {
+            Some((_, change)) => change > threshold,
+            None => false,
+        })
+        .collect()
+}
+
+/// The result for the same benchmark in baseline, and the change of the
+/// median relative to it.
+fn compare<'a>(
+    result: &BenchmarkResult,
+    baseline: &'a [BenchmarkResult],
+) -> Option<(&'a BenchmarkResult, f64)> {
+    let old = baseline.iter().find(|old| old.file == result.file && old.name == result.name)?;
+    if old.stats.median <= 0.0 {
+        return None;
+    }
+    Some((old, (result.stats.median - old.stats.median) / old.stats.median))
+}
+
+fn seconds(secs: f64) -> String {
+    if secs < 1e-3 {
+        format!("{:.1}us", secs * 1e6)
+    } else if secs < 1.0 {
+        format!("{:.2}ms", secs * 1e3)
+    } else {
+        format!("{:.3}s", secs)
+    }
+}
+
+pub fn to_json(results: &[BenchmarkResult]) -> Value {
+    let benchmarks: Vec<Value> = results
+        .iter()
+        .map(|result| {
+            json!({
+                "file": result.file.to_string_lossy(),
+                "name": result.name,
+                "iterations": result.stats.iterations,
+                "mean": result.stats.mean,
+                "median": result.stats.median,
+                "stddev": result.stats.stddev,
+            })
+        })
+        .collect();
+    json!({ "benchmarks": benchmarks })
+}
+
+pub fn from_json(json: &Value) -> Result<Vec<BenchmarkResult>, String> {
+    let benchmarks = match json.get("benchmarks").and_then(Value::as_array) {
+        Some(benchmarks) => benchmarks,
+        None => return Err("baseline has no benchmarks".to_string()),
+    };
+    let mut results = Vec::new();
+    for benchmark in benchmarks {
+        let string = |key: &str| {
+            benchmark
+                .get(key)
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("baseline benchmark has no {}: {}", key, benchmark))
+        };
+        let number = |key: &str| {
+            benchmark
+                .get(key)
+                .and_then(Value::as_f64)
+                .ok_or_else(|| format!("baseline benchmark has no {}: {}", key, benchmark))
+        };
+        results.push(BenchmarkResult {
+            file: PathBuf::from(string("file")?),
+            name: string("name")?.to_string(),
+            stats: Stats {
+                iterations: number("iterations")? as usize,
+                mean: number("mean")?,
+                median: number("median")?,
+                stddev: number("stddev")?,
+            },
+        });
+    }
+    Ok(results)
+}
+
+/// Writes results to path as a JSON baseline.
+pub fn save_baseline(results: &[BenchmarkResult], path: &Path) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&to_json(results)).map_err(|err| err.to_string())?;
+    fs::write(path, json + "\n")
+        .map_err(|err| format!("cannot write baseline '{}': {}", path.display(), err))
+}
+
+/// Reads a JSON baseline written by save_baseline.
+pub fn load_baseline(path: &Path) -> Result<Vec<BenchmarkResult>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("cannot read baseline '{}': {}", path.display(), err))?;
+    let json: Value = serde_json::from_str(&text)
+        .map_err(|err| format!("cannot parse baseline '{}': {}", path.display(), err))?;
+    from_json(&json)
+}
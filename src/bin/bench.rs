@@ -1,40 +1,150 @@
-use std::time::Instant;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-fn benchmark<F: FnOnce() -> ()>(name: &str, f: F) {
-    let t0 = Instant::now();
-    f();
-    let t1 = Instant::now();
-    println!("{}: {:?}", name, t1 - t0);
-}
+use clap::{App, Arg, ArgMatches};
+use foolang::benchmark::{self, Options};
+use foolang::isolate::Isolate;
+use foolang::time::TimeInfo;
 
-fn sum_floats(floats: Vec<f64>) {
-    let mut sum = 0.0;
-    for f in floats.iter() {
-        sum += f;
-    }
-    assert!(sum > 1.0);
+fn oops<T: std::fmt::Display>(what: T) -> ! {
+    println!("FATAL - {}\n", what);
+    std::process::exit(1)
 }
 
-fn do_sum_floats() {
-    let mut arr = Vec::new();
-    for i in 1..150_000 {
-        arr.push(i as f64);
-    }
-    benchmark("SumFloats", move || sum_floats(arr))
+fn main() {
+    // Same stack as bootstrap-interpreter, so deep recursion behaves alike.
+    std::thread::Builder::new()
+        .name(String::from("foo_bench"))
+        .stack_size(8 * 1024 * 1024)
+        .spawn(bench_main)
+        .unwrap()
+        .join()
+        .unwrap();
 }
 
-fn fibonacci(n: u64) -> u64 {
-    if n < 2 {
-        return 1;
+fn count(matches: &ArgMatches, name: &str) -> usize {
+    let value = matches.value_of(name).unwrap();
+    match value.parse() {
+        Ok(count) => count,
+        Err(_) => oops(format!("--{} expects a number, got '{}'", name, value)),
     }
-    return fibonacci(n - 1) + fibonacci(n - 2);
-}
-
-fn do_fibonacci() {
-    benchmark("Fibonacci", move || assert!(100 < fibonacci(21)));
 }
 
-fn main() {
-    do_sum_floats();
-    do_fibonacci();
+fn bench_main() {
+    TimeInfo::init();
+    let matches = App::new("Foolang benchmarks")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Runs the Benchmarks suites in Foolang modules and reports their times.")
+        .arg(
+            Arg::with_name("suite")
+                .index(1)
+                .value_name("SUITE")
+                .help("Module with a Benchmarks class.")
+                .takes_value(true)
+                .multiple(true)
+                .default_value("foo/tests/test_benchmarks.foo"),
+        )
+        .arg(
+            Arg::with_name("prelude")
+                .long("prelude")
+                .value_name("PRELUDE")
+                .help("Override standard prelude.")
+                .takes_value(true)
+                .default_value("foo/lang/bootstrap_prelude.foo"),
+        )
+        .arg(
+            Arg::with_name("warmup")
+                .long("warmup")
+                .value_name("N")
+                .help("Unmeasured runs of each benchmark before measuring it.")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("iterations")
+                .long("iterations")
+                .value_name("N")
+                .help("Measured runs of each benchmark.")
+                .takes_value(true)
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("full")
+                .long("full")
+                .help("Run the suites at full size, instead of the small size used by tests."),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .value_name("NAME")
+                .help("Run only benchmarks whose name contains NAME.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("save")
+                .long("save")
+                .value_name("FILE")
+                .help("Write the results to FILE as a JSON baseline.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("baseline")
+                .long("baseline")
+                .value_name("FILE")
+                .help("Compare the results to a baseline written by --save.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("threshold")
+                .long("threshold")
+                .value_name("PERCENT")
+                .help("Flag benchmarks whose median is slower than the baseline by more than this.")
+                .takes_value(true)
+                .default_value("10"),
+        )
+        .get_matches();
+    let options = Options {
+        warmup: count(&matches, "warmup"),
+        iterations: count(&matches, "iterations"),
+        full: matches.is_present("full"),
+        filters: matches
+            .values_of("filter")
+            .map_or(vec![], |filters| filters.map(String::from).collect()),
+    };
+    let threshold = match matches.value_of("threshold").unwrap().parse::<f64>() {
+        Ok(percent) => percent / 100.0,
+        Err(_) => oops("--threshold expects a number"),
+    };
+    let baseline = matches
+        .value_of("baseline")
+        .map(|file| benchmark::load_baseline(Path::new(file)).unwrap_or_else(|err| oops(err)));
+    let files: Vec<PathBuf> = matches.values_of("suite").unwrap().map(PathBuf::from).collect();
+    let mut roots = HashMap::new();
+    roots.insert(".".to_string(), std::env::current_dir().unwrap());
+    let prelude = Path::new(matches.value_of("prelude").unwrap());
+    let isolate = Isolate::new(prelude, roots).unwrap_or_else(|err| oops(err));
+    let results = isolate.benchmark(&files, &options).unwrap_or_else(|err| oops(err));
+    let mut stdout = std::io::stdout();
+    if let Err(err) = benchmark::write_report(&results, baseline.as_deref(), threshold, &mut stdout)
+    {
+        oops(err)
+    }
+    if let Some(file) = matches.value_of("save") {
+        if let Err(err) = benchmark::save_baseline(&results, Path::new(file)) {
+            oops(err)
+        }
+    }
+    if let Some(baseline) = &baseline {
+        let regressions = benchmark::regressions(&results, baseline, threshold);
+        if !regressions.is_empty() {
+            println!(
+                "\n{} benchmark(s) regressed by more than {}%",
+                regressions.len(),
+                threshold * 100.0
+            );
+            std::process::exit(1)
+        }
+    }
 }
@@ -0,0 +1,493 @@
+//! Debug Adapter Protocol server for editors: runs a Foolang program under
+//! the debugger, talking to the client over stdin and stdout.
+//!
+//! The program runs on the same thread as the server, so requests are only
+//! read while it is paused. Its output is sent to the client as output
+//! events.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde_json::{json, Value};
+
+use foolang::debugger::{Breakpoint, Debugger, Frontend, Resume, Stop};
+use foolang::isolate::Isolate;
+use foolang::objects::Object;
+use foolang::time::TimeInfo;
+
+static SEQ: AtomicU64 = AtomicU64::new(1);
+
+fn send(mut message: Value) {
+    message["seq"] = json!(SEQ.fetch_add(1, Ordering::SeqCst));
+    let body = message.to_string();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| out.flush())
+        .expect("cannot write to client");
+}
+
+fn event(name: &str, body: Value) {
+    send(json!({"type": "event", "event": name, "body": body}));
+}
+
+fn respond(request: &Value, result: Result<Value, String>) {
+    let mut response = json!({
+        "type": "response",
+        "request_seq": request["seq"],
+        "command": request["command"],
+    });
+    match result {
+        Ok(body) => {
+            response["success"] = json!(true);
+            response["body"] = body;
+        }
+        Err(message) => {
+            response["success"] = json!(false);
+            response["message"] = json!(message);
+        }
+    }
+    send(response)
+}
+
+/// Reads the next message, or None at the end of input.
+fn receive() -> Option<Value> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() && length.is_some() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+/// Sends the output of the program to the client.
+struct OutputEvents;
+
+impl Write for OutputEvents {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        event("output", json!({"category": "stdout", "output": String::from_utf8_lossy(buf)}));
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Arguments of the launch request.
+struct Launch {
+    program: PathBuf,
+    source: String,
+    args: Vec<String>,
+    prelude: PathBuf,
+    roots: HashMap<String, PathBuf>,
+    stop_on_entry: bool,
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    match value.as_array() {
+        Some(values) => {
+            values.iter().filter_map(|value| value.as_str().map(String::from)).collect()
+        }
+        None => Vec::new(),
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl Launch {
+    fn parse(arguments: &Value) -> Result<Launch, String> {
+        let program = match arguments["program"].as_str() {
+            Some(program) => canonical(Path::new(program)),
+            None => return Err("No program to launch".to_string()),
+        };
+        let source = std::fs::read_to_string(&program)
+            .map_err(|err| format!("Cannot load program '{}': {}", program.display(), err))?;
+        let prelude = arguments["prelude"].as_str().unwrap_or("foo/lang/bootstrap_prelude.foo");
+        let mut roots = HashMap::new();
+        for module in strings(&arguments["use"]) {
+            let path = std::fs::canonicalize(&module)
+                .map_err(|_| format!("Cannot find module: {}", module))?;
+            match (path.file_name().and_then(|name| name.to_str()), path.parent()) {
+                (Some(name), Some(root)) => roots.insert(name.to_string(), root.to_path_buf()),
+                _ => return Err(format!("Cannot determine root of module: {}", module)),
+            };
+        }
+        if let Some(root) = program.parent() {
+            roots.insert(".".to_string(), root.to_path_buf());
+        }
+        Ok(Launch {
+            program,
+            source,
+            args: strings(&arguments["args"]),
+            prelude: canonical(Path::new(prelude)),
+            roots,
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+        })
+    }
+}
+
+/// What a variablesReference handed out during the current pause refers
+/// to.
+enum Reference {
+    Locals(usize),
+    Slots(Object),
+}
+
+/// State of the session with the client.
+struct Session {
+    /// Breakpoints set before the program was launched.
+    pending: Vec<Breakpoint>,
+    configured: bool,
+    stop_on_entry: bool,
+    references: Vec<Reference>,
+}
+
+fn running(debugger: Option<&Debugger>) -> Result<&Debugger, String> {
+    debugger.ok_or_else(|| "No program is running".to_string())
+}
+
+fn frame_id(arguments: &Value) -> usize {
+    arguments["frameId"].as_u64().unwrap_or(0) as usize
+}
+
+impl Session {
+    fn new() -> Session {
+        Session {
+            pending: Vec::new(),
+            configured: false,
+            stop_on_entry: false,
+            references: Vec::new(),
+        }
+    }
+
+    /// Handles a request, returning how to resume if it resumes the program.
+    fn handle(&mut self, debugger: Option<&Debugger>, request: &Value) -> Option<Resume> {
+        let arguments = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or("");
+        let resume = match command {
+            "continue" => Some(Resume::Continue),
+            "next" => Some(Resume::StepOver),
+            "stepIn" => Some(Resume::StepIn),
+            "stepOut" => Some(Resume::StepOut),
+            _ => None,
+        };
+        if resume.is_some() {
+            let result = running(debugger).map(|_| json!({"allThreadsContinued": true}));
+            let ok = result.is_ok();
+            respond(request, result);
+            return if ok {
+                resume
+            } else {
+                None
+            };
+        }
+        let result = match command {
+            "initialize" => {
+                respond(
+                    request,
+                    Ok(json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsFunctionBreakpoints": true,
+                        "supportsTerminateRequest": true,
+                    })),
+                );
+                event("initialized", json!({}));
+                return None;
+            }
+            "configurationDone" => {
+                self.configured = true;
+                Ok(json!({}))
+            }
+            "setBreakpoints" => self.set_breakpoints(debugger, arguments),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(debugger, arguments),
+            "threads" => Ok(json!({"threads": [{"id": 1, "name": "Main"}]})),
+            "stackTrace" => running(debugger).map(stack_trace),
+            "scopes" => {
+                running(debugger).map(|debugger| self.scopes(debugger, frame_id(arguments)))
+            }
+            "variables" => {
+                running(debugger).and_then(|debugger| self.variables(debugger, arguments))
+            }
+            "evaluate" => running(debugger).and_then(|debugger| {
+                let expression = arguments["expression"].as_str().unwrap_or("");
+                let value = debugger.evaluate(frame_id(arguments), expression)?;
+                Ok(json!({
+                    "result": format!("{:?}", value),
+                    "variablesReference": self.reference(debugger, &value),
+                }))
+            }),
+            "disconnect" | "terminate" => {
+                respond(request, Ok(json!({})));
+                match debugger {
+                    Some(debugger) => debugger.quit(),
+                    None => std::process::exit(0),
+                }
+            }
+            _ => Err(format!("Unsupported request: {}", command)),
+        };
+        respond(request, result);
+        None
+    }
+
+    /// Replaces breakpoints for which replaced returns true with new ones.
+    fn replace_breakpoints(
+        &mut self,
+        debugger: Option<&Debugger>,
+        replaced: impl Fn(&Breakpoint) -> bool,
+        new: Vec<Breakpoint>,
+    ) {
+        match debugger {
+            Some(debugger) => {
+                debugger.retain_breakpoints(|breakpoint| !replaced(breakpoint));
+                for breakpoint in new {
+                    debugger.add_breakpoint(breakpoint);
+                }
+            }
+            None => {
+                self.pending.retain(|breakpoint| !replaced(breakpoint));
+                self.pending.extend(new);
+            }
+        }
+    }
+
+    fn set_breakpoints(
+        &mut self,
+        debugger: Option<&Debugger>,
+        arguments: &Value,
+    ) -> Result<Value, String> {
+        let path = match arguments["source"]["path"].as_str() {
+            Some(path) => canonical(Path::new(path)),
+            None => return Err("No source path".to_string()),
+        };
+        let lines: Vec<usize> = match arguments["breakpoints"].as_array() {
+            Some(breakpoints) => breakpoints
+                .iter()
+                .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                .map(|line| line as usize)
+                .collect(),
+            None => Vec::new(),
+        };
+        self.replace_breakpoints(
+            debugger,
+            |breakpoint| matches!(breakpoint, Breakpoint::Line(file, _) if file == &path),
+            lines.iter().map(|line| Breakpoint::Line(path.clone(), *line)).collect(),
+        );
+        let verified: Vec<Value> =
+            lines.iter().map(|line| json!({"verified": true, "line": line})).collect();
+        Ok(json!({ "breakpoints": verified }))
+    }
+
+    fn set_function_breakpoints(
+        &mut self,
+        debugger: Option<&Debugger>,
+        arguments: &Value,
+    ) -> Result<Value, String> {
+        let names: Vec<String> = match arguments["breakpoints"].as_array() {
+            Some(breakpoints) => breakpoints
+                .iter()
+                .filter_map(|breakpoint| breakpoint["name"].as_str().map(String::from))
+                .collect(),
+            None => Vec::new(),
+        };
+        self.replace_breakpoints(
+            debugger,
+            |breakpoint| matches!(breakpoint, Breakpoint::Method(_)),
+            names.iter().map(|name| Breakpoint::Method(name.clone())).collect(),
+        );
+        let verified: Vec<Value> = names.iter().map(|_| json!({"verified": true})).collect();
+        Ok(json!({ "breakpoints": verified }))
+    }
+
+    fn scopes(&mut self, debugger: &Debugger, frame: usize) -> Value {
+        self.references.push(Reference::Locals(frame));
+        let mut scopes = vec![json!({
+            "name": "Locals",
+            "variablesReference": self.references.len(),
+            "expensive": false,
+        })];
+        if let Some(receiver) = debugger.receiver(frame) {
+            scopes.push(json!({
+                "name": "Receiver",
+                "variablesReference": self.reference(debugger, &receiver),
+                "expensive": false,
+            }));
+        }
+        json!({ "scopes": scopes })
+    }
+
+    fn variables(&mut self, debugger: &Debugger, arguments: &Value) -> Result<Value, String> {
+        let index = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+        let variables = match index.checked_sub(1).and_then(|i| self.references.get(i)) {
+            Some(Reference::Locals(frame)) => debugger.locals(*frame),
+            Some(Reference::Slots(object)) => debugger.slots(object),
+            None => return Err(format!("No variables reference {}", index)),
+        };
+        let variables: Vec<Value> = variables
+            .iter()
+            .map(|(name, value)| {
+                json!({
+                    "name": name,
+                    "value": format!("{:?}", value),
+                    "variablesReference": self.reference(debugger, value),
+                })
+            })
+            .collect();
+        Ok(json!({ "variables": variables }))
+    }
+
+    /// Reference to the slots of value if it has any, otherwise 0.
+    fn reference(&mut self, debugger: &Debugger, value: &Object) -> usize {
+        if debugger.slots(value).is_empty() {
+            0
+        } else {
+            self.references.push(Reference::Slots(value.clone()));
+            self.references.len()
+        }
+    }
+
+    /// Handles requests until one resumes the program. Quits if the client
+    /// goes away.
+    fn serve(&mut self, debugger: &Debugger) -> Resume {
+        loop {
+            match receive() {
+                Some(request) => {
+                    if let Some(resume) = self.handle(Some(debugger), &request) {
+                        return resume;
+                    }
+                }
+                None => debugger.quit(),
+            }
+        }
+    }
+}
+
+fn stack_trace(debugger: &Debugger) -> Value {
+    let frames: Vec<Value> = debugger
+        .stack()
+        .iter()
+        .enumerate()
+        .map(|(id, frame)| {
+            let path = canonical(&frame.file);
+            json!({
+                "id": id,
+                "name": frame.name,
+                "source": {
+                    "name": path.file_name().map(|name| name.to_string_lossy()),
+                    "path": path,
+                },
+                "line": frame.line,
+                "column": frame.column,
+            })
+        })
+        .collect();
+    json!({ "totalFrames": frames.len(), "stackFrames": frames })
+}
+
+impl Frontend for Session {
+    fn paused(&mut self, debugger: &Debugger, stop: Stop) -> Resume {
+        let reason = match stop {
+            Stop::Entry => {
+                for breakpoint in self.pending.drain(..) {
+                    debugger.add_breakpoint(breakpoint);
+                }
+                // Configuration requests follow launch: the program starts
+                // once they are done.
+                while !self.configured {
+                    match receive() {
+                        Some(request) => {
+                            self.handle(Some(debugger), &request);
+                        }
+                        None => debugger.quit(),
+                    }
+                }
+                if !self.stop_on_entry {
+                    return Resume::Continue;
+                }
+                "entry"
+            }
+            Stop::Breakpoint => "breakpoint",
+            Stop::Step => "step",
+        };
+        event("stopped", json!({"reason": reason, "threadId": 1, "allThreadsStopped": true}));
+        let resume = self.serve(debugger);
+        self.references.clear();
+        resume
+    }
+}
+
+fn main() {
+    // Same stack size as bootstrap-interpreter.
+    std::thread::Builder::new()
+        .name(String::from("foo_main"))
+        .stack_size(8 * 1024 * 1024)
+        .spawn(dap_main)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+fn dap_main() {
+    TimeInfo::init();
+    let mut session = Session::new();
+    let launch = loop {
+        let request = match receive() {
+            Some(request) => request,
+            None => return,
+        };
+        if request["command"] == "launch" {
+            match Launch::parse(&request["arguments"]) {
+                Ok(launch) => {
+                    respond(&request, Ok(json!({})));
+                    break launch;
+                }
+                Err(err) => respond(&request, Err(err)),
+            }
+        } else {
+            session.handle(None, &request);
+        }
+    };
+    session.stop_on_entry = launch.stop_on_entry;
+    let debugger = Debugger::new(&launch.program, Box::new(session));
+    let Launch {
+        source,
+        args,
+        prelude,
+        roots,
+        ..
+    } = launch;
+    let res = Isolate::with_debugger(&prelude, roots, debugger)
+        .and_then(|isolate| isolate.run_with_output(&source, args, Box::new(OutputEvents)));
+    let code = match res {
+        Ok(()) => 0,
+        Err(err) => {
+            event("output", json!({"category": "stderr", "output": format!("{}\n", err)}));
+            1
+        }
+    };
+    event("exited", json!({ "exitCode": code }));
+    event("terminated", json!({}));
+    while let Some(request) = receive() {
+        if request["command"] == "disconnect" {
+            respond(&request, Ok(json!({})));
+            break;
+        }
+        respond(&request, Err("The program has terminated".to_string()));
+    }
+}
@@ -77,13 +77,15 @@ pub fn as_array<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Array, Unwind> {
 }
 
 pub fn into_array(foolang: &Foolang, data: Vec<Object>, etype: Option<Object>) -> Object {
-    Object {
-        vtable: Rc::clone(&foolang.array_vtable),
-        datum: Datum::Array(Rc::new(Array {
+    let array = Object::new(
+        Rc::clone(&foolang.array_vtable),
+        Datum::Array(Rc::new(Array {
             etype,
             data: RefCell::new(data),
         })),
-    }
+    );
+    foolang.heap.register(&array);
+    array
 }
 
 fn array_of(receiver: &Object, args: &[Object], env: &Env) -> Eval {
@@ -92,13 +94,15 @@ fn array_of(receiver: &Object, args: &[Object], env: &Env) -> Eval {
     for elt in data.iter() {
         etype.send("typecheck:", std::slice::from_ref(elt), env)?;
     }
-    Ok(Object {
-        vtable: receiver.vtable.clone(),
-        datum: Datum::Array(Rc::new(Array {
+    let array = Object::new(
+        receiver.vtable(&env.foo).clone(),
+        Datum::Array(Rc::new(Array {
             etype: Some(etype),
             data: RefCell::new(data.clone()),
         })),
-    })
+    );
+    env.foo.heap.register(&array);
+    Ok(array)
 }
 
 pub fn class_vtable() -> Vtable {
@@ -12,9 +12,13 @@ pub fn class_vtable() -> Vtable {
 }
 
 fn boolean_if_true_if_false(receiver: &Object, args: &[Object], env: &Env) -> Eval {
-    if receiver.boolean() {
-        args[0].send("value", &[], env)
+    let arm = if receiver.boolean() {
+        &args[0]
     } else {
-        args[1].send("value", &[], env)
+        &args[1]
+    };
+    if env.foo.coverage.is_some() {
+        env.count_branch(arm);
     }
+    arm.send("value", &[], env)
 }
@@ -1,4 +1,4 @@
-use std::cell::{Ref, RefCell, RefMut};
+use std::cell::{BorrowError, Ref, RefCell, RefMut};
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
@@ -18,6 +18,9 @@ impl ByteArray {
     pub fn borrow_mut(&self) -> RefMut<Vec<u8>> {
         self.data.borrow_mut()
     }
+    pub fn try_borrow(&self) -> Result<Ref<'_, Vec<u8>>, BorrowError> {
+        self.data.try_borrow()
+    }
 }
 
 impl PartialEq for ByteArray {
@@ -89,12 +92,14 @@ fn class_byte_array_new(_receiver: &Object, args: &[Object], env: &Env) -> Eval
     } else {
         let mut vec = Vec::with_capacity(arg as usize);
         vec.resize(arg as usize, 0 as u8);
-        Ok(Object {
-            vtable: Rc::clone(&env.foo.byte_array_vtable),
-            datum: Datum::ByteArray(Rc::new(ByteArray {
+        let bytes = Object::new(
+            Rc::clone(&env.foo.byte_array_vtable),
+            Datum::ByteArray(Rc::new(ByteArray {
                 data: RefCell::new(vec),
             })),
-        })
+        );
+        env.foo.heap.register(&bytes);
+        Ok(bytes)
     }
 }
 
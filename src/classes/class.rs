@@ -11,6 +11,8 @@ pub struct Class {
     pub class_vtable: Rc<Vtable>,
     pub instance_vtable: Rc<Vtable>,
     pub interface: bool,
+    /// Instances of value classes are immutable and compared by content.
+    pub value: bool,
 }
 
 impl PartialEq for Class {
@@ -29,39 +31,51 @@ impl Hash for Class {
 
 impl Class {
     pub fn new_class(name: &str) -> Object {
+        Class::new_class_with_value(name, false)
+    }
+    pub fn new_value_class(name: &str) -> Object {
+        Class::new_class_with_value(name, true)
+    }
+    fn new_class_with_value(name: &str, value: bool) -> Object {
         let class_vtable = Rc::new(Vtable::for_class(name));
         let instance_vtable = Rc::new(Vtable::for_instance(name));
-        let class = Object {
-            vtable: class_vtable.clone(),
-            datum: Datum::Class(Rc::new(Class {
+        let class = Object::new(
+            class_vtable.clone(),
+            Datum::Class(Rc::new(Class {
                 class_vtable,
                 instance_vtable: instance_vtable.clone(),
                 interface: false,
+                value,
             })),
-        };
+        );
         instance_vtable.class.borrow_mut().replace(class.clone());
         class
     }
     pub fn new_interface(name: &str) -> Object {
         let class_vtable = Rc::new(Vtable::for_class(name));
-        Object {
-            vtable: class_vtable.clone(),
-            datum: Datum::Class(Rc::new(Class {
+        let instance_vtable = Rc::new(Vtable::for_instance(name));
+        let interface = Object::new(
+            class_vtable.clone(),
+            Datum::Class(Rc::new(Class {
                 class_vtable,
-                instance_vtable: Rc::new(Vtable::for_instance(name)),
+                instance_vtable: instance_vtable.clone(),
                 interface: true,
+                value: false,
             })),
-        }
+        );
+        instance_vtable.class.borrow_mut().replace(interface.clone());
+        interface
     }
     pub fn object(class_vtable: &Rc<Vtable>, instance_vtable: &Rc<Vtable>) -> Object {
-        let class = Object {
-            vtable: Rc::clone(class_vtable),
-            datum: Datum::Class(Rc::new(Class {
+        let class = Object::new(
+            Rc::clone(class_vtable),
+            Datum::Class(Rc::new(Class {
                 class_vtable: class_vtable.clone(),
                 instance_vtable: Rc::clone(instance_vtable),
                 interface: false,
+                value: false,
             })),
-        };
+        );
         instance_vtable.class.borrow_mut().replace(class.clone());
         class
     }
@@ -158,21 +172,28 @@ fn interface_typecheck_(receiver: &Object, args: &[Object], env: &Env) -> Eval {
 }
 
 pub fn generic_class_class(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
-    if let Some(ref class) = *receiver.vtable.class.borrow() {
+    let vtable = receiver.vtable(&env.foo);
+    if let Some(ref class) = *vtable.class.borrow() {
         return Ok(class.clone());
     }
-    let metaclass = Object {
-        vtable: env.foo.class_vtable.clone(),
-        datum: Datum::Class(Rc::new(Class {
+    let metaclass = Object::new(
+        env.foo.class_vtable.clone(),
+        Datum::Class(Rc::new(Class {
             class_vtable: env.foo.class_vtable.clone(),
-            instance_vtable: receiver.vtable.clone(),
+            instance_vtable: vtable.clone(),
             interface: false,
+            value: false,
         })),
-    };
-    receiver.vtable.class.borrow_mut().replace(metaclass.clone());
+    );
+    vtable.class.borrow_mut().replace(metaclass.clone());
     Ok(metaclass)
 }
 
+pub fn generic_class_all_instances(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    let class = receiver.as_class_ref()?;
+    Ok(env.foo.into_array(env.foo.heap.all_instances(&class.instance_vtable), None))
+}
+
 pub fn generic_class_name(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
     Ok(env.foo.make_string(&receiver.as_class_ref()?.instance_vtable.name))
 }
@@ -186,7 +207,7 @@ pub fn generic_class_interfaces(receiver: &Object, _args: &[Object], env: &Env)
 }
 
 pub fn generic_instance_class(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
-    match *receiver.vtable.class.borrow() {
+    match *receiver.vtable(&env.foo).class.borrow() {
         Some(ref class) => Ok(class.clone()),
         None => Ok(env.foo.make_boolean(false)),
     }
@@ -195,7 +216,7 @@ pub fn generic_instance_class(receiver: &Object, _args: &[Object], env: &Env) ->
 pub fn generic_class_typecheck_(receiver: &Object, args: &[Object], env: &Env) -> Eval {
     let class = receiver.as_class_ref()?;
     let arg = &args[0];
-    if arg.is_type(&class.instance_vtable) {
+    if arg.is_type(&class.instance_vtable, &env.foo) {
         Ok(arg.clone())
     } else {
         // panic!("boom");
@@ -209,7 +230,7 @@ pub fn generic_class_typecheck_(receiver: &Object, args: &[Object], env: &Env) -
 
 pub fn generic_class_includes_(receiver: &Object, args: &[Object], env: &Env) -> Eval {
     let class = receiver.as_class_ref()?;
-    Ok(env.foo.make_boolean(args[0].is_type(&class.instance_vtable)))
+    Ok(env.foo.make_boolean(args[0].is_type(&class.instance_vtable, &env.foo)))
 }
 
 pub fn generic_class_add_direct_method_(receiver: &Object, args: &[Object], env: &Env) -> Eval {
@@ -248,12 +269,15 @@ pub fn generic_class_new_(receiver: &Object, args: &[Object], env: &Env) -> Eval
         }
         instance_variables.push(val);
     }
-    Ok(Object {
-        vtable: Rc::clone(&class.instance_vtable),
-        datum: Datum::Instance(Rc::new(Instance {
+    let instance = Object::new(
+        Rc::clone(&class.instance_vtable),
+        Datum::Instance(Rc::new(Instance {
             instance_variables: RefCell::new(instance_variables),
+            value: class.value,
         })),
-    })
+    );
+    env.foo.heap.register(&instance);
+    Ok(instance)
 }
 
 // FIXME: duplicates logic in Foolang::make_class()
@@ -290,8 +314,8 @@ fn class_new_(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
         .iter()
     {
         // println!("Adding class method: {}", method);
-        class_object
-            .vtable
+        class
+            .class_vtable
             .add_method(method.send("name", &[], env)?.as_str()?, Method::object(method))?
     }
     for method in args[4]
@@ -324,8 +348,8 @@ fn interface_new_(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
         .iter()
     {
         // println!("Adding interface method: {}", method);
-        interface_object
-            .vtable
+        interface
+            .class_vtable
             .add_method(method.send("name", &[], env)?.as_str()?, Method::object(method))?
     }
     for method in args[3]
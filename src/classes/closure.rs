@@ -1,4 +1,4 @@
-use crate::eval::{Binding, Env, EnvRef, SymbolTable};
+use crate::eval::{Binding, Env, EnvRef, SymbolTable, Tail};
 use crate::expr::*;
 use crate::objects::{Arg, Eval, Object, Signature, Source, Vtable};
 use crate::unwind::Unwind;
@@ -13,6 +13,8 @@ pub struct Closure {
     pub params: Vec<Arg>,
     pub body: Expr,
     pub signature: Signature,
+    /// Slots of the activation frame, see resolve.rs.
+    pub slots: SlotNames,
 }
 
 impl PartialEq for Closure {
@@ -31,6 +33,25 @@ impl Hash for Closure {
 
 impl Closure {
     pub fn apply(&self, receiver: Option<&Object>, args: &[Object], send_env: &Env) -> Eval {
+        self.apply_tail(receiver, args, send_env)?.resolve(send_env)
+    }
+
+    pub fn apply0(&self, receiver: Option<&Object>, send_env: &Env) -> Eval {
+        self.apply0_tail(receiver, send_env)?.resolve(send_env)
+    }
+
+    pub fn apply1(&self, receiver: Option<&Object>, args: &[Object], send_env: &Env) -> Eval {
+        self.apply1_tail(receiver, args, send_env)?.resolve(send_env)
+    }
+
+    /// Like apply, but a send in tail position of the body is returned for
+    /// the caller to perform instead of being performed here.
+    pub fn apply_tail(
+        &self,
+        receiver: Option<&Object>,
+        args: &[Object],
+        send_env: &Env,
+    ) -> Result<Tail, Unwind> {
         if self.params.len() != args.len() {
             return Unwind::error_at(
                 // FIXME: call-site would be 1000 x better...
@@ -45,80 +66,88 @@ impl Closure {
             );
         }
         if args.len() == 0 {
-            return self.apply0(receiver, send_env);
+            return self.apply0_tail(receiver, send_env);
         }
         if args.len() == 1 {
-            return self.apply1(receiver, args, send_env);
+            return self.apply1_tail(receiver, args, send_env);
         }
-        let closure_env = Env {
-            env_ref: self
-                .env_ref
-                .extend(SymbolTable::Big(Vec::with_capacity(args.len())), receiver),
-            foo: send_env.foo.clone(),
-        };
-        for ((arg, vt), obj) in self
-            .params
+        let closure_env = self.activation(receiver, send_env);
+        for (index, (vt, obj)) in self
+            .signature
+            .parameter_types
             .iter()
-            .zip(&self.signature.parameter_types)
             .zip(args.into_iter().map(|x| (*x).clone()))
+            .enumerate()
         {
             let binding = match vt {
                 None => Binding::untyped(obj),
                 Some(ref typed) => Binding::typed(typed.clone(), obj, &closure_env)?,
             };
-            closure_env.ensure_binding(&arg.name, binding);
-        }
-        let ret = closure_env.eval(&self.body);
-        let result = match ret {
-            Ok(value) => value,
-            Err(Unwind::ReturnFrom(ref ret_env, ref value)) if ret_env == &closure_env.env_ref => {
-                value.clone()
-            }
-            Err(unwind) => {
-                return Err(unwind);
-            }
-        };
-        if let Some(typed) = &self.signature.return_type {
-            typed.send("typecheck:", &[result], &closure_env).source(&self.body.source_location())
-        } else {
-            Ok(result)
+            closure_env.env_ref.bind_slot(index, binding);
         }
+        self.run(closure_env)
     }
 
-    pub fn apply0(&self, receiver: Option<&Object>, send_env: &Env) -> Eval {
-        let closure_env = Env {
-            env_ref: self.env_ref.extend(SymbolTable::Empty, receiver),
-            foo: send_env.foo.clone(),
+    pub fn apply0_tail(&self, receiver: Option<&Object>, send_env: &Env) -> Result<Tail, Unwind> {
+        let closure_env = self.activation(receiver, send_env);
+        self.run(closure_env)
+    }
+
+    pub fn apply1_tail(
+        &self,
+        receiver: Option<&Object>,
+        args: &[Object],
+        send_env: &Env,
+    ) -> Result<Tail, Unwind> {
+        let closure_env = self.activation(receiver, send_env);
+        let obj = args[0].clone();
+        let binding = match &self.signature.parameter_types[0] {
+            None => Binding::untyped(obj),
+            Some(ref typed) => Binding::typed(typed.clone(), obj, &closure_env)?,
         };
-        let ret = closure_env.eval(&self.body);
-        let result = match ret {
-            Ok(value) => value,
-            Err(Unwind::ReturnFrom(ref ret_env, ref value)) if ret_env == &closure_env.env_ref => {
-                value.clone()
+        closure_env.env_ref.bind_slot(0, binding);
+        self.run(closure_env)
+    }
+
+    fn run(&self, closure_env: Env) -> Result<Tail, Unwind> {
+        match closure_env.foo.debugger.clone() {
+            None => {
+                let ret = closure_env.eval_tail(&self.body);
+                self.finish(closure_env, ret)
             }
-            Err(unwind) => {
-                return Err(unwind);
+            Some(debugger) => {
+                debugger.enter(&self.name, &closure_env);
+                let ret = closure_env.eval_tail(&self.body);
+                // Before finish: the debugger's reference to the frame would
+                // keep it from passing on tail sends.
+                debugger.leave();
+                self.finish(closure_env, ret)
             }
-        };
-        if let Some(typed) = &self.signature.return_type {
-            typed.send("typecheck:", &[result], &closure_env).source(&self.body.source_location())
-        } else {
-            Ok(result)
         }
     }
 
-    pub fn apply1(&self, receiver: Option<&Object>, args: &[Object], send_env: &Env) -> Eval {
-        let closure_env = Env {
-            env_ref: self.env_ref.extend(SymbolTable::Empty, receiver),
+    fn activation(&self, receiver: Option<&Object>, send_env: &Env) -> Env {
+        Env {
+            env_ref: self.env_ref.extend(SymbolTable::slots(&self.slots), receiver),
             foo: send_env.foo.clone(),
+        }
+    }
+
+    /// Handles returns to the frame of the closure, and checks the return
+    /// type. A tail send is passed on only if nothing else refers to the
+    /// frame anymore, since a block holding on to it could still return
+    /// from it, and if there is no return type to check.
+    fn finish(&self, closure_env: Env, ret: Result<Tail, Unwind>) -> Result<Tail, Unwind> {
+        let ret = match ret {
+            Ok(Tail::Send(send))
+                if self.signature.return_type.is_none() && closure_env.env_ref.is_unshared() =>
+            {
+                return Ok(Tail::Send(send));
+            }
+            Ok(tail) => tail.resolve(&closure_env),
+            Err(unwind) => Err(unwind),
         };
-        let obj = args[0].clone();
-        let binding = match &self.signature.parameter_types[0] {
-            None => Binding::untyped(obj),
-            Some(ref typed) => Binding::typed(typed.clone(), obj, &closure_env)?,
-        };
-        closure_env.ensure_binding(&self.params[0].name, binding);
-        let ret = closure_env.eval(&self.body);
+        closure_env.env_ref.exit(&self.body);
         let result = match ret {
             Ok(value) => value,
             Err(Unwind::ReturnFrom(ref ret_env, ref value)) if ret_env == &closure_env.env_ref => {
@@ -129,9 +158,12 @@ impl Closure {
             }
         };
         if let Some(typed) = &self.signature.return_type {
-            typed.send("typecheck:", &[result], &closure_env).source(&self.body.source_location())
+            typed
+                .send("typecheck:", &[result], &closure_env)
+                .source(&self.body.source_location())
+                .map(Tail::Value)
         } else {
-            Ok(result)
+            Ok(Tail::Value(result))
         }
     }
 }
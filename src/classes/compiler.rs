@@ -29,9 +29,9 @@ impl Hash for Compiler {
 }
 
 pub fn make_compiler(foo: &Foolang) -> Object {
-    Object {
-        vtable: Rc::clone(&foo.compiler_vtable),
-        datum: Datum::Compiler(Rc::new(Compiler {
+    Object::new(
+        Rc::clone(&foo.compiler_vtable),
+        Datum::Compiler(Rc::new(Compiler {
             // This makes the objects resulting from Compiler eval share same
             // vtable instances as the parent, which seems like the right thing
             // -- but it would be nice to be able to specify a different
@@ -40,7 +40,7 @@ pub fn make_compiler(foo: &Foolang) -> Object {
             source: RefCell::new(String::new()),
             parsed: RefCell::new(Vec::new()),
         })),
-    }
+    )
 }
 
 pub fn class_vtable() -> Vtable {
@@ -69,7 +69,7 @@ fn compiler_evaluate(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
     for s in compiler.parsed.borrow().iter() {
         res = match s {
             Syntax::Def(ref def) => compiler.env.augment(def).context(&source)?,
-            Syntax::Expr(ref expr) => compiler.env.eval(expr).context(&source)?,
+            Syntax::Expr(ref expr) => compiler.env.eval_toplevel(expr).context(&source)?,
         }
     }
     Ok(res)
@@ -1,4 +1,4 @@
-use std::cell::{Ref, RefCell, RefMut};
+use std::cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut};
 use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
@@ -19,6 +19,12 @@ impl Dictionary {
     pub fn borrow_mut(&self) -> RefMut<HashMap<Object, Object>> {
         self.data.borrow_mut()
     }
+    pub fn try_borrow(&self) -> Result<Ref<'_, HashMap<Object, Object>>, BorrowError> {
+        self.data.try_borrow()
+    }
+    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, HashMap<Object, Object>>, BorrowMutError> {
+        self.data.try_borrow_mut()
+    }
 }
 
 impl PartialEq for Dictionary {
@@ -68,12 +74,14 @@ pub fn instance_vtable() -> Vtable {
 }
 
 pub fn into_dictionary(foolang: &Foolang, data: HashMap<Object, Object>) -> Object {
-    Object {
-        vtable: Rc::clone(&foolang.dictionary_vtable),
-        datum: Datum::Dictionary(Rc::new(Dictionary {
+    let dictionary = Object::new(
+        Rc::clone(&foolang.dictionary_vtable),
+        Datum::Dictionary(Rc::new(Dictionary {
             data: RefCell::new(data),
         })),
-    }
+    );
+    foolang.heap.register(&dictionary);
+    dictionary
 }
 
 pub fn as_dictionary<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Dictionary, Unwind> {
@@ -84,13 +92,7 @@ pub fn as_dictionary<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Dictionary, U
 }
 
 fn class_dictionary_new(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
-    let data = RefCell::new(HashMap::new());
-    Ok(Object {
-        vtable: env.foo.dictionary_vtable.clone(),
-        datum: Datum::Dictionary(Rc::new(Dictionary {
-            data,
-        })),
-    })
+    Ok(into_dictionary(&env.foo, HashMap::new()))
 }
 
 fn dictionary_at_if_none(receiver: &Object, args: &[Object], env: &Env) -> Eval {
@@ -24,10 +24,7 @@ pub struct File {
 
 impl File {
     fn object(self, env: &Env) -> Object {
-        Object {
-            vtable: env.foo.file_vtable.clone(),
-            datum: Datum::File(Rc::new(self)),
-        }
+        Object::new(env.foo.file_vtable.clone(), Datum::File(Rc::new(self)))
     }
     fn open_options(&self) -> OpenOptions {
         let mut opts = OpenOptions::new();
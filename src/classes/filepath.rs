@@ -60,12 +60,12 @@ pub fn instance_vtable() -> Vtable {
 }
 
 fn into_filepath(path: PathBuf, env: &Env) -> Object {
-    Object {
-        vtable: env.foo.filepath_vtable.clone(),
-        datum: Datum::FilePath(Rc::new(FilePath {
+    Object::new(
+        env.foo.filepath_vtable.clone(),
+        Datum::FilePath(Rc::new(FilePath {
             path,
         })),
-    }
+    )
 }
 
 fn filepath_delete_file(receiver: &Object, _args: &[Object], _env: &Env) -> Eval {
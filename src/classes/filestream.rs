@@ -24,6 +24,14 @@ impl FileStream {
             Ok(RefMut::map(f, |opt| opt.as_mut().unwrap()))
         }
     }
+
+    /// Closes the file unless it is in use, returns true if it was open.
+    pub fn close(&self) -> bool {
+        match self.file.try_borrow_mut() {
+            Ok(mut file) => file.take().is_some(),
+            Err(_) => false,
+        }
+    }
 }
 
 impl PartialEq for FileStream {
@@ -88,19 +96,19 @@ pub fn instance_vtable() -> Vtable {
 }
 
 pub fn make_filestream(path: &Path, file: File, env: &Env) -> Object {
-    Object {
-        vtable: env.foo.filestream_vtable.clone(),
-        datum: Datum::FileStream(Rc::new(FileStream {
+    let filestream = Object::new(
+        env.foo.filestream_vtable.clone(),
+        Datum::FileStream(Rc::new(FileStream {
             path: PathBuf::from(path),
             file: RefCell::new(Some(file)),
         })),
-    }
+    );
+    env.foo.heap.register(&filestream);
+    filestream
 }
 
 fn filestream_close(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
-    Ok(env.foo.make_boolean(
-        receiver.as_filestream("FileStream#close")?.file.borrow_mut().take().is_some(),
-    ))
+    Ok(env.foo.make_boolean(receiver.as_filestream("FileStream#close")?.close()))
 }
 
 fn filestream_pathname(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
@@ -0,0 +1,348 @@
+use std::cell::{Cell, RefCell};
+use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle, ThreadId};
+
+use crate::eval::Env;
+use crate::heap::Heap;
+use crate::objects::{Datum, Eval, Object, Vtable};
+use crate::unwind::Unwind;
+
+/// Same as the main thread.
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Moves values holding Objects between a generator thread and its consumer.
+///
+/// Objects are not Send, since they are reference counted with Rc. This is
+/// fine as long as the two threads never run at the same time: the consumer
+/// blocks after resuming the generator until it yields or finishes, and the
+/// generator blocks after yielding until resumed, so only one of them touches
+/// the reference counts at a time, and the channels order those accesses.
+struct Handoff<T>(T);
+
+/// What the generator thread starts with: the block, its environment, and
+/// its ends of the channels.
+type Start = (Object, Env, Sender<Handoff<Yielded>>, Receiver<Resume>);
+
+// SAFETY: Only these two are handed off. Start is sent before the generator
+// thread runs, and the consumer blocks on the yielded channel right after.
+// After sending a Yielded::Value the generator thread blocks on the resume
+// channel, touching nothing until resumed. A Yielded::Done or Yielded::Error
+// is sent, and the channel disconnects if the thread panics, only after run
+// has dropped every Rc the thread held.
+unsafe impl Send for Handoff<Start> {}
+unsafe impl Send for Handoff<Yielded> {}
+
+enum Resume {
+    Next,
+    Cancel,
+}
+
+enum Yielded {
+    Value(Object),
+    Done,
+    Error(Unwind),
+}
+
+enum State {
+    New(Object),
+    Suspended(Suspended),
+    Running,
+    Done,
+}
+
+/// Thread of a generator waiting to be resumed.
+pub struct Suspended {
+    resume: Sender<Resume>,
+    yielded: Receiver<Handoff<Yielded>>,
+    thread: JoinHandle<()>,
+}
+
+impl Suspended {
+    /// Lets the generator unwind on its own thread, running its finally:
+    /// blocks, and waits for it to finish.
+    pub fn cancel(self) {
+        while self.resume.send(Resume::Cancel).is_ok() {
+            match self.yielded.recv() {
+                Ok(Handoff(Yielded::Value(_))) => continue,
+                _ => break,
+            }
+        }
+        let _ = self.thread.join();
+    }
+}
+
+/// Coroutine running a block on its own stack: the block receives a Yielder,
+/// and each value it yields is returned by one call to next.
+pub struct Generator {
+    state: RefCell<State>,
+    /// Value yielded but not yet returned by next, see isDone.
+    lookahead: RefCell<Option<Object>>,
+    /// Where to queue the generator for cancellation if it is dropped
+    /// while suspended. Weak, since the heap can refer to generators.
+    heap: Weak<Heap>,
+}
+
+impl PartialEq for Generator {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Generator {}
+
+impl Hash for Generator {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self, state);
+    }
+}
+
+impl Drop for Generator {
+    fn drop(&mut self) {
+        // Cancelling runs finally: blocks, which must not happen in the middle
+        // of whatever dropped the generator, eg. a collection: leave it to the
+        // next safe point. Without a heap nothing else is running.
+        if let State::Suspended(suspended) = std::mem::replace(self.state.get_mut(), State::Done) {
+            match self.heap.upgrade() {
+                Some(heap) => heap.queue_cancellation(suspended),
+                None => suspended.cancel(),
+            }
+        }
+    }
+}
+
+impl Generator {
+    /// Returns the next value, or None if the generator is done.
+    fn next(&self, env: &Env) -> Result<Option<Object>, Unwind> {
+        if let Some(value) = self.lookahead.borrow_mut().take() {
+            return Ok(Some(value));
+        }
+        let (resume, yielded, thread) = match self.state.replace(State::Running) {
+            State::New(block) => start(block, env),
+            State::Suspended(Suspended {
+                resume,
+                yielded,
+                thread,
+            }) => {
+                let _ = resume.send(Resume::Next);
+                (resume, yielded, thread)
+            }
+            State::Running => return Unwind::error("Generator resumed while running"),
+            State::Done => {
+                self.state.replace(State::Done);
+                return Ok(None);
+            }
+        };
+        let res = match yielded.recv() {
+            Ok(Handoff(Yielded::Value(value))) => {
+                self.state.replace(State::Suspended(Suspended {
+                    resume,
+                    yielded,
+                    thread,
+                }));
+                return Ok(Some(value));
+            }
+            Ok(Handoff(Yielded::Done)) => Ok(None),
+            Ok(Handoff(Yielded::Error(unwind))) => Err(unwind),
+            Err(_) => Unwind::error("Generator thread died"),
+        };
+        self.state.replace(State::Done);
+        let _ = thread.join();
+        res
+    }
+
+    /// True if the generator has no more values. Runs the generator up to
+    /// the next yield if necessary.
+    fn is_done(&self, env: &Env) -> Result<bool, Unwind> {
+        if self.lookahead.borrow().is_some() {
+            return Ok(false);
+        }
+        match self.next(env)? {
+            Some(value) => {
+                self.lookahead.replace(Some(value));
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+}
+
+/// Passed to the block of a generator: value: suspends the generator,
+/// returning the argument from next.
+pub struct Yielder {
+    thread: ThreadId,
+    yielded: Sender<Handoff<Yielded>>,
+    resume: Receiver<Resume>,
+    cancelled: Cell<bool>,
+    /// Depth of the debugger stack when the generator was last resumed:
+    /// frames above it are the generator's.
+    base: Cell<usize>,
+}
+
+impl PartialEq for Yielder {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Yielder {}
+
+impl Hash for Yielder {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self, state);
+    }
+}
+
+fn start(block: Object, env: &Env) -> (Sender<Resume>, Receiver<Handoff<Yielded>>, JoinHandle<()>) {
+    let (resume_sender, resume_receiver) = channel();
+    let (yielded_sender, yielded_receiver) = channel();
+    // Not the caller's environment: if that refers to the generator, the
+    // generator would keep itself alive.
+    let start: Handoff<Start> =
+        Handoff((block, env.foo.toplevel_env(), yielded_sender, resume_receiver));
+    let thread = thread::Builder::new()
+        .name("generator".to_string())
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let Handoff((block, env, yielded, resume)) = start;
+            let last = run(block, env, yielded.clone(), resume);
+            // The consumer runs as soon as it receives the last message, or
+            // as soon as this sender is dropped if run panics: either way run
+            // has dropped everything it had by then.
+            let _ = yielded.send(Handoff(last));
+        })
+        .expect("BUG: could not start generator thread");
+    (resume_sender, yielded_receiver, thread)
+}
+
+/// Runs the block of a generator on its own thread. Owns everything the
+/// thread holds that refers to Objects, so that all of it is dropped when
+/// this returns or unwinds.
+fn run(
+    block: Object,
+    env: Env,
+    yielded: Sender<Handoff<Yielded>>,
+    resume: Receiver<Resume>,
+) -> Yielded {
+    let yielder = Object::new(
+        env.foo.yielder_vtable.clone(),
+        Datum::Yielder(Rc::new(Yielder {
+            thread: thread::current().id(),
+            yielded,
+            resume,
+            cancelled: Cell::new(false),
+            base: Cell::new(env.foo.debugger.as_ref().map_or(0, |debugger| debugger.depth())),
+        })),
+    );
+    match block.send("value:", &[yielder], &env) {
+        Ok(_) => Yielded::Done,
+        Err(unwind) => Yielded::Error(unwind),
+    }
+}
+
+pub fn class_vtable() -> Vtable {
+    let vt = Vtable::for_class("Generator");
+    vt.add_primitive_method_or_panic("new:", class_generator_new);
+    vt
+}
+
+pub fn instance_vtable() -> Vtable {
+    let vt = Vtable::for_instance("Generator");
+    vt.add_primitive_method_or_panic("hasNext", generator_has_next);
+    vt.add_primitive_method_or_panic("isDone", generator_is_done);
+    vt.add_primitive_method_or_panic("next", generator_next);
+    vt.add_primitive_method_or_panic("nextIfNone:", generator_next_if_none);
+    vt
+}
+
+pub fn yielder_vtable() -> Vtable {
+    let vt = Vtable::for_instance("Yielder");
+    vt.add_primitive_method_or_panic("value:", yielder_value);
+    vt
+}
+
+pub fn as_generator<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Generator, Unwind> {
+    match &obj.datum {
+        Datum::Generator(ref generator) => Ok(generator),
+        _ => Unwind::error(&format!("{:?} is not a Generator in {}", obj, ctx)),
+    }
+}
+
+pub fn as_yielder<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Yielder, Unwind> {
+    match &obj.datum {
+        Datum::Yielder(ref yielder) => Ok(yielder),
+        _ => Unwind::error(&format!("{:?} is not a Yielder in {}", obj, ctx)),
+    }
+}
+
+fn class_generator_new(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    Ok(Object::new(
+        Rc::clone(&env.foo.generator_vtable),
+        Datum::Generator(Rc::new(Generator {
+            state: RefCell::new(State::New(args[0].clone())),
+            lookahead: RefCell::new(None),
+            heap: Rc::downgrade(&env.foo.heap),
+        })),
+    ))
+}
+
+fn generator_has_next(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    let done = as_generator(receiver, "Generator#hasNext")?.is_done(env)?;
+    Ok(env.foo.make_boolean(!done))
+}
+
+fn generator_is_done(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    let done = as_generator(receiver, "Generator#isDone")?.is_done(env)?;
+    Ok(env.foo.make_boolean(done))
+}
+
+fn generator_next(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    match as_generator(receiver, "Generator#next")?.next(env)? {
+        Some(value) => Ok(value),
+        None => Unwind::error("Generator is done"),
+    }
+}
+
+fn generator_next_if_none(receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    match as_generator(receiver, "Generator#nextIfNone:")?.next(env)? {
+        Some(value) => Ok(value),
+        None => args[0].send("value", &[], env),
+    }
+}
+
+fn yielder_value(receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let yielder = as_yielder(receiver, "Yielder#value:")?;
+    if yielder.thread != thread::current().id() {
+        return Unwind::error("Cannot yield outside the generator");
+    }
+    if yielder.cancelled.get() {
+        return Unwind::error("Generator cancelled");
+    }
+    // The frames of the generator must be off the debugger stack while the
+    // consumer runs, and back on it before the generator runs again, even to
+    // unwind.
+    let debugger = env.foo.debugger.as_ref();
+    let frames = debugger.map(|debugger| debugger.suspend(yielder.base.get()));
+    let restore = || {
+        if let (Some(debugger), Some(frames)) = (debugger, frames) {
+            yielder.base.set(debugger.depth());
+            debugger.resume(frames);
+        }
+    };
+    let value = args[0].clone();
+    if yielder.yielded.send(Handoff(Yielded::Value(value))).is_err() {
+        restore();
+        yielder.cancelled.set(true);
+        return Unwind::error("Generator cancelled");
+    }
+    let resumed = yielder.resume.recv();
+    restore();
+    match resumed {
+        Ok(Resume::Next) => Ok(args[0].clone()),
+        _ => {
+            yielder.cancelled.set(true);
+            Unwind::error("Generator cancelled")
+        }
+    }
+}
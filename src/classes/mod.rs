@@ -10,11 +10,17 @@ pub mod file;
 pub mod filepath;
 pub mod filestream;
 pub mod float;
+pub mod generator;
 pub mod input;
 pub mod integer;
 pub mod output;
+pub mod process;
+pub mod property;
 pub mod random;
 pub mod record;
 pub mod string;
+pub mod syntax;
 pub mod system;
 pub mod time;
+pub mod weakref;
+pub mod wrapper;
@@ -0,0 +1,464 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::classes::record::into_record;
+use crate::eval::Env;
+use crate::isolate::Isolate;
+use crate::objects::{Datum, Eval, Foolang, Object, Vtable};
+use crate::unwind::Unwind;
+
+/// Restarts allowed per supervisor before it gives up.
+const MAX_RESTARTS: usize = 10;
+
+/// How long a one for all supervisor waits for the other children to die
+/// after killing them.
+const KILL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Mailbox of a process, shared between the threads sending to it and the
+/// thread running it.
+pub struct ProcessState {
+    pub name: String,
+    mailbox: Mutex<Mailbox>,
+    arrived: Condvar,
+}
+
+struct Mailbox {
+    messages: VecDeque<Message>,
+    killed: bool,
+}
+
+impl PartialEq for ProcessState {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for ProcessState {}
+
+impl Hash for ProcessState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self, state);
+    }
+}
+
+impl fmt::Debug for ProcessState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<Process {}>", &self.name)
+    }
+}
+
+impl ProcessState {
+    pub fn new(name: &str) -> Arc<ProcessState> {
+        Arc::new(ProcessState {
+            name: name.to_string(),
+            mailbox: Mutex::new(Mailbox {
+                messages: VecDeque::new(),
+                killed: false,
+            }),
+            arrived: Condvar::new(),
+        })
+    }
+
+    fn post(&self, message: Message) {
+        self.mailbox.lock().unwrap().messages.push_back(message);
+        self.arrived.notify_one();
+    }
+
+    /// Waits for the next message, returning None if timeout expires first.
+    fn take(&self, timeout: Option<Duration>) -> Result<Option<Message>, Unwind> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut mailbox = self.mailbox.lock().unwrap();
+        loop {
+            if mailbox.killed {
+                return Unwind::error(&format!("Process {} killed", &self.name));
+            }
+            if let Some(message) = mailbox.messages.pop_front() {
+                return Ok(Some(message));
+            }
+            mailbox = match deadline {
+                None => self.arrived.wait(mailbox).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(None);
+                    }
+                    self.arrived.wait_timeout(mailbox, deadline - now).unwrap().0
+                }
+            }
+        }
+    }
+
+    /// Makes the process panic the next time it receives. Processes which
+    /// never receive cannot be killed.
+    fn kill(&self) {
+        self.mailbox.lock().unwrap().killed = true;
+        self.arrived.notify_all();
+    }
+
+    fn is_killed(&self) -> bool {
+        self.mailbox.lock().unwrap().killed
+    }
+
+    fn revive(&self) {
+        self.mailbox.lock().unwrap().killed = false;
+    }
+}
+
+/// Deep copy of an object, which can be moved to another process.
+enum Message {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Message>),
+    Record(Vec<(String, Message)>),
+    Process(Arc<ProcessState>),
+}
+
+impl Message {
+    fn from_object(obj: &Object) -> Result<Message, Unwind> {
+        Ok(match &obj.datum {
+            Datum::Boolean(x) => Message::Boolean(*x),
+            Datum::Integer(x) => Message::Integer(*x),
+            Datum::Float(x) => Message::Float(*x),
+            Datum::String(x) => Message::String(x.to_string()),
+            Datum::Array(array) => Message::Array(
+                array.borrow().iter().map(Message::from_object).collect::<Result<_, _>>()?,
+            ),
+            Datum::Record(record) => {
+                let mut fields = vec![];
+                for (k, v) in record.borrow().iter() {
+                    fields.push((k.to_string(), Message::from_object(v)?));
+                }
+                Message::Record(fields)
+            }
+            Datum::Process(process) => Message::Process(process.clone()),
+            _ => return Unwind::error(&format!("Cannot send {:?} to another process", obj)),
+        })
+    }
+
+    fn into_object(self, foolang: &Foolang) -> Object {
+        match self {
+            Message::Boolean(x) => foolang.make_boolean(x),
+            Message::Integer(x) => foolang.make_integer(x),
+            Message::Float(x) => foolang.make_float(x),
+            Message::String(x) => foolang.into_string(x),
+            Message::Array(data) => foolang.into_array(
+                data.into_iter().map(|message| message.into_object(foolang)).collect(),
+                None,
+            ),
+            Message::Record(fields) => into_record(
+                foolang,
+                fields.into_iter().map(|(k, v)| (k, v.into_object(foolang))).collect(),
+            ),
+            Message::Process(process) => into_process(foolang, process),
+        }
+    }
+}
+
+/// Everything needed to start the program again in a new process.
+#[derive(Clone)]
+struct ProgramSpec {
+    prelude: PathBuf,
+    roots: HashMap<String, PathBuf>,
+    source: Arc<String>,
+}
+
+impl ProgramSpec {
+    fn of(foolang: &Foolang) -> Result<ProgramSpec, Unwind> {
+        match &foolang.program {
+            Some(source) => Ok(ProgramSpec {
+                prelude: foolang.prelude.clone(),
+                roots: foolang.roots.clone(),
+                source: source.clone(),
+            }),
+            None => Unwind::error("Processes can only be started when running a program"),
+        }
+    }
+}
+
+/// Identifies a child of a supervisor to its monitor thread.
+type Monitor = (usize, Sender<(usize, bool)>);
+
+/// Runs the class on its own thread, with its own interpreter. The program
+/// is loaded before the thread starts, so that errors loading it are reported
+/// to the caller.
+fn start(
+    spec: ProgramSpec,
+    class: String,
+    process: Arc<ProcessState>,
+    monitor: Option<Monitor>,
+) -> Result<(), Unwind> {
+    let isolate = match Isolate::load(&spec.prelude, spec.roots, spec.source, process.clone())
+        .and_then(Isolate::detach)
+    {
+        Ok(isolate) => isolate,
+        Err(err) => return Unwind::error(&format!("Could not start process {}: {}", &class, err)),
+    };
+    let res = thread::Builder::new().name(process.name.clone()).stack_size(8 * 1024 * 1024).spawn(
+        move || {
+            let crashed = match isolate.attach().run_process(&class) {
+                Ok(()) => false,
+                Err(_) if process.is_killed() => true,
+                Err(err) => {
+                    eprintln!("ERROR in process {}: {}", &process.name, err);
+                    true
+                }
+            };
+            if let Some((index, monitor)) = monitor {
+                // Monitor gone means the supervisor gave up.
+                let _ = monitor.send((index, crashed));
+            }
+        },
+    );
+    match res {
+        Ok(_) => Ok(()),
+        Err(err) => Unwind::error(&format!("Could not start process: {}", err)),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Strategy {
+    /// Restart only the child that crashed.
+    OneForOne,
+    /// Kill the other children and restart all of them.
+    OneForAll,
+}
+
+pub struct Supervisor {
+    pub children: Vec<Arc<ProcessState>>,
+}
+
+impl PartialEq for Supervisor {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Supervisor {}
+
+impl Hash for Supervisor {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self, state);
+    }
+}
+
+fn supervise(
+    spec: ProgramSpec,
+    strategy: Strategy,
+    classes: Vec<String>,
+    children: Vec<Arc<ProcessState>>,
+    sender: Sender<(usize, bool)>,
+    receiver: Receiver<(usize, bool)>,
+) {
+    let mut running = children.len();
+    let mut alive = vec![true; children.len()];
+    let mut restarts = 0;
+    while running > 0 {
+        let (index, crashed) = receiver.recv().unwrap();
+        running -= 1;
+        alive[index] = false;
+        if !crashed {
+            continue;
+        }
+        if restarts == MAX_RESTARTS {
+            eprintln!("Supervisor giving up after {} restarts", restarts);
+            children.iter().for_each(|child| child.kill());
+            return;
+        }
+        restarts += 1;
+        let restart = match strategy {
+            Strategy::OneForOne => vec![index],
+            Strategy::OneForAll => {
+                children.iter().for_each(|child| child.kill());
+                let deadline = Instant::now() + KILL_TIMEOUT;
+                while running > 0 {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match receiver.recv_timeout(timeout) {
+                        Ok((index, _)) => {
+                            running -= 1;
+                            alive[index] = false;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                // Children which did not receive in time are left running.
+                for (child, _) in children.iter().zip(&alive).filter(|(_, alive)| **alive) {
+                    eprintln!(
+                        "Supervisor could not kill process {}, not restarting it",
+                        child.name
+                    );
+                    child.revive();
+                }
+                (0..children.len()).filter(|i| !alive[*i]).collect()
+            }
+        };
+        for i in restart {
+            children[i].revive();
+            let monitor = Some((i, sender.clone()));
+            match start(spec.clone(), classes[i].clone(), children[i].clone(), monitor) {
+                Ok(()) => {
+                    running += 1;
+                    alive[i] = true;
+                }
+                Err(err) => eprintln!("ERROR restarting process {}: {}", &children[i].name, err),
+            }
+        }
+    }
+}
+
+pub fn class_vtable() -> Vtable {
+    let vt = Vtable::for_class("Process");
+    vt.add_primitive_method_or_panic("current", class_process_current);
+    vt.add_primitive_method_or_panic("spawn:", class_process_spawn);
+    vt
+}
+
+pub fn instance_vtable() -> Vtable {
+    let vt = Vtable::for_instance("Process");
+    vt.add_primitive_method_or_panic("name", process_name);
+    vt.add_primitive_method_or_panic("receive", process_receive);
+    vt.add_primitive_method_or_panic("receive:timeout:", process_receive_timeout);
+    vt.add_primitive_method_or_panic("send:", process_send);
+    vt
+}
+
+pub fn supervisor_class_vtable() -> Vtable {
+    let vt = Vtable::for_class("Supervisor");
+    vt.add_primitive_method_or_panic("oneForAll:", class_supervisor_one_for_all);
+    vt.add_primitive_method_or_panic("oneForOne:", class_supervisor_one_for_one);
+    vt
+}
+
+pub fn supervisor_instance_vtable() -> Vtable {
+    let vt = Vtable::for_instance("Supervisor");
+    vt.add_primitive_method_or_panic("at:", supervisor_at);
+    vt.add_primitive_method_or_panic("children", supervisor_children);
+    vt
+}
+
+pub fn into_process(foolang: &Foolang, process: Arc<ProcessState>) -> Object {
+    Object::new(Rc::clone(&foolang.process_vtable), Datum::Process(process))
+}
+
+pub fn as_process<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Arc<ProcessState>, Unwind> {
+    match &obj.datum {
+        Datum::Process(ref process) => Ok(process),
+        _ => Unwind::error(&format!("{:?} is not a Process in {}", obj, ctx)),
+    }
+}
+
+pub fn as_supervisor<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Supervisor, Unwind> {
+    match &obj.datum {
+        Datum::Supervisor(ref supervisor) => Ok(supervisor),
+        _ => Unwind::error(&format!("{:?} is not a Supervisor in {}", obj, ctx)),
+    }
+}
+
+fn class_name(obj: &Object) -> Result<String, Unwind> {
+    Ok(obj.as_class_ref()?.instance_vtable.name.clone())
+}
+
+fn class_process_current(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    Ok(into_process(&env.foo, env.foo.process.clone()))
+}
+
+fn class_process_spawn(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let class = class_name(&args[0])?;
+    let process = ProcessState::new(&class);
+    start(ProgramSpec::of(&env.foo)?, class, process.clone(), None)?;
+    Ok(into_process(&env.foo, process))
+}
+
+fn process_name(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    Ok(env.foo.make_string(&as_process(receiver, "Process#name")?.name))
+}
+
+fn own_process<'a>(receiver: &'a Object, env: &Env, ctx: &str) -> Result<&'a ProcessState, Unwind> {
+    let process = as_process(receiver, ctx)?;
+    if !Arc::ptr_eq(process, &env.foo.process) {
+        return Unwind::error(&format!("Cannot receive from another process: {}", &process.name));
+    }
+    Ok(process)
+}
+
+fn process_receive(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    let process = own_process(receiver, env, "Process#receive")?;
+    match process.take(None)? {
+        Some(message) => Ok(message.into_object(&env.foo)),
+        None => unreachable!("BUG: receive without timeout timed out"),
+    }
+}
+
+fn process_receive_timeout(receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let process = own_process(receiver, env, "Process#receive:timeout:")?;
+    let timeout = Duration::from_millis(args[1].as_u64("timeout in Process#receive:timeout:")?);
+    match process.take(Some(timeout))? {
+        Some(message) => args[0].send("value:", &[message.into_object(&env.foo)], env),
+        None => Ok(env.foo.make_boolean(false)),
+    }
+}
+
+fn process_send(receiver: &Object, args: &[Object], _env: &Env) -> Eval {
+    let process = as_process(receiver, "Process#send:")?;
+    process.post(Message::from_object(&args[0])?);
+    Ok(receiver.clone())
+}
+
+fn start_supervisor(strategy: Strategy, args: &[Object], env: &Env) -> Eval {
+    let spec = ProgramSpec::of(&env.foo)?;
+    let mut classes = vec![];
+    for class in args[0].as_array("Supervisor children")?.borrow().iter() {
+        classes.push(class_name(class)?);
+    }
+    let children: Vec<Arc<ProcessState>> =
+        classes.iter().map(|class| ProcessState::new(class)).collect();
+    let (sender, receiver) = channel();
+    for (i, child) in children.iter().enumerate() {
+        start(spec.clone(), classes[i].clone(), child.clone(), Some((i, sender.clone())))?;
+    }
+    let monitored = children.clone();
+    let res = thread::Builder::new()
+        .name("supervisor".to_string())
+        .spawn(move || supervise(spec, strategy, classes, monitored, sender, receiver));
+    if let Err(err) = res {
+        return Unwind::error(&format!("Could not start supervisor: {}", err));
+    }
+    Ok(Object::new(
+        Rc::clone(&env.foo.supervisor_vtable),
+        Datum::Supervisor(Rc::new(Supervisor {
+            children,
+        })),
+    ))
+}
+
+fn class_supervisor_one_for_all(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    start_supervisor(Strategy::OneForAll, args, env)
+}
+
+fn class_supervisor_one_for_one(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    start_supervisor(Strategy::OneForOne, args, env)
+}
+
+fn supervisor_at(receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let supervisor = as_supervisor(receiver, "Supervisor#at:")?;
+    let index = args[0].as_index("Supervisor#at:")?;
+    match supervisor.children.get((index - 1) as usize) {
+        Some(process) => Ok(into_process(&env.foo, process.clone())),
+        _ => Unwind::error(&format!("Supervisor has no child at index {}", index)),
+    }
+}
+
+fn supervisor_children(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    let supervisor = as_supervisor(receiver, "Supervisor#children")?;
+    let children =
+        supervisor.children.iter().map(|process| into_process(&env.foo, process.clone())).collect();
+    Ok(env.foo.into_array(children, None))
+}
@@ -0,0 +1,605 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::classes::random::Random;
+use crate::classes::record::into_record;
+use crate::eval::Env;
+use crate::objects::{Datum, Eval, Object, Vtable};
+use crate::unwind::Unwind;
+
+/// Number of random inputs a property is checked with.
+const RUNS: usize = 100;
+
+/// Maximum number of property checks spent on shrinking a failing input.
+const SHRINK_BUDGET: usize = 2000;
+
+/// Maximum number of choices a single input is generated from.
+const MAX_CHOICES: usize = 10_000;
+
+/// Maximum number of elements in generated collections.
+const MAX_SIZE: usize = 64;
+
+/// Characters in generated strings, simplest first.
+const ALPHABET: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 \
+                        !\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~\t\näöå€λ";
+
+/// Environment variable overriding the location of the example database.
+const DATABASE_VAR: &str = "FOOLANG_EXAMPLES";
+
+/// Default location of the example database, relative to the current
+/// directory.
+const DATABASE_DIR: &str = ".foolang/examples";
+
+/// Generator of inputs for property checks.
+///
+/// Generators turn a sequence of choices into a value. Random choices are
+/// recorded, so a failing input can be replayed and shrunk: smaller choices
+/// produce simpler values, and zeros the simplest ones.
+#[derive(Debug)]
+pub enum Gen {
+    Array(Rc<Gen>),
+    Boolean,
+    Dictionary(Rc<Gen>, Rc<Gen>),
+    Float,
+    Integer,
+    IntegerRange(i64, i64),
+    /// Fields in alphabetical order.
+    Record(Vec<(String, Rc<Gen>)>),
+    String,
+}
+
+impl PartialEq for Gen {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Gen {}
+
+impl Hash for Gen {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self, state);
+    }
+}
+
+impl fmt::Display for Gen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Gen::Array(elements) => write!(f, "Gen arrayOf: ({})", elements),
+            Gen::Boolean => write!(f, "Gen boolean"),
+            Gen::Dictionary(keys, values) => {
+                write!(f, "Gen dictionaryFrom: ({}) to: ({})", keys, values)
+            }
+            Gen::Float => write!(f, "Gen float"),
+            Gen::Integer => write!(f, "Gen integer"),
+            Gen::IntegerRange(min, max) => write!(f, "Gen integerFrom: {} to: {}", min, max),
+            Gen::Record(fields) => {
+                write!(f, "Gen record: {{")?;
+                for (i, (name, gen)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {}: {}", name, gen)?;
+                }
+                write!(f, " }}")
+            }
+            Gen::String => write!(f, "Gen string"),
+        }
+    }
+}
+
+/// Choices made while generating an input: first replayed from prefix, then
+/// drawn from random, or zeros if there is none.
+struct Choices<'a> {
+    prefix: &'a [u64],
+    random: Option<&'a Random>,
+    drawn: Vec<u64>,
+}
+
+impl<'a> Choices<'a> {
+    fn draw(&mut self) -> u64 {
+        let i = self.drawn.len();
+        let choice = if i < self.prefix.len() {
+            self.prefix[i]
+        } else {
+            match self.random {
+                Some(random) if i < MAX_CHOICES => random.next_u64(),
+                _ => 0,
+            }
+        };
+        self.drawn.push(choice);
+        choice
+    }
+
+    /// Records the choice reduced below n, so that shrinking it makes the
+    /// value simpler.
+    fn draw_below(&mut self, n: u64) -> u64 {
+        let choice = self.draw() % n;
+        *self.drawn.last_mut().expect("BUG: no choice") = choice;
+        choice
+    }
+
+    /// Decides whether to add another element to a collection of size.
+    fn more(&mut self, size: usize) -> bool {
+        size < MAX_SIZE && self.draw_below(8) != 0
+    }
+
+    /// Integer of up to max_bits bits and a sign, with smaller magnitudes as
+    /// likely as bigger ones.
+    fn draw_integer(&mut self, max_bits: u32) -> i64 {
+        let bits = self.draw_below(max_bits as u64 + 1) as u32;
+        let magnitude = self.draw_below(1 << bits) as i64;
+        if self.draw_below(2) == 1 {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+impl Gen {
+    fn generate(&self, choices: &mut Choices, env: &Env) -> Eval {
+        let foolang = &env.foo;
+        Ok(match self {
+            Gen::Array(elements) => {
+                let mut data = Vec::new();
+                while choices.more(data.len()) {
+                    data.push(elements.generate(choices, env)?);
+                }
+                foolang.into_array(data, None)
+            }
+            Gen::Boolean => foolang.make_boolean(choices.draw_below(2) == 1),
+            Gen::Dictionary(keys, values) => {
+                let mut entries = Vec::new();
+                while choices.more(entries.len()) {
+                    let key = keys.generate(choices, env)?;
+                    entries.push((key, values.generate(choices, env)?));
+                }
+                foolang.into_dictionary(entries.into_iter().collect())
+            }
+            Gen::Float => {
+                let whole = choices.draw_integer(53) as f64;
+                let fraction = choices.draw_below(1024) as f64 / 1024.0;
+                foolang.make_float(if whole < 0.0 {
+                    whole - fraction
+                } else {
+                    whole + fraction
+                })
+            }
+            Gen::Integer => foolang.make_integer(choices.draw_integer(63)),
+            Gen::IntegerRange(min, max) => {
+                // Offset from the end of the range closest to zero, or from
+                // zero if the range contains it.
+                let (min, max) = (*min as i128, *max as i128);
+                let value = if min >= 0 {
+                    min + choices.draw_below((max - min + 1) as u64) as i128
+                } else if max <= 0 {
+                    max - choices.draw_below((max - min + 1) as u64) as i128
+                } else if choices.draw_below(2) == 1 {
+                    -(choices.draw_below((-min) as u64) as i128) - 1
+                } else {
+                    choices.draw_below((max + 1) as u64) as i128
+                };
+                foolang.make_integer(value as i64)
+            }
+            Gen::Record(fields) => {
+                let mut data = HashMap::new();
+                for (name, gen) in fields {
+                    data.insert(name.clone(), gen.generate(choices, env)?);
+                }
+                into_record(foolang, data)
+            }
+            Gen::String => {
+                let alphabet: Vec<char> = ALPHABET.chars().collect();
+                let mut string = String::new();
+                let mut size = 0;
+                while choices.more(size) {
+                    string.push(alphabet[choices.draw_below(alphabet.len() as u64) as usize]);
+                    size += 1;
+                }
+                foolang.into_string(string)
+            }
+        })
+    }
+}
+
+pub fn class_vtable() -> Vtable {
+    let vt = Vtable::for_class("Gen");
+    vt.add_primitive_method_or_panic("arrayOf:", class_gen_array_of);
+    vt.add_primitive_method_or_panic("boolean", class_gen_boolean);
+    vt.add_primitive_method_or_panic("dictionaryFrom:to:", class_gen_dictionary_from_to);
+    vt.add_primitive_method_or_panic("float", class_gen_float);
+    vt.add_primitive_method_or_panic("integer", class_gen_integer);
+    vt.add_primitive_method_or_panic("integerFrom:to:", class_gen_integer_from_to);
+    vt.add_primitive_method_or_panic("record:", class_gen_record);
+    vt.add_primitive_method_or_panic("string", class_gen_string);
+    vt
+}
+
+pub fn instance_vtable() -> Vtable {
+    let vt = Vtable::for_instance("Gen");
+    vt.add_primitive_method_or_panic("generate:", gen_generate);
+    vt.add_primitive_method_or_panic("toString", gen_to_string);
+    vt
+}
+
+pub fn property_class_vtable() -> Vtable {
+    let vt = Vtable::for_class("Property");
+    vt.add_primitive_method_or_panic("check:forAll:", class_property_check_for_all);
+    vt.add_primitive_method_or_panic("check:forAll:seed:", class_property_check_for_all_seed);
+    vt
+}
+
+pub fn property_instance_vtable() -> Vtable {
+    Vtable::for_instance("Property")
+}
+
+pub fn as_gen<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Rc<Gen>, Unwind> {
+    match &obj.datum {
+        Datum::Gen(ref gen) => Ok(gen),
+        _ => Unwind::error(&format!("{:?} is not a Gen ({})", obj, ctx)),
+    }
+}
+
+fn into_gen(gen: Gen, env: &Env) -> Eval {
+    Ok(Object::new(Rc::clone(&env.foo.gen_vtable), Datum::Gen(Rc::new(gen))))
+}
+
+fn class_gen_array_of(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let elements = as_gen(&args[0], "elements in Gen##arrayOf:")?;
+    into_gen(Gen::Array(elements.clone()), env)
+}
+
+fn class_gen_boolean(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    into_gen(Gen::Boolean, env)
+}
+
+fn class_gen_dictionary_from_to(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let keys = as_gen(&args[0], "keys in Gen##dictionaryFrom:to:")?;
+    let values = as_gen(&args[1], "values in Gen##dictionaryFrom:to:")?;
+    into_gen(Gen::Dictionary(keys.clone(), values.clone()), env)
+}
+
+fn class_gen_float(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    into_gen(Gen::Float, env)
+}
+
+fn class_gen_integer(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    into_gen(Gen::Integer, env)
+}
+
+fn class_gen_integer_from_to(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let min = args[0].as_i64("min in Gen##integerFrom:to:")?;
+    let max = args[1].as_i64("max in Gen##integerFrom:to:")?;
+    if min > max {
+        return Unwind::error(&format!("Empty range in Gen##integerFrom:to: {} {}", min, max));
+    }
+    into_gen(Gen::IntegerRange(min, max), env)
+}
+
+fn class_gen_record(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let record = args[0].as_record("fields in Gen##record:")?;
+    let mut fields = Vec::new();
+    for (name, gen) in record.borrow().iter() {
+        fields.push((name.clone(), as_gen(gen, "field in Gen##record:")?.clone()));
+    }
+    fields.sort_by(|a, b| a.0.cmp(&b.0));
+    into_gen(Gen::Record(fields), env)
+}
+
+fn class_gen_string(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    into_gen(Gen::String, env)
+}
+
+fn gen_generate(receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let gen = as_gen(receiver, "receiver in Gen#generate:")?;
+    let mut choices = Choices {
+        prefix: &[],
+        random: Some(args[0].as_random("argument in Gen#generate:")?),
+        drawn: Vec::new(),
+    };
+    gen.generate(&mut choices, env)
+}
+
+fn gen_to_string(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    Ok(env.foo.into_string(as_gen(receiver, "receiver in Gen#toString")?.to_string()))
+}
+
+/// Outcome of checking a property against one input.
+enum Check {
+    Pass,
+    /// The property returned False, or the error it panicked with.
+    Fail(Option<String>),
+}
+
+/// A property together with the generators of its arguments.
+struct Property<'a> {
+    block: &'a Object,
+    gens: Vec<Rc<Gen>>,
+    env: &'a Env,
+}
+
+impl<'a> Property<'a> {
+    /// Generates arguments from choices and checks the property against
+    /// them.
+    fn check(&self, choices: &mut Choices) -> Result<(Vec<Object>, Check), Unwind> {
+        let env = self.env;
+        let mut args = Vec::new();
+        for gen in &self.gens {
+            args.push(gen.generate(choices, env)?);
+        }
+        let check = match self.block.closure_ref().apply(None, &args, env) {
+            Ok(res) => match res.datum {
+                Datum::Boolean(true) => Check::Pass,
+                Datum::Boolean(false) => Check::Fail(None),
+                _ => Check::Fail(Some(format!("Property returned {:?}, not a Boolean", res))),
+            },
+            Err(Unwind::Panic(error, _)) => Check::Fail(Some(error.what())),
+            Err(unwind) => return Err(unwind),
+        };
+        Ok((args, check))
+    }
+
+    fn replay(&self, prefix: &[u64]) -> Result<(Vec<u64>, Vec<Object>, Check), Unwind> {
+        let mut choices = Choices {
+            prefix,
+            random: None,
+            drawn: Vec::new(),
+        };
+        let (args, check) = self.check(&mut choices)?;
+        Ok((choices.drawn, args, check))
+    }
+
+    /// Greedily simplifies the choices of a failing input while it keeps
+    /// failing: first by deleting runs of choices, then by making each one
+    /// smaller. Returns the simplest failing choices and their check.
+    fn shrink(&self, mut best: Vec<u64>) -> Result<(Vec<u64>, Option<String>), Unwind> {
+        let mut error = match self.replay(&best)? {
+            (drawn, _, Check::Fail(error)) => {
+                best = drawn;
+                error
+            }
+            (_, _, Check::Pass) => return Ok((best, None)),
+        };
+        let mut budget = SHRINK_BUDGET;
+        let mut improved = true;
+        while improved && budget > 0 {
+            improved = false;
+            for size in &[8, 4, 2, 1] {
+                let mut i = 0;
+                while i + size <= best.len() && budget > 0 {
+                    let mut candidate = best.clone();
+                    candidate.drain(i..i + size);
+                    budget -= 1;
+                    if self.try_candidate(&candidate, &mut best, &mut error)? {
+                        improved = true;
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+            for i in 0..best.len() {
+                let (mut lo, mut hi) = (0, best[i]);
+                while lo < hi && budget > 0 {
+                    let mid = lo + (hi - lo) / 2;
+                    let mut candidate = best.clone();
+                    candidate[i] = mid;
+                    budget -= 1;
+                    if self.try_candidate(&candidate, &mut best, &mut error)? {
+                        improved = true;
+                        hi = mid;
+                    } else {
+                        lo = mid + 1;
+                    }
+                    if i >= best.len() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok((best, error))
+    }
+
+    /// Replaces best with the choices made for candidate if they are simpler
+    /// and still fail.
+    fn try_candidate(
+        &self,
+        candidate: &[u64],
+        best: &mut Vec<u64>,
+        error: &mut Option<String>,
+    ) -> Result<bool, Unwind> {
+        if let (drawn, _, Check::Fail(e)) = self.replay(candidate)? {
+            if (drawn.len(), &drawn) < (best.len(), best) {
+                *best = drawn;
+                *error = e;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Identifies the property in the example database: hash of the source
+    /// of the block and the generators.
+    fn key(&self) -> String {
+        let closure = self.block.closure_ref();
+        let location = closure.body.source_location();
+        let span = location.get_span();
+        let source = match location.code() {
+            Some(code) if span.end <= code.len() => code[span].to_string(),
+            _ => format!("{:?}", closure.body),
+        };
+        let mut hash = Fnv::new();
+        hash.write(source.as_bytes());
+        for gen in &self.gens {
+            hash.write(gen.to_string().as_bytes());
+        }
+        format!("{:016x}", hash.finish())
+    }
+
+    fn result(
+        &self,
+        runs: usize,
+        seed: u64,
+        failure: Option<(Vec<Object>, Option<String>)>,
+    ) -> Object {
+        let foolang = &self.env.foo;
+        let mut data = HashMap::new();
+        data.insert("runs".to_string(), foolang.make_integer(runs as i64));
+        data.insert("seed".to_string(), foolang.make_integer(seed as i64));
+        data.insert("passed".to_string(), foolang.make_boolean(failure.is_none()));
+        let (input, error) = match failure {
+            None => (foolang.make_boolean(false), foolang.make_boolean(false)),
+            Some((args, error)) => (
+                foolang.into_array(args, None),
+                match error {
+                    Some(error) => foolang.into_string(error),
+                    None => foolang.make_boolean(false),
+                },
+            ),
+        };
+        data.insert("input".to_string(), input);
+        data.insert("error".to_string(), error);
+        into_record(foolang, data)
+    }
+}
+
+/// 64 bit FNV-1a: the example database needs a hash which is stable across
+/// builds.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Failing inputs of past checks, stored as their choices: one file per
+/// property, one line per input.
+struct Database {
+    file: PathBuf,
+}
+
+impl Database {
+    fn new(key: &str) -> Database {
+        let dir = match std::env::var_os(DATABASE_VAR) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(DATABASE_DIR),
+        };
+        Database {
+            file: dir.join(key),
+        }
+    }
+
+    fn load(&self) -> Vec<Vec<u64>> {
+        let text = fs::read_to_string(&self.file).unwrap_or_default();
+        text.lines()
+            .map(|line| line.split_whitespace().filter_map(|choice| choice.parse().ok()).collect())
+            .collect()
+    }
+
+    /// Errors are ignored: the database is only a cache.
+    fn save(&self, choices: &[u64]) {
+        if let Some(dir) = self.file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let line: Vec<String> = choices.iter().map(|choice| choice.to_string()).collect();
+        let _ = fs::write(&self.file, format!("{}\n", line.join(" ")));
+    }
+
+    fn clear(&self) {
+        let _ = fs::remove_file(&self.file);
+    }
+}
+
+/// Checks block against inputs from gens: first inputs which failed
+/// earlier, then RUNS random ones. A failing input is shrunk and saved to
+/// the example database. Seeded checks are reproducible, and neither use
+/// nor update the database.
+fn check(block: &Object, gens: &Object, seed: Option<u64>, env: &Env) -> Eval {
+    if !matches!(block.datum, Datum::Closure(_)) {
+        return Unwind::error(&format!("{:?} is not a block (Property##check:forAll:)", block));
+    }
+    let gens = gens
+        .as_array("generators in Property##check:forAll:")?
+        .borrow()
+        .iter()
+        .map(|gen| as_gen(gen, "generator in Property##check:forAll:").map(Rc::clone))
+        .collect::<Result<Vec<_>, Unwind>>()?;
+    let property = Property {
+        block,
+        gens,
+        env,
+    };
+    let database = match seed {
+        Some(_) => None,
+        None => Some(Database::new(&property.key())),
+    };
+    let seed = seed.unwrap_or_else(|| rand::random::<u64>() >> 1);
+    let mut failing = None;
+    let mut runs = 0;
+    if let Some(database) = &database {
+        for example in database.load() {
+            runs += 1;
+            if let (drawn, _, Check::Fail(_)) = property.replay(&example)? {
+                failing = Some(drawn);
+                break;
+            }
+        }
+    }
+    if failing.is_none() {
+        let random = Random::new(seed);
+        for _ in 0..RUNS {
+            let mut choices = Choices {
+                prefix: &[],
+                random: Some(&random),
+                drawn: Vec::new(),
+            };
+            runs += 1;
+            if let (_, Check::Fail(_)) = property.check(&mut choices)? {
+                failing = Some(choices.drawn);
+                break;
+            }
+        }
+    }
+    let failing = match failing {
+        None => {
+            if let Some(database) = &database {
+                database.clear();
+            }
+            return Ok(property.result(runs, seed, None));
+        }
+        Some(failing) => failing,
+    };
+    let (shrunk, error) = property.shrink(failing)?;
+    if let Some(database) = &database {
+        database.save(&shrunk);
+    }
+    let (_, args, _) = property.replay(&shrunk)?;
+    Ok(property.result(runs, seed, Some((args, error))))
+}
+
+fn class_property_check_for_all(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    check(&args[0], &args[1], None, env)
+}
+
+fn class_property_check_for_all_seed(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let seed = args[2].as_u64("seed in Property##check:forAll:seed:")?;
+    check(&args[0], &args[1], Some(seed), env)
+}
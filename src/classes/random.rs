@@ -13,6 +13,14 @@ pub struct Random {
 }
 
 impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random {
+            rng: RefCell::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+    pub fn next_u64(&self) -> u64 {
+        self.rng.borrow_mut().gen()
+    }
     pub fn borrow(&self) -> Ref<StdRng> {
         self.rng.borrow()
     }
@@ -58,22 +66,17 @@ pub fn as_random<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Random, Unwind> {
 }
 
 fn class_random_new(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
-    Ok(Object {
-        vtable: Rc::clone(&env.foo.random_vtable),
-        datum: Datum::Random(Rc::new(Random {
+    Ok(Object::new(
+        Rc::clone(&env.foo.random_vtable),
+        Datum::Random(Rc::new(Random {
             rng: RefCell::new(StdRng::from_entropy()),
         })),
-    })
+    ))
 }
 
 fn class_random_new_arg(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
     let arg = args[0].as_u64("seed in Random##new:")?;
-    Ok(Object {
-        vtable: Rc::clone(&env.foo.random_vtable),
-        datum: Datum::Random(Rc::new(Random {
-            rng: RefCell::new(StdRng::seed_from_u64(arg)),
-        })),
-    })
+    Ok(Object::new(Rc::clone(&env.foo.random_vtable), Datum::Random(Rc::new(Random::new(arg)))))
 }
 
 fn random_integer(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
@@ -1,11 +1,11 @@
-use std::cell::{Ref, RefCell, RefMut};
+use std::cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut};
 use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
 use crate::eval::Env;
-use crate::objects::{Datum, Eval, Object, Vtable};
+use crate::objects::{Datum, Eval, Foolang, Object, Vtable};
 use crate::unwind::Unwind;
 
 pub struct Record {
@@ -19,6 +19,12 @@ impl Record {
     pub fn borrow_mut(&self) -> RefMut<HashMap<String, Object>> {
         self.data.borrow_mut()
     }
+    pub fn try_borrow(&self) -> Result<Ref<'_, HashMap<String, Object>>, BorrowError> {
+        self.data.try_borrow()
+    }
+    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, HashMap<String, Object>>, BorrowMutError> {
+        self.data.try_borrow_mut()
+    }
 }
 
 impl PartialEq for Record {
@@ -66,6 +72,17 @@ pub fn instance_vtable() -> Vtable {
     vt
 }
 
+pub fn into_record(foolang: &Foolang, data: HashMap<String, Object>) -> Object {
+    let record = Object::new(
+        foolang.record_vtable.clone(),
+        Datum::Record(Rc::new(Record {
+            data: RefCell::new(data),
+        })),
+    );
+    foolang.heap.register(&record);
+    record
+}
+
 pub fn as_record<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Record, Unwind> {
     match &obj.datum {
         Datum::Record(ref record) => Ok(record),
@@ -80,12 +97,7 @@ fn class_record_perform_with(_receiver: &Object, args: &[Object], env: &Env) ->
     for (k, v) in selector.split(':').zip(&*values) {
         data.insert(k.to_string(), v.clone());
     }
-    Ok(Object {
-        vtable: env.foo.record_vtable.clone(),
-        datum: Datum::Record(Rc::new(Record {
-            data: RefCell::new(data),
-        })),
-    })
+    Ok(into_record(&env.foo, data))
 }
 
 fn class_record_keys_in(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
@@ -0,0 +1,136 @@
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::eval::Env;
+use crate::expr::*;
+use crate::objects::{Datum, Eval, Foolang, Object, Vtable};
+use crate::source_location::SourceLocation;
+use crate::unwind::Unwind;
+
+/// Unevaluated expression, as seen by macros.
+#[derive(Debug)]
+pub struct Syntax {
+    pub expr: Expr,
+}
+
+impl PartialEq for Syntax {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl std::cmp::Eq for Syntax {}
+
+impl Hash for Syntax {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self, state);
+    }
+}
+
+/// Macro defined with 'macro': invoked by sending selector to its name, with
+/// the unevaluated arguments as Syntax objects.
+#[derive(Debug)]
+pub struct Macro {
+    pub name: String,
+    pub selector: String,
+    pub function: Object,
+}
+
+impl PartialEq for Macro {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl std::cmp::Eq for Macro {}
+
+impl Hash for Macro {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self, state);
+    }
+}
+
+pub fn class_vtable() -> Vtable {
+    let vt = Vtable::for_class("Syntax");
+    vt.add_primitive_method_or_panic("literal:", class_syntax_literal);
+    vt
+}
+
+pub fn instance_vtable() -> Vtable {
+    let vt = Vtable::for_instance("Syntax");
+    vt.add_primitive_method_or_panic("isLiteral", syntax_is_literal);
+    vt.add_primitive_method_or_panic("isVariable", syntax_is_variable);
+    vt.add_primitive_method_or_panic("name", syntax_name);
+    vt.add_primitive_method_or_panic("value", syntax_value);
+    vt
+}
+
+pub fn macro_vtable() -> Vtable {
+    Vtable::for_instance("Macro")
+}
+
+pub fn into_syntax(foolang: &Foolang, expr: Expr) -> Object {
+    Object::new(
+        Rc::clone(&foolang.syntax_vtable),
+        Datum::Syntax(Rc::new(Syntax {
+            expr,
+        })),
+    )
+}
+
+pub fn make_macro(foolang: &Foolang, name: &str, selector: &str, function: Object) -> Object {
+    Object::new(
+        Rc::clone(&foolang.macro_vtable),
+        Datum::Macro(Rc::new(Macro {
+            name: name.to_string(),
+            selector: selector.to_string(),
+            function,
+        })),
+    )
+}
+
+pub fn as_syntax<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Syntax, Unwind> {
+    match &obj.datum {
+        Datum::Syntax(ref syntax) => Ok(syntax),
+        _ => Unwind::error(&format!("{:?} is not Syntax in {}", obj, ctx)),
+    }
+}
+
+fn class_syntax_literal(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let literal = match &args[0].datum {
+        Datum::Boolean(value) => Literal::Boolean(*value),
+        Datum::Integer(value) => Literal::Integer(*value),
+        Datum::Float(value) => Literal::Float(*value),
+        Datum::String(value) => Literal::String(value.to_string()),
+        _ => return Unwind::error(&format!("Cannot make a literal from {:?}", &args[0])),
+    };
+    let source_location = match env.foo.expansion_site() {
+        Some(site) => site,
+        None => SourceLocation::Span(0..0),
+    };
+    Ok(into_syntax(&env.foo, Const::expr(source_location, literal)))
+}
+
+fn syntax_is_literal(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    let syntax = as_syntax(receiver, "Syntax#isLiteral")?;
+    Ok(env.foo.make_boolean(matches!(syntax.expr, Expr::Const(_))))
+}
+
+fn syntax_is_variable(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    let syntax = as_syntax(receiver, "Syntax#isVariable")?;
+    Ok(env.foo.make_boolean(syntax.expr.is_var()))
+}
+
+fn syntax_name(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    match &as_syntax(receiver, "Syntax#name")?.expr {
+        Expr::Var(var) => Ok(env.foo.make_string(&var.name)),
+        _ => Unwind::error("Syntax#name: not a variable"),
+    }
+}
+
+fn syntax_value(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    match &as_syntax(receiver, "Syntax#value")?.expr {
+        Expr::Const(constant) => env.eval(&Expr::Const(constant.clone())),
+        _ => Unwind::error("Syntax#value: not a literal"),
+    }
+}
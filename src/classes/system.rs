@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::process::Command;
 use std::{thread, time};
 
@@ -5,7 +6,8 @@ use getrandom;
 
 use crate::classes;
 use crate::eval::Env;
-use crate::objects::{Eval, Object, Vtable};
+use crate::objects::{Datum, Eval, Object, Vtable};
+use crate::trace::Filter;
 use crate::unwind::Unwind;
 
 pub fn vtable() -> Vtable {
@@ -17,7 +19,9 @@ pub fn vtable() -> Vtable {
     vt.add_primitive_method_or_panic("exit", system_exit);
     vt.add_primitive_method_or_panic("exit:", system_exit_arg);
     vt.add_primitive_method_or_panic("files", system_files);
+    vt.add_primitive_method_or_panic("gc", system_gc);
     vt.add_primitive_method_or_panic("getenv:", system_getenv);
+    vt.add_primitive_method_or_panic("heapStats", system_heap_stats);
     vt.add_primitive_method_or_panic("input", system_input);
     vt.add_primitive_method_or_panic("isWindows", system_is_windows);
     vt.add_primitive_method_or_panic("isUnix", system_is_unix);
@@ -28,6 +32,7 @@ pub fn vtable() -> Vtable {
     vt.add_primitive_method_or_panic("random:", system_random_arg);
     vt.add_primitive_method_or_panic("sleep", system_sleep);
     vt.add_primitive_method_or_panic("sleep:", system_sleep_arg);
+    vt.add_primitive_method_or_panic("trace:", system_trace_arg);
     vt
 }
 
@@ -70,12 +75,12 @@ fn system_current_directory(_receiver: &Object, _args: &[Object], env: &Env) ->
     classes::filepath::make_current_directory_filepath(env)
 }
 
-fn system_exit(_receiver: &Object, _args: &[Object], _env: &Env) -> Eval {
-    std::process::exit(0)
+fn system_exit(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    env.foo.exit(0)
 }
 
-fn system_exit_arg(_receiver: &Object, args: &[Object], _env: &Env) -> Eval {
-    std::process::exit(args[0].integer() as i32)
+fn system_exit_arg(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    env.foo.exit(args[0].integer() as i32)
 }
 
 fn system_files(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
@@ -99,6 +104,35 @@ fn system_is_unix(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
     Ok(env.foo.make_boolean(cfg!(target_family = "unix")))
 }
 
+fn system_gc(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    let collected = env.foo.heap.collect();
+    env.safe_point();
+    Ok(env.foo.make_integer(collected as i64))
+}
+
+fn system_heap_stats(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    let stats = env.foo.heap.stats();
+    let counts = |counts: Vec<(&str, usize)>| {
+        env.foo.into_dictionary(
+            counts
+                .into_iter()
+                .map(|(name, count)| {
+                    (env.foo.make_string(name), env.foo.make_integer(count as i64))
+                })
+                .collect(),
+        )
+    };
+    let mut record = HashMap::new();
+    record.insert("objects".to_string(), counts(stats.objects.into_iter().collect()));
+    record.insert(
+        "instances".to_string(),
+        counts(stats.instances.iter().map(|(name, count)| (name.as_str(), *count)).collect()),
+    );
+    record.insert("bytes".to_string(), counts(stats.bytes.into_iter().collect()));
+    record.insert("allocations".to_string(), counts(stats.allocations.into_iter().collect()));
+    Ok(classes::record::into_record(&env.foo, record))
+}
+
 fn system_is_windows(_receiver: &Object, _args: &[Object], env: &Env) -> Eval {
     Ok(env.foo.make_boolean(cfg!(target_family = "windows")))
 }
@@ -146,3 +180,22 @@ fn system_sleep_arg(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
     }
     Ok(env.foo.make_boolean(true))
 }
+
+fn system_trace_arg(receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let trace = &env.foo.trace;
+    match &args[0].datum {
+        Datum::Boolean(false) => trace.disable(),
+        Datum::Boolean(true) => trace.enable(Filter::default()),
+        _ => {
+            let mut specs = Vec::new();
+            for spec in args[0].as_array("System#trace:")?.borrow().iter() {
+                specs.push(spec.as_str()?.to_string());
+            }
+            match Filter::parse(&specs) {
+                Ok(filter) => trace.enable(filter),
+                Err(err) => return Unwind::error(&err),
+            }
+        }
+    }
+    Ok(receiver.clone())
+}
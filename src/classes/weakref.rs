@@ -0,0 +1,85 @@
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::eval::Env;
+use crate::heap::WeakObject;
+use crate::objects::{Datum, Eval, Object, Vtable};
+use crate::unwind::Unwind;
+
+/// Reference which does not keep its object alive.
+pub struct WeakRef {
+    pub object: WeakObject,
+}
+
+impl PartialEq for WeakRef {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for WeakRef {}
+
+impl Hash for WeakRef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self, state);
+    }
+}
+
+pub fn class_vtable() -> Vtable {
+    let vt = Vtable::for_class("WeakRef");
+    vt.add_primitive_method_or_panic("to:", class_weakref_to);
+    vt
+}
+
+pub fn instance_vtable() -> Vtable {
+    let vt = Vtable::for_instance("WeakRef");
+    vt.add_primitive_method_or_panic("isAlive", weakref_is_alive);
+    vt.add_primitive_method_or_panic("value", weakref_value);
+    vt
+}
+
+pub fn finalizer_class_vtable() -> Vtable {
+    let vt = Vtable::for_class("Finalizer");
+    vt.add_primitive_method_or_panic("on:do:", class_finalizer_on_do);
+    vt
+}
+
+pub fn finalizer_instance_vtable() -> Vtable {
+    Vtable::for_instance("Finalizer")
+}
+
+pub fn as_weakref<'a>(obj: &'a Object, ctx: &str) -> Result<&'a WeakRef, Unwind> {
+    match &obj.datum {
+        Datum::WeakRef(ref weakref) => Ok(weakref),
+        _ => Unwind::error(&format!("{:?} is not a WeakRef in {}", obj, ctx)),
+    }
+}
+
+fn class_weakref_to(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    Ok(Object::new(
+        Rc::clone(&env.foo.weakref_vtable),
+        Datum::WeakRef(Rc::new(WeakRef {
+            object: WeakObject::new(&args[0]),
+        })),
+    ))
+}
+
+fn weakref_is_alive(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    Ok(env.foo.make_boolean(as_weakref(receiver, "WeakRef#isAlive")?.object.is_alive()))
+}
+
+fn weakref_value(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    match as_weakref(receiver, "WeakRef#value")?.object.upgrade() {
+        Some(object) => Ok(object),
+        None => Ok(env.foo.make_boolean(false)),
+    }
+}
+
+fn class_finalizer_on_do(_receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let object = WeakObject::new(&args[0]);
+    if !object.is_weak() {
+        return Unwind::error(&format!("Cannot finalize {:?}: it is never collected", &args[0]));
+    }
+    env.foo.heap.add_finalizer(object, args[1].clone());
+    Ok(args[0].clone())
+}
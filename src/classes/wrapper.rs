@@ -0,0 +1,127 @@
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::eval::Env;
+use crate::objects::{Datum, Eval, Method, Object, Vtable};
+use crate::unwind::Unwind;
+
+/// Method installed by Class#wrap:with:, which calls the wrapper with the
+/// original method, the arguments and the receiver.
+pub struct Wrapper {
+    pub selector: String,
+    pub wrapper: Object,
+    pub original: Method,
+}
+
+impl Wrapper {
+    pub fn invoke(&self, receiver: &Object, args: &[Object], env: &Env) -> Eval {
+        let original = Object::new(
+            Rc::clone(&env.foo.original_vtable),
+            Datum::Original(Rc::new(Original {
+                selector: self.selector.clone(),
+                method: self.original.clone(),
+            })),
+        );
+        self.wrapper.send(
+            "value:value:value:",
+            &[original, env.foo.make_array(args), receiver.clone()],
+            env,
+        )
+    }
+}
+
+/// The method a wrapper was installed over, as passed to the wrapper.
+pub struct Original {
+    pub selector: String,
+    pub method: Method,
+}
+
+impl PartialEq for Original {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Original {}
+
+impl Hash for Original {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self, state);
+    }
+}
+
+pub fn instance_vtable() -> Vtable {
+    let vt = Vtable::for_instance("Original");
+    vt.add_primitive_method_or_panic("invoke:on:", original_invoke_on_);
+    vt.add_primitive_method_or_panic("name", original_name);
+    vt
+}
+
+pub fn as_original<'a>(obj: &'a Object, ctx: &str) -> Result<&'a Original, Unwind> {
+    match &obj.datum {
+        Datum::Original(ref original) => Ok(original),
+        _ => Unwind::error(&format!("{:?} is not an Original in {}", obj, ctx)),
+    }
+}
+
+fn original_invoke_on_(receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let original = as_original(receiver, "Original#invoke:on:")?;
+    let arguments = args[0].as_array("Original#invoke:on:")?.borrow().clone();
+    args[1].invoke(&original.method, &original.selector, &arguments, env)
+}
+
+fn original_name(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
+    Ok(env.foo.make_string(&as_original(receiver, "Original#name")?.selector))
+}
+
+/// Class#wrap:with:, installs a wrapper around an instance method.
+pub fn generic_class_wrap_with_(receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let class = receiver.as_class_ref()?;
+    let vtable = &class.instance_vtable;
+    let selector = args[0].send("name", &[], env)?;
+    let selector = selector.as_str()?;
+    let original = match vtable.get(selector) {
+        Some(Method::Required(_)) => {
+            return Unwind::error(&format!(
+                "Cannot wrap {}#{}, method is required but not implemented",
+                vtable.name, selector
+            ))
+        }
+        Some(method) => method,
+        None => {
+            return Unwind::error(&format!(
+                "Cannot wrap {}#{}, no such method",
+                vtable.name, selector
+            ))
+        }
+    };
+    vtable.methods.borrow_mut().insert(
+        selector.to_string(),
+        Method::Wrapped(Rc::new(Wrapper {
+            selector: selector.to_string(),
+            wrapper: args[1].clone(),
+            original,
+        })),
+    );
+    Ok(receiver.clone())
+}
+
+/// Class#unwrap:, removes the latest wrapper installed around an instance
+/// method.
+pub fn generic_class_unwrap_(receiver: &Object, args: &[Object], env: &Env) -> Eval {
+    let class = receiver.as_class_ref()?;
+    let vtable = &class.instance_vtable;
+    let selector = args[0].send("name", &[], env)?;
+    let selector = selector.as_str()?;
+    let original = match vtable.get(selector) {
+        Some(Method::Wrapped(wrapper)) => wrapper.original.clone(),
+        _ => {
+            return Unwind::error(&format!(
+                "Cannot unwrap {}#{}, method is not wrapped",
+                vtable.name, selector
+            ))
+        }
+    };
+    vtable.methods.borrow_mut().insert(selector.to_string(), original);
+    Ok(receiver.clone())
+}
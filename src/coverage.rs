@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::mem::{discriminant, Discriminant};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::def::{Def, MethodDefinition};
+use crate::expr::Expr;
+use crate::source_location::{Lines, SourceLocation, Span};
+
+/// Records which expressions of code loaded from files get evaluated, and
+/// which arms of ifTrue:ifFalse: are taken, for an lcov report.
+///
+/// Expressions are identified by their spans: definitions register theirs
+/// when loaded, so that expressions which never run are reported too.
+pub struct Coverage {
+    report: PathBuf,
+    files: RefCell<BTreeMap<Rc<PathBuf>, FileCoverage>>,
+}
+
+impl PartialEq for Coverage {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<Coverage {}>", self.report.display())
+    }
+}
+
+/// Identifies an expression in a file: a string interpolation for example
+/// produces nested expressions with the same span.
+type Key = (Span, Discriminant<Expr>);
+
+fn key(expr: &Expr) -> Key {
+    (expr.span(), discriminant(expr))
+}
+
+#[derive(Default)]
+struct FileCoverage {
+    /// Evaluation counts of expressions.
+    exprs: HashMap<Key, u64>,
+    /// Methods: their names, where they start, and their bodies.
+    functions: Vec<(String, usize, Key)>,
+    /// Spans of the true and false arms of each ifTrue:ifFalse: with literal
+    /// blocks.
+    branches: Vec<(Span, [Span; 2])>,
+    /// Counts of arms taken, by the span of their body.
+    arms: HashMap<Span, u64>,
+}
+
+impl Coverage {
+    /// New recorder, writing its report to the given file.
+    pub fn new(report: &Path) -> Coverage {
+        Coverage {
+            report: report.to_path_buf(),
+            files: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Registers the methods and expressions of a definition loaded from a
+    /// file.
+    pub fn register(&self, def: &Def) {
+        match def {
+            Def::ClassDef(class) => {
+                self.methods(&class.name, &class.instance_methods, &class.class_methods)
+            }
+            Def::DefineDef(define) => self.expr(&define.init),
+            Def::ExtensionDef(extension) => {
+                self.methods(&extension.name, &extension.instance_methods, &extension.class_methods)
+            }
+            Def::InterfaceDef(interface) => {
+                self.methods(&interface.name, &interface.instance_methods, &interface.class_methods)
+            }
+            Def::MacroDef(definition) => {
+                self.function(&definition.name, &definition.source_location, &definition.body);
+                self.expr(&definition.body);
+            }
+            Def::ImportDef(_) | Def::OperatorDef(_) => (),
+        }
+    }
+
+    fn methods(&self, name: &str, instance: &[MethodDefinition], class: &[MethodDefinition]) {
+        for (separator, methods) in [("#", instance), ("##", class)].iter() {
+            for method in methods.iter() {
+                if let Some(body) = &method.body {
+                    let name = format!("{}{}{}", name, separator, method.selector);
+                    self.function(&name, &method.source_location, body);
+                    self.expr(body);
+                }
+            }
+        }
+    }
+
+    fn function(&self, name: &str, location: &SourceLocation, body: &Expr) {
+        let start = location.get_span().start;
+        let body = key(body);
+        self.with_file(location, |file| file.functions.push((name.to_string(), start, body)));
+    }
+
+    fn expr(&self, expr: &Expr) {
+        self.walk(&mut expr.clone());
+    }
+
+    fn walk(&self, expr: &mut Expr) {
+        if !matches!(expr, Expr::Block(_)) {
+            let key = key(expr);
+            self.with_file(&expr.source_location(), |file| {
+                file.exprs.entry(key).or_insert(0);
+            });
+        }
+        match expr {
+            // Templates are counted where they are spliced in.
+            Expr::Quote(_) => return,
+            Expr::Chain(chain) => {
+                for message in &chain.messages {
+                    if message.selector != "ifTrue:ifFalse:" {
+                        continue;
+                    }
+                    if let [Expr::Block(t), Expr::Block(f)] = message.args.as_slice() {
+                        let arms = [t.body.span(), f.body.span()];
+                        let point = message.source_location.get_span();
+                        self.with_file(&message.source_location, |file| {
+                            file.branches.push((point, arms))
+                        });
+                    }
+                }
+            }
+            _ => (),
+        }
+        let _: Result<(), ()> = expr.for_each_subexpr_mut(&mut |subexpr| {
+            self.walk(subexpr);
+            Ok(())
+        });
+    }
+
+    fn with_file(&self, location: &SourceLocation, fun: impl FnOnce(&mut FileCoverage)) {
+        if let Some(path) = location.file() {
+            fun(self.files.borrow_mut().entry(path.clone()).or_default())
+        }
+    }
+
+    /// Counts an evaluation of expr. Creating a block does not count: its
+    /// body is counted when it runs.
+    pub fn hit(&self, expr: &Expr) {
+        if let Expr::Block(_) = expr {
+            return;
+        }
+        let key = key(expr);
+        self.with_file(&expr.source_location(), |file| *file.exprs.entry(key).or_insert(0) += 1)
+    }
+
+    /// Counts a branch to the arm whose body is at location.
+    pub fn branch(&self, location: &SourceLocation) {
+        self.with_file(location, |file| *file.arms.entry(location.get_span()).or_insert(0) += 1)
+    }
+
+    /// Writes the report to the file given to Coverage::new.
+    pub fn write_report(&self) -> io::Result<()> {
+        let mut out = io::BufWriter::new(fs::File::create(&self.report)?);
+        self.write_lcov(&mut out)?;
+        out.flush()
+    }
+
+    /// Writes the counts in lcov tracefile format, with a record per file.
+    /// The count of a line is the highest count of the expressions starting
+    /// on it.
+    pub fn write_lcov(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+        for (path, file) in self.files.borrow().iter() {
+            let code = fs::read_to_string(path.as_path())?;
+            let lines = Lines::new(&code);
+            let absolute = fs::canonicalize(path.as_path()).unwrap_or_else(|_| path.to_path_buf());
+            writeln!(out, "SF:{}", absolute.display())?;
+            let mut functions: Vec<_> = file.functions.iter().collect();
+            functions.sort_by_key(|(_, start, _)| *start);
+            let mut hit = 0;
+            for (name, start, _) in &functions {
+                writeln!(out, "FN:{},{}", lines.line(*start), name)?;
+            }
+            for (name, _, body) in &functions {
+                let count = file.exprs.get(body).copied().unwrap_or(0);
+                if count > 0 {
+                    hit += 1;
+                }
+                writeln!(out, "FNDA:{},{}", count, name)?;
+            }
+            writeln!(out, "FNF:{}", file.functions.len())?;
+            writeln!(out, "FNH:{}", hit)?;
+            let mut blocks: HashMap<usize, usize> = HashMap::new();
+            let mut taken = 0;
+            for (point, arms) in &file.branches {
+                let line = lines.line(point.start);
+                let block = blocks.entry(line).or_insert(0);
+                let reached = arms.iter().any(|arm| file.arms.contains_key(arm));
+                for (i, arm) in arms.iter().enumerate() {
+                    let count = file.arms.get(arm).copied().unwrap_or(0);
+                    if count > 0 {
+                        taken += 1;
+                    }
+                    if reached {
+                        writeln!(out, "BRDA:{},{},{},{}", line, block, i, count)?;
+                    } else {
+                        writeln!(out, "BRDA:{},{},{},-", line, block, i)?;
+                    }
+                }
+                *block += 1;
+            }
+            writeln!(out, "BRF:{}", file.branches.len() * 2)?;
+            writeln!(out, "BRH:{}", taken)?;
+            let mut counts: BTreeMap<usize, u64> = BTreeMap::new();
+            for ((span, _), count) in &file.exprs {
+                let line = counts.entry(lines.line(span.start)).or_insert(0);
+                *line = (*line).max(*count);
+            }
+            for (line, count) in &counts {
+                writeln!(out, "DA:{},{}", line, count)?;
+            }
+            writeln!(out, "LF:{}", counts.len())?;
+            writeln!(out, "LH:{}", counts.values().filter(|count| **count > 0).count())?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
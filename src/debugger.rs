@@ -0,0 +1,566 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::eval::Env;
+use crate::expr::Expr;
+use crate::objects::{read_instance_variable, Object};
+use crate::source_location::{Lines, SourceLocation};
+
+/// Where to pause: at a line of a file, or on entry to a method named like
+/// Class#selector, or Class##selector for class methods.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Breakpoint {
+    Line(PathBuf, usize),
+    Method(String),
+}
+
+impl Breakpoint {
+    /// Parses FILE:LINE or Class#selector.
+    pub fn parse(spec: &str) -> Result<Breakpoint, String> {
+        let spec = spec.trim();
+        if spec.contains('#') {
+            return Ok(Breakpoint::Method(spec.to_string()));
+        }
+        if let Some(colon) = spec.rfind(':') {
+            if let Ok(line) = spec[colon + 1..].parse() {
+                return Ok(Breakpoint::Line(PathBuf::from(&spec[..colon]), line));
+            }
+        }
+        Err(format!("Bad breakpoint: '{}', expected FILE:LINE or Class#selector", spec))
+    }
+
+    fn at_line(&self, file: &Path, line: usize) -> bool {
+        match self {
+            Breakpoint::Line(path, at) => *at == line && file.ends_with(path),
+            Breakpoint::Method(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Line(path, line) => write!(f, "{}:{}", path.display(), line),
+            Breakpoint::Method(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Why the debugger paused.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    /// Before running Main, so that breakpoints can be set.
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+/// How to carry on from a pause.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resume {
+    Continue,
+    /// Pause at the next line, wherever it is.
+    StepIn,
+    /// Pause at the next line of the current frame or its callers.
+    StepOver,
+    /// Pause at the next line of a caller.
+    StepOut,
+}
+
+/// User interface of the debugger: gets control whenever the program
+/// pauses, and inspects it through the Debugger.
+pub trait Frontend {
+    fn paused(&mut self, debugger: &Debugger, stop: Stop) -> Resume;
+}
+
+/// Method or block activation, as shown to the user.
+#[derive(Clone, Debug)]
+pub struct StackFrame {
+    pub name: String,
+    pub file: Rc<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Activation being evaluated, innermost last in Debugger::frames.
+struct Frame {
+    name: String,
+    /// Environment of the last expression evaluated.
+    env: Env,
+    location: Option<SourceLocation>,
+    /// Line of the last expression evaluated, while tracked.
+    line: Option<(Rc<PathBuf>, usize)>,
+}
+
+/// Frames of a generator set aside while it is suspended, see
+/// Debugger::suspend.
+pub struct SuspendedFrames(Vec<Frame>);
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Run,
+    /// Entered a method with a breakpoint.
+    Break,
+    StepIn,
+    StepOver(usize),
+    StepOut(usize),
+}
+
+struct Source {
+    code: String,
+    lines: Lines,
+}
+
+/// Pauses the program at breakpoints and steps, handing control to a
+/// Frontend. Activations are reported by Closure, and evaluated expressions
+/// by Env::eval and Env::eval_tail: a pause happens when evaluation moves to
+/// a new line.
+pub struct Debugger {
+    /// Code loaded without a path belongs to the program being debugged.
+    program: Rc<PathBuf>,
+    frontend: RefCell<Box<dyn Frontend>>,
+    breakpoints: RefCell<Vec<(usize, Breakpoint)>>,
+    breakpoint_counter: Cell<usize>,
+    frames: RefCell<Vec<Frame>>,
+    /// Environment of the program while paused at entry.
+    toplevel: RefCell<Option<Env>>,
+    mode: Cell<Mode>,
+    /// Set while the frontend has control: expressions it evaluates do not
+    /// pause.
+    paused: Cell<bool>,
+    sources: RefCell<HashMap<Rc<PathBuf>, Option<Rc<Source>>>>,
+}
+
+impl PartialEq for Debugger {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Debugger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<Debugger {}>", self.program.display())
+    }
+}
+
+impl Debugger {
+    /// New debugger for program, controlled by frontend.
+    pub fn new(program: &Path, frontend: Box<dyn Frontend>) -> Debugger {
+        Debugger {
+            program: Rc::new(program.to_path_buf()),
+            frontend: RefCell::new(frontend),
+            breakpoints: RefCell::new(Vec::new()),
+            breakpoint_counter: Cell::new(0),
+            frames: RefCell::new(Vec::new()),
+            toplevel: RefCell::new(None),
+            mode: Cell::new(Mode::Run),
+            paused: Cell::new(false),
+            sources: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    /// Pauses before running the program loaded into env.
+    pub fn start(&self, env: &Env) {
+        self.toplevel.replace(Some(env.clone()));
+        self.pause(Stop::Entry);
+        self.toplevel.replace(None);
+    }
+
+    /// Called on activation of a method or block.
+    pub fn enter(&self, name: &str, env: &Env) {
+        self.frames.borrow_mut().push(Frame {
+            name: name.to_string(),
+            env: env.clone(),
+            location: None,
+            line: None,
+        });
+        if !self.paused.get()
+            && self.breakpoints.borrow().iter().any(|(_, breakpoint)| match breakpoint {
+                Breakpoint::Method(method) => method == name,
+                Breakpoint::Line(..) => false,
+            })
+        {
+            self.mode.set(Mode::Break);
+        }
+    }
+
+    /// Called when the activation last entered returns or unwinds.
+    pub fn leave(&self) {
+        self.frames.borrow_mut().pop();
+    }
+
+    /// Number of frames on the stack.
+    pub fn depth(&self) -> usize {
+        self.frames.borrow().len()
+    }
+
+    /// Called when a generator yields: takes its frames, those above base,
+    /// off the stack, as the consumer carries on from where it resumed the
+    /// generator.
+    pub fn suspend(&self, base: usize) -> SuspendedFrames {
+        SuspendedFrames(self.frames.borrow_mut().split_off(base))
+    }
+
+    /// Called when a generator is resumed: puts its frames back on top of
+    /// the consumer's.
+    pub fn resume(&self, frames: SuspendedFrames) {
+        self.frames.borrow_mut().extend(frames.0);
+    }
+
+    /// Called before evaluating expr in env.
+    pub fn step(&self, expr: &Expr, env: &Env) {
+        if self.paused.get() {
+            return;
+        }
+        let mode = self.mode.get();
+        let location = expr.source_location();
+        let mut frames = self.frames.borrow_mut();
+        let depth = frames.len();
+        let frame = match frames.last_mut() {
+            Some(frame) => frame,
+            None => return,
+        };
+        frame.env = env.clone();
+        if let Mode::Run = mode {
+            if !self.breakpoints.borrow().iter().any(|(_, bp)| matches!(bp, Breakpoint::Line(..))) {
+                frame.location = Some(location);
+                frame.line = None;
+                return;
+            }
+        }
+        let file = location.file().unwrap_or(&self.program).clone();
+        let line = match self.source(&file) {
+            Some(source) => source.lines.line(location.get_span().start),
+            None => return,
+        };
+        frame.location = Some(location);
+        if frame.line.as_ref() == Some(&(file.clone(), line)) {
+            return;
+        }
+        frame.line = Some((file.clone(), line));
+        drop(frames);
+        let stop = match mode {
+            Mode::Break => Some(Stop::Breakpoint),
+            Mode::StepIn => Some(Stop::Step),
+            Mode::StepOver(at) if depth <= at => Some(Stop::Step),
+            Mode::StepOut(at) if depth < at => Some(Stop::Step),
+            _ => None,
+        };
+        let stop = stop.or_else(|| {
+            if self.breakpoints.borrow().iter().any(|(_, bp)| bp.at_line(&file, line)) {
+                Some(Stop::Breakpoint)
+            } else {
+                None
+            }
+        });
+        if let Some(stop) = stop {
+            self.pause(stop);
+        }
+    }
+
+    fn pause(&self, stop: Stop) {
+        self.paused.set(true);
+        let resume = self.frontend.borrow_mut().paused(self, stop);
+        self.paused.set(false);
+        let depth = self.frames.borrow().len();
+        self.mode.set(match resume {
+            Resume::Continue => Mode::Run,
+            Resume::StepIn => Mode::StepIn,
+            Resume::StepOver => Mode::StepOver(depth),
+            Resume::StepOut => Mode::StepOut(depth),
+        });
+    }
+
+    fn source(&self, file: &Rc<PathBuf>) -> Option<Rc<Source>> {
+        if let Some(source) = self.sources.borrow().get(file) {
+            return source.clone();
+        }
+        let source = fs::read_to_string(file.as_path()).ok().map(|code| {
+            Rc::new(Source {
+                lines: Lines::new(&code),
+                code,
+            })
+        });
+        self.sources.borrow_mut().insert(file.clone(), source.clone());
+        source
+    }
+
+    /// Text of the one-based line of file.
+    pub fn source_line(&self, file: &Rc<PathBuf>, line: usize) -> Option<String> {
+        let source = self.source(file)?;
+        let start = source.lines.start(line)?;
+        source.code[start..].lines().next().map(String::from)
+    }
+
+    /// Frames of the paused program, innermost first.
+    pub fn stack(&self) -> Vec<StackFrame> {
+        self.frames
+            .borrow()
+            .iter()
+            .rev()
+            .map(|frame| {
+                let file = match &frame.location {
+                    Some(location) => location.file().unwrap_or(&self.program).clone(),
+                    None => self.program.clone(),
+                };
+                let (line, column) = match (&frame.location, self.source(&file)) {
+                    (Some(location), Some(source)) => {
+                        let offset = location.get_span().start;
+                        (source.lines.line(offset), source.lines.column(offset))
+                    }
+                    _ => (0, 0),
+                };
+                StackFrame {
+                    name: frame.name.clone(),
+                    file,
+                    line,
+                    column,
+                }
+            })
+            .collect()
+    }
+
+    /// Environment of the frame at index of the stack, or of the program
+    /// when paused at entry.
+    pub fn env(&self, index: usize) -> Option<Env> {
+        let frames = self.frames.borrow();
+        match frames.len().checked_sub(index + 1) {
+            Some(i) => Some(frames[i].env.clone()),
+            None if frames.is_empty() => self.toplevel.borrow().clone(),
+            None => None,
+        }
+    }
+
+    /// Local variables of the frame at index, innermost first.
+    pub fn locals(&self, index: usize) -> Vec<(String, Object)> {
+        match self.env(index) {
+            Some(env) => env.env_ref.locals(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Receiver of the frame at index.
+    pub fn receiver(&self, index: usize) -> Option<Object> {
+        self.env(index)?.receiver()
+    }
+
+    /// Names and values of the instance variables of object.
+    pub fn slots(&self, object: &Object) -> Vec<(String, Object)> {
+        let env = match self.env(0) {
+            Some(env) => env,
+            None => return Vec::new(),
+        };
+        let slots = object.slots(&env.foo);
+        slots
+            .iter()
+            .filter_map(|slot| {
+                read_instance_variable(object, slot.index)
+                    .ok()
+                    .map(|value| (slot.name.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Evaluates source in the frame at index.
+    pub fn evaluate(&self, index: usize, source: &str) -> Result<Object, String> {
+        match self.env(index) {
+            Some(env) => env.eval_all(source).map_err(|unwind| format!("{}", unwind)),
+            None => Err(format!("No frame {}", index)),
+        }
+    }
+
+    /// Adds a breakpoint, returning its number.
+    pub fn add_breakpoint(&self, breakpoint: Breakpoint) -> usize {
+        let id = self.breakpoint_counter.get() + 1;
+        self.breakpoint_counter.set(id);
+        self.breakpoints.borrow_mut().push((id, breakpoint));
+        id
+    }
+
+    /// Deletes the breakpoint numbered id, returning false if there is none.
+    pub fn delete_breakpoint(&self, id: usize) -> bool {
+        let mut breakpoints = self.breakpoints.borrow_mut();
+        let before = breakpoints.len();
+        breakpoints.retain(|(each, _)| *each != id);
+        breakpoints.len() < before
+    }
+
+    /// Deletes the breakpoints for which keep returns false.
+    pub fn retain_breakpoints(&self, keep: impl Fn(&Breakpoint) -> bool) {
+        self.breakpoints.borrow_mut().retain(|(_, breakpoint)| keep(breakpoint));
+    }
+
+    pub fn breakpoints(&self) -> Vec<(usize, Breakpoint)> {
+        self.breakpoints.borrow().clone()
+    }
+
+    /// Exits the program, as if by System exit:.
+    pub fn quit(&self) -> ! {
+        match self.env(0) {
+            Some(env) => env.foo.exit(0),
+            None => std::process::exit(0),
+        }
+    }
+}
+
+/// Path relative to the current directory, if it is inside it.
+fn display_path(path: &Path) -> String {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok().map(Path::to_path_buf));
+    relative.as_deref().unwrap_or(path).display().to_string()
+}
+
+const HELP: &str = "\
+break FILE:LINE | Class#selector   set a breakpoint (b)
+delete N                           delete breakpoint N
+breakpoints                        list breakpoints
+continue                           run to the next breakpoint (c)
+step                               step into sends (s)
+next                               step over sends (n)
+finish                             step out of the current method (f)
+where                              show the stack (bt)
+env                                show the receiver and local variables
+print EXPR                         evaluate EXPR in the current frame (p)
+quit                               exit the program (q)";
+
+/// Line-oriented frontend reading commands from input, which is stdin or
+/// a script. Commands read from a script are echoed. At the end of input
+/// the program runs to completion.
+pub struct Console {
+    input: Box<dyn BufRead>,
+    echo: bool,
+    attached: bool,
+}
+
+impl Console {
+    pub fn new(input: Box<dyn BufRead>, echo: bool) -> Console {
+        Console {
+            input,
+            echo,
+            attached: true,
+        }
+    }
+
+    fn show_stop(&self, debugger: &Debugger, stop: Stop) {
+        let what = match stop {
+            Stop::Entry => {
+                println!("Debugging {}, type help for commands.", debugger.program().display());
+                return;
+            }
+            Stop::Breakpoint => "Breakpoint",
+            Stop::Step => "Stopped",
+        };
+        if let Some(frame) = debugger.stack().first() {
+            println!("{} in {} at {}:{}", what, frame.name, display_path(&frame.file), frame.line);
+            if let Some(text) = debugger.source_line(&frame.file, frame.line) {
+                println!("{:>5} | {}", frame.line, text);
+            }
+        }
+    }
+
+    fn show_stack(&self, debugger: &Debugger) {
+        for (i, frame) in debugger.stack().iter().enumerate() {
+            println!(
+                "#{} {} at {}:{}:{}",
+                i,
+                frame.name,
+                display_path(&frame.file),
+                frame.line,
+                frame.column
+            );
+        }
+    }
+
+    fn show_env(&self, debugger: &Debugger) {
+        if let Some(receiver) = debugger.receiver(0) {
+            println!("self = {:?}", receiver);
+            for (name, value) in debugger.slots(&receiver) {
+                println!("  {} = {:?}", name, value);
+            }
+        }
+        for (name, value) in debugger.locals(0) {
+            println!("{} = {:?}", name, value);
+        }
+    }
+
+    /// Performs a command, returning how to resume if it resumes the
+    /// program.
+    fn command(&mut self, debugger: &Debugger, line: &str) -> Option<Resume> {
+        let line = line.trim();
+        let (command, arg) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" => {}
+            "c" | "continue" => return Some(Resume::Continue),
+            "s" | "step" => return Some(Resume::StepIn),
+            "n" | "next" => return Some(Resume::StepOver),
+            "f" | "finish" => return Some(Resume::StepOut),
+            "b" | "break" => match Breakpoint::parse(arg) {
+                Ok(breakpoint) => {
+                    let id = debugger.add_breakpoint(breakpoint.clone());
+                    println!("Breakpoint {}: {}", id, breakpoint);
+                }
+                Err(err) => println!("{}", err),
+            },
+            "delete" => match arg.parse() {
+                Ok(id) if debugger.delete_breakpoint(id) => println!("Deleted breakpoint {}", id),
+                _ => println!("No breakpoint '{}'", arg),
+            },
+            "breakpoints" => {
+                for (id, breakpoint) in debugger.breakpoints() {
+                    println!("Breakpoint {}: {}", id, breakpoint);
+                }
+            }
+            "bt" | "where" | "backtrace" => self.show_stack(debugger),
+            "env" => self.show_env(debugger),
+            "p" | "print" => match debugger.evaluate(0, arg) {
+                Ok(value) => println!("{:?}", value),
+                Err(err) => println!("{}", err),
+            },
+            "q" | "quit" => debugger.quit(),
+            "h" | "help" => println!("{}", HELP),
+            _ => println!("Unknown command '{}', type help for commands.", command),
+        }
+        None
+    }
+}
+
+impl Frontend for Console {
+    fn paused(&mut self, debugger: &Debugger, stop: Stop) -> Resume {
+        if !self.attached {
+            return Resume::Continue;
+        }
+        self.show_stop(debugger, stop);
+        loop {
+            print!("(debug) ");
+            std::io::stdout().flush().ok();
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(n) if n > 0 => {}
+                _ => {
+                    println!();
+                    self.attached = false;
+                    return Resume::Continue;
+                }
+            }
+            if self.echo {
+                println!("{}", line.trim_end());
+            }
+            if let Some(resume) = self.command(debugger, &line) {
+                return resume;
+            }
+        }
+    }
+}
@@ -6,13 +6,15 @@ use crate::source_location::SourceLocation;
 use crate::source_location::Span;
 use crate::unwind::Unwind;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Def {
     ClassDef(ClassDef),
     DefineDef(DefineDef),
     ExtensionDef(ExtensionDef),
     ImportDef(ImportDef),
     InterfaceDef(InterfaceDef),
+    MacroDef(MacroDef),
+    OperatorDef(OperatorDef),
 }
 
 impl Def {
@@ -32,6 +34,8 @@ impl Def {
             ExtensionDef(extension) => extension.source_location.clone(),
             ImportDef(import) => import.source_location.clone(),
             InterfaceDef(interface) => interface.source_location.clone(),
+            MacroDef(definition) => definition.source_location.clone(),
+            OperatorDef(operator) => operator.source_location.clone(),
         }
     }
 
@@ -60,6 +64,10 @@ impl Def {
                 import.source_location.tweak_span(shift, extend);
             }
             Def::InterfaceDef(interface) => interface.tweak_span(shift, extend),
+            Def::MacroDef(def) => def.tweak_span(shift, extend),
+            Def::OperatorDef(operator) => {
+                operator.source_location.tweak_span(shift, extend);
+            }
         }
     }
 }
@@ -73,6 +81,8 @@ pub struct ClassDef {
     pub class_methods: Vec<MethodDefinition>,
     pub interfaces: Vec<String>,
     pub default_constructor: Option<String>,
+    /// True for value classes: immutable instances with structural equality.
+    pub value: bool,
 }
 
 impl ClassDef {
@@ -89,6 +99,7 @@ impl ClassDef {
             class_methods: Vec::new(),
             interfaces: Vec::new(),
             default_constructor: None,
+            value: false,
         }
     }
 
@@ -302,6 +313,37 @@ impl MethodDefinition {
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct MacroDef {
+    pub source_location: SourceLocation,
+    pub name: String,
+    pub selector: String,
+    pub parameters: Vec<Var>,
+    pub body: Expr,
+}
+
+impl MacroDef {
+    fn tweak_span(&mut self, shift: usize, extend: isize) {
+        self.source_location.tweak_span(shift, extend);
+        for p in &mut self.parameters {
+            p.source_location.tweak_span(shift, extend);
+        }
+        self.body.tweak_span(shift, extend);
+    }
+}
+
+/// Operator declaration: affects only parsing of the rest of the module,
+/// the operator itself is implemented by methods as usual.
+#[derive(Debug, PartialEq, Clone)]
+pub struct OperatorDef {
+    pub source_location: SourceLocation,
+    pub name: String,
+    pub is_prefix: bool,
+    pub is_binary: bool,
+    /// Precedence as written in source, from 1 (loosest) to 10 (tightest).
+    pub precedence: usize,
+}
+
 #[derive(Debug)]
 pub enum MethodKind {
     Class,
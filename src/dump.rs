@@ -0,0 +1,369 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+use crate::def::*;
+use crate::expr::*;
+use crate::parse::Parser;
+use crate::source_location::Span;
+use crate::syntax::Syntax;
+use crate::tokenstream::{Token, TokenStream};
+use crate::unwind::Unwind;
+
+/// Every token in source, ending with EOF, with its span.
+pub fn tokens(source: &str) -> Result<Vec<(Token, Span)>, Unwind> {
+    let mut stream = TokenStream::new(source);
+    let mut tokens = Vec::new();
+    loop {
+        let token = stream.scan().map_err(|unwind| unwind.with_context(source))?;
+        tokens.push((token, stream.span()));
+        if token == Token::EOF {
+            return Ok(tokens);
+        }
+    }
+}
+
+/// Lines like 12..17 WORD "class".
+pub fn tokens_text(source: &str, tokens: &[(Token, Span)]) -> String {
+    let mut text = String::new();
+    for (token, span) in tokens {
+        writeln!(text, "{}..{} {:?} {:?}", span.start, span.end, token, &source[span.clone()])
+            .unwrap();
+    }
+    text
+}
+
+pub fn tokens_json(source: &str, tokens: &[(Token, Span)]) -> Value {
+    Value::Array(
+        tokens
+            .iter()
+            .map(|(token, span)| {
+                json!({
+                    "token": format!("{:?}", token),
+                    "span": [span.start, span.end],
+                    "slice": &source[span.clone()],
+                })
+            })
+            .collect(),
+    )
+}
+
+/// The toplevel definitions and expressions in file, as parsed without
+/// evaluating anything.
+pub fn syntax(file: &Path) -> Result<Vec<Syntax>, Unwind> {
+    let root = file.parent().unwrap_or_else(|| Path::new("."));
+    Parser::parse_file(file, root, |parser| {
+        let mut syntax = Vec::new();
+        while !parser.at_eof() {
+            match parser.parse() {
+                Ok(s) => syntax.push(s),
+                Err(unwind) => return Err(unwind.with_context(parser.code())),
+            }
+        }
+        Ok(syntax)
+    })
+}
+
+/// A node of the syntax tree: the kind of the node, its span if it has its
+/// own, and its fields in source order. Fields holding nodes or lists of
+/// nodes are its children.
+pub struct Node {
+    kind: &'static str,
+    span: Option<Span>,
+    fields: Vec<(&'static str, Field)>,
+}
+
+pub enum Field {
+    Value(Value),
+    Node(Node),
+    List(Vec<Node>),
+}
+
+impl Node {
+    fn new(kind: &'static str, span: Option<Span>) -> Node {
+        Node {
+            kind,
+            span,
+            fields: Vec::new(),
+        }
+    }
+
+    fn value(mut self, name: &'static str, value: Value) -> Node {
+        self.fields.push((name, Field::Value(value)));
+        self
+    }
+
+    fn node(mut self, name: &'static str, node: Node) -> Node {
+        self.fields.push((name, Field::Node(node)));
+        self
+    }
+
+    fn list(mut self, name: &'static str, nodes: Vec<Node>) -> Node {
+        self.fields.push((name, Field::List(nodes)));
+        self
+    }
+
+    /// Object with "kind", "span" as [start, end], and the fields.
+    pub fn to_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert("kind".to_string(), json!(self.kind));
+        if let Some(span) = &self.span {
+            map.insert("span".to_string(), json!([span.start, span.end]));
+        }
+        for (name, field) in &self.fields {
+            let value = match field {
+                Field::Value(value) => value.clone(),
+                Field::Node(node) => node.to_json(),
+                Field::List(nodes) => Value::Array(nodes.iter().map(Node::to_json).collect()),
+            };
+            map.insert(name.to_string(), value);
+        }
+        Value::Object(map)
+    }
+
+    /// Indented outline, one node per line: Kind start..end name=value ...
+    /// Null fields and empty lists are left out.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        self.write_text(&mut text, 0, "");
+        text
+    }
+
+    fn write_text(&self, text: &mut String, indent: usize, label: &str) {
+        write!(text, "{:indent$}{}{}", "", label, self.kind, indent = indent).unwrap();
+        if let Some(span) = &self.span {
+            write!(text, " {}..{}", span.start, span.end).unwrap();
+        }
+        for (name, field) in &self.fields {
+            match field {
+                Field::Value(Value::Null) => (),
+                Field::Value(Value::Array(values)) if values.is_empty() => (),
+                Field::Value(value) => write!(text, " {}={}", name, value).unwrap(),
+                _ => (),
+            }
+        }
+        writeln!(text).unwrap();
+        for (name, field) in &self.fields {
+            match field {
+                Field::Node(node) => node.write_text(text, indent + 2, &format!("{}: ", name)),
+                Field::List(nodes) if !nodes.is_empty() => {
+                    writeln!(text, "{:indent$}{}:", "", name, indent = indent + 2).unwrap();
+                    for node in nodes {
+                        node.write_text(text, indent + 4, "");
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+fn span(span: Span) -> Option<Span> {
+    Some(span)
+}
+
+fn optional(expr: &Option<Box<Expr>>) -> Option<Node> {
+    expr.as_ref().map(|expr| expr_node(expr))
+}
+
+fn with_optional(node: Node, name: &'static str, child: Option<Node>) -> Node {
+    match child {
+        Some(child) => node.node(name, child),
+        None => node,
+    }
+}
+
+pub fn syntax_node(syntax: &Syntax) -> Node {
+    match syntax {
+        Syntax::Def(def) => def_node(def),
+        Syntax::Expr(expr) => expr_node(expr),
+    }
+}
+
+pub fn def_node(def: &Def) -> Node {
+    match def {
+        Def::ClassDef(class) => Node::new("ClassDef", span(class.source_location.get_span()))
+            .value("name", json!(class.name))
+            .value("value", json!(class.value))
+            .value("interfaces", json!(class.interfaces))
+            .value("default_constructor", json!(class.default_constructor))
+            .list("instance_variables", class.instance_variables.iter().map(var_node).collect())
+            .list("class_methods", methods(&class.class_methods))
+            .list("instance_methods", methods(&class.instance_methods)),
+        Def::DefineDef(define) => Node::new("DefineDef", span(define.source_location.get_span()))
+            .value("name", json!(define.name))
+            .node("init", expr_node(&define.init)),
+        Def::ExtensionDef(ext) => Node::new("ExtensionDef", span(ext.source_location.get_span()))
+            .value("name", json!(ext.name))
+            .value("interfaces", json!(ext.interfaces))
+            .list("class_methods", methods(&ext.class_methods))
+            .list("instance_methods", methods(&ext.instance_methods)),
+        Def::ImportDef(import) => Node::new("ImportDef", span(import.source_location.get_span()))
+            .value("path", json!(import.path.to_string_lossy()))
+            .value("prefix", json!(import.prefix))
+            .value("name", json!(import.name)),
+        Def::InterfaceDef(interface) => {
+            Node::new("InterfaceDef", span(interface.source_location.get_span()))
+                .value("name", json!(interface.name))
+                .value("interfaces", json!(interface.interfaces))
+                .list("class_methods", methods(&interface.class_methods))
+                .list("instance_methods", methods(&interface.instance_methods))
+                .list("required_methods", methods(&interface.required_methods))
+        }
+        Def::MacroDef(mac) => Node::new("MacroDef", span(mac.source_location.get_span()))
+            .value("name", json!(mac.name))
+            .value("selector", json!(mac.selector))
+            .list("parameters", mac.parameters.iter().map(var_node).collect())
+            .node("body", expr_node(&mac.body)),
+        Def::OperatorDef(op) => Node::new("OperatorDef", span(op.source_location.get_span()))
+            .value("name", json!(op.name))
+            .value("is_prefix", json!(op.is_prefix))
+            .value("is_binary", json!(op.is_binary))
+            .value("precedence", json!(op.precedence)),
+    }
+}
+
+fn methods(methods: &[MethodDefinition]) -> Vec<Node> {
+    methods.iter().map(method_node).collect()
+}
+
+fn method_node(method: &MethodDefinition) -> Node {
+    let node = Node::new("MethodDefinition", span(method.source_location.get_span()))
+        .value("selector", json!(method.selector))
+        .value("return_type", json!(method.return_type))
+        .list("parameters", method.parameters.iter().map(var_node).collect());
+    with_optional(node, "body", optional(&method.body))
+}
+
+fn var_node(var: &Var) -> Node {
+    Node::new("Var", span(var.source_location.get_span()))
+        .value("name", json!(var.name))
+        .value("typename", json!(var.typename))
+        .value("dynamic", json!(var.dynamic))
+}
+
+fn message_node(message: &Message) -> Node {
+    Node::new("Message", span(message.source_location.get_span()))
+        .value("selector", json!(message.selector))
+        .list("args", message.args.iter().map(expr_node).collect())
+}
+
+fn const_node(constant: &Const) -> Node {
+    let (literal, value) = match &constant.literal {
+        Literal::Boolean(value) => ("Boolean", json!(value)),
+        Literal::Integer(value) => ("Integer", json!(value)),
+        Literal::Float(value) => ("Float", json!(value)),
+        Literal::String(value) => ("String", json!(value)),
+        Literal::Selector(value) => ("Selector", json!(value)),
+    };
+    Node::new("Const", span(constant.source_location.get_span()))
+        .value("literal", json!(literal))
+        .value("value", value)
+}
+
+fn pattern_node(pattern: &Pattern) -> Node {
+    let span = span(pattern.source_location().get_span());
+    match pattern {
+        Pattern::Wildcard(_) => Node::new("Wildcard", span),
+        Pattern::Var(var) => var_node(var),
+        Pattern::Literal(constant) => const_node(constant),
+        Pattern::Record(_, fields) => Node::new("RecordPattern", span).list(
+            "fields",
+            fields
+                .iter()
+                .map(|(key, pattern)| {
+                    Node::new("Field", None)
+                        .value("key", json!(key))
+                        .node("pattern", pattern_node(pattern))
+                })
+                .collect(),
+        ),
+        Pattern::Array(_, elements) => Node::new("ArrayPattern", span)
+            .list("elements", elements.iter().map(pattern_node).collect()),
+    }
+}
+
+pub fn expr_node(expr: &Expr) -> Node {
+    let span = span(expr.span());
+    match expr {
+        Expr::Array(array) => {
+            Node::new("Array", span).list("data", array.data.iter().map(expr_node).collect())
+        }
+        Expr::Assign(assign) => Node::new("Assign", span)
+            .value("name", json!(assign.name))
+            .node("value", expr_node(&assign.value)),
+        Expr::Bind(bind) => {
+            let node = Node::new("Bind", span)
+                .value("name", json!(bind.name))
+                .value("typename", json!(bind.typename))
+                .value("dynamic", json!(bind.dynamic))
+                .node("value", expr_node(&bind.value));
+            with_optional(node, "body", optional(&bind.body))
+        }
+        Expr::Block(block) => Node::new("Block", span)
+            .value("rtype", json!(block.rtype))
+            .list("params", block.params.iter().map(var_node).collect())
+            .node("body", expr_node(&block.body)),
+        Expr::Cascade(cascade) => {
+            Node::new("Cascade", span).node("receiver", expr_node(&cascade.receiver)).list(
+                "chains",
+                cascade
+                    .chains
+                    .iter()
+                    .map(|chain| {
+                        Node::new("CascadeChain", None)
+                            .list("messages", chain.iter().map(message_node).collect())
+                    })
+                    .collect(),
+            )
+        }
+        Expr::Chain(chain) => Node::new("Chain", span)
+            .node("receiver", expr_node(&chain.receiver))
+            .list("messages", chain.messages.iter().map(message_node).collect()),
+        Expr::Const(constant) => const_node(constant),
+        Expr::Destructure(destructure) => {
+            let node = Node::new("Destructure", span)
+                .node("pattern", pattern_node(&destructure.pattern))
+                .node("value", expr_node(&destructure.value));
+            with_optional(node, "body", optional(&destructure.body))
+        }
+        Expr::Dictionary(dictionary) => Node::new("Dictionary", span).list(
+            "assoc",
+            dictionary
+                .assoc
+                .iter()
+                .map(|(key, value)| {
+                    Node::new("Entry", None)
+                        .node("key", expr_node(key))
+                        .node("value", expr_node(value))
+                })
+                .collect(),
+        ),
+        Expr::Eq(eq) => Node::new("Eq", span)
+            .node("left", expr_node(&eq.left))
+            .node("right", expr_node(&eq.right)),
+        Expr::Match(m) => Node::new("Match", span).node("value", expr_node(&m.value)).list(
+            "arms",
+            m.arms
+                .iter()
+                .map(|arm| {
+                    let node =
+                        Node::new("MatchArm", None).node("pattern", pattern_node(&arm.pattern));
+                    with_optional(node, "guard", arm.guard.as_ref().map(expr_node))
+                        .node("body", expr_node(&arm.body))
+                })
+                .collect(),
+        ),
+        Expr::Panic(panic) => Node::new("Panic", span).node("value", expr_node(&panic.value)),
+        Expr::Quote(quote) => Node::new("Quote", span).node("body", expr_node(&quote.body)),
+        Expr::Return(ret) => Node::new("Return", span).node("value", expr_node(&ret.value)),
+        Expr::Seq(seq) => {
+            Node::new("Seq", span).list("exprs", seq.exprs.iter().map(expr_node).collect())
+        }
+        Expr::Typecheck(typecheck) => Node::new("Typecheck", span)
+            .value("typename", json!(typecheck.typename))
+            .node("expr", expr_node(&typecheck.expr)),
+        Expr::Var(var) => var_node(var),
+    }
+}
@@ -7,13 +7,50 @@ use std::rc::Rc;
 use crate::def::*;
 use crate::expr::*;
 use crate::objects::{
-    read_instance_variable, write_instance_variable, Arg, Datum, Eval, Foolang, Object, Source,
+    read_instance_variable, write_instance_variable, Arg, Datum, Eval, Foolang, Method, Object,
+    Source,
 };
 use crate::parse::Parser;
 use crate::source_location::SourceLocation;
 use crate::syntax::Syntax;
 use crate::unwind::Unwind;
 
+/// Result of evaluating the body of a closure: either a value, or a send in
+/// tail position which the caller performs once the frame of the closure is
+/// gone, so that tail calls run in constant stack space.
+pub enum Tail {
+    Value(Object),
+    Send(TailSend),
+}
+
+pub struct TailSend {
+    receiver: Object,
+    selector: String,
+    args: Vec<Object>,
+    source_location: SourceLocation,
+}
+
+impl Tail {
+    /// Performs pending tail sends until there is a value.
+    pub fn resolve(self, env: &Env) -> Eval {
+        let mut tail = self;
+        loop {
+            match tail {
+                Tail::Value(value) => return Ok(value),
+                Tail::Send(send) => {
+                    tail = match send.receiver.send_tail(&send.selector, &send.args, env) {
+                        Ok(tail) => tail,
+                        Err(mut unwind) => {
+                            unwind.add_source_location(&send.source_location);
+                            return Err(unwind);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Binding {
     pub typed: Option<Object>,
@@ -47,10 +84,26 @@ pub enum SymbolTable {
     Big(Vec<(String, Binding)>),
     Small((String, Binding)),
     Empty,
+    /// Activation frame of a method or block: bindings are at the slots
+    /// assigned by the resolver, see resolve.rs, which also names them.
+    /// Slots are empty until their let or pattern has been evaluated.
+    Slots(SlotNames, Vec<Option<Binding>>),
+}
+
+impl SymbolTable {
+    /// Activation frame with the given slots, all empty.
+    pub fn slots(names: &SlotNames) -> SymbolTable {
+        SymbolTable::Slots(names.clone(), vec![None; names.len()])
+    }
+}
+
+/// Index of the innermost bound slot named name.
+fn bound_slot(names: &[String], slots: &[Option<Binding>], name: &str) -> Option<usize> {
+    (0..slots.len()).rev().find(|&index| slots[index].is_some() && names[index] == name)
 }
 
 #[derive(Debug, PartialEq, Clone)]
-enum HomeRef {
+pub enum HomeRef {
     None,
     This,
     Other(EnvRef),
@@ -71,6 +124,10 @@ pub struct EnvFrame {
     home: HomeRef,
     /// Current receiver.
     receiver: Option<Object>,
+    /// Source location of the body whose call this frame belongs to, once the
+    /// call has returned. Set only if something else refers to the frame: a
+    /// block which could still try to return from it.
+    exited: Option<SourceLocation>,
 }
 
 impl EnvFrame {
@@ -101,6 +158,15 @@ impl EnvFrame {
             SymbolTable::Empty => {
                 self.symbols = SymbolTable::Small((name.to_string(), binding));
             }
+            SymbolTable::Slots(names, slots) => match bound_slot(names, slots, name) {
+                Some(index) => slots[index] = Some(binding),
+                None => {
+                    let mut more = names.to_vec();
+                    more.push(String::from(name));
+                    *names = more.into();
+                    slots.push(Some(binding));
+                }
+            },
         }
     }
     fn get_here(&self, name: &str) -> Option<&Binding> {
@@ -121,6 +187,9 @@ impl EnvFrame {
                 }
             }
             SymbolTable::Empty => None,
+            SymbolTable::Slots(names, slots) => {
+                bound_slot(names, slots, name).and_then(|index| slots[index].as_ref())
+            }
         }
     }
     fn set_here(&mut self, name: &str, value: Object) {
@@ -139,6 +208,14 @@ impl EnvFrame {
                     return;
                 }
             }
+            SymbolTable::Slots(names, slots) => {
+                if let Some(index) = bound_slot(names, slots, name) {
+                    if let Some(binding) = &mut slots[index] {
+                        binding.value = value;
+                        return;
+                    }
+                }
+            }
             _ => {
                 unreachable!();
             }
@@ -156,6 +233,9 @@ impl EnvFrame {
                 return false;
             }
             SymbolTable::Small((key, _)) if key == name => return true,
+            SymbolTable::Slots(names, slots) if bound_slot(names, slots, name).is_some() => {
+                return true
+            }
             _ => {}
         }
         match &self.parent {
@@ -168,13 +248,44 @@ impl EnvFrame {
             SymbolTable::Big(vec) => Box::new(vec.iter().map(|each| (&each.0, &each.1))),
             SymbolTable::Small(pair) => Box::new(std::iter::once((&pair.0, &pair.1))),
             SymbolTable::Empty => Box::new(std::iter::empty()),
+            SymbolTable::Slots(names, slots) => Box::new(
+                names.iter().zip(slots).filter_map(|(name, slot)| Some((name, slot.as_ref()?))),
+            ),
         }
     }
+    /// Calls objects with each object and frames with each frame referenced
+    /// by this frame: used by the cycle collector.
+    pub fn for_each_reference(
+        &self,
+        objects: &mut dyn FnMut(&Object),
+        frames: &mut dyn FnMut(&EnvRef),
+    ) {
+        for (_, binding) in self.iter() {
+            binding.typed.iter().for_each(&mut *objects);
+            objects(&binding.value);
+        }
+        self.receiver.iter().for_each(&mut *objects);
+        self.parent.iter().for_each(&mut *frames);
+        if let HomeRef::Other(home) = &self.home {
+            frames(home);
+        }
+    }
+    /// Drops all references held by this frame: used by the cycle collector
+    /// to break cycles in garbage.
+    pub fn clear(&mut self) -> (SymbolTable, Option<EnvRef>, HomeRef, Option<Object>) {
+        (
+            std::mem::replace(&mut self.symbols, SymbolTable::Empty),
+            self.parent.take(),
+            std::mem::replace(&mut self.home, HomeRef::None),
+            self.receiver.take(),
+        )
+    }
     fn len(&self) -> usize {
         match &self.symbols {
             SymbolTable::Big(map) => map.len(),
             SymbolTable::Small(_) => 1,
             SymbolTable::Empty => 0,
+            SymbolTable::Slots(_, slots) => slots.iter().flatten().count(),
         }
     }
 }
@@ -203,6 +314,9 @@ impl EnvRef {
             parent.debug();
         }
     }
+    pub fn frame(&self) -> &Rc<RefCell<EnvFrame>> {
+        &self.frame
+    }
     pub fn new() -> EnvRef {
         EnvRef {
             frame: Rc::new(RefCell::new(EnvFrame {
@@ -211,18 +325,24 @@ impl EnvRef {
                 parent: None,
                 home: HomeRef::None,
                 receiver: None,
+                exited: None,
             })),
         }
     }
 
     pub fn enclose(&self) -> EnvRef {
+        self.enclose_with(SymbolTable::Empty)
+    }
+
+    fn enclose_with(&self, symbols: SymbolTable) -> EnvRef {
         EnvRef {
             frame: Rc::new(RefCell::new(EnvFrame {
                 depth: self.depth() + 1,
-                symbols: SymbolTable::Empty,
+                symbols,
                 parent: Some(self.clone()),
                 home: HomeRef::None,
                 receiver: None,
+                exited: None,
             })),
         }
     }
@@ -241,9 +361,86 @@ impl EnvRef {
                 parent: Some(self.clone()),
                 home,
                 receiver: receiver.map(|obj| obj.clone()),
+                exited: None,
             })),
         }
     }
+    /// Marks the call this frame belongs to as returned, see EnvFrame.
+    pub fn exit(&self, body: &Expr) {
+        if Rc::strong_count(&self.frame) > 1 {
+            self.frame.borrow_mut().exited = Some(body.source_location());
+        }
+    }
+    /// True for activation frames of methods and blocks, which hold the
+    /// variables of resolved code.
+    pub fn is_activation(&self) -> bool {
+        matches!(self.frame.borrow().symbols, SymbolTable::Slots(..))
+    }
+    /// Innermost enclosing frame which is not an activation frame: where
+    /// variables free in resolved code are found.
+    fn outside_activations(&self) -> EnvRef {
+        let mut env = self.clone();
+        while env.is_activation() {
+            match env.parent() {
+                Some(parent) => env = parent,
+                None => break,
+            }
+        }
+        env
+    }
+    fn frame_at(&self, depth: usize) -> Option<EnvRef> {
+        let mut env = self.clone();
+        for _ in 0..depth {
+            env = env.parent()?;
+        }
+        Some(env)
+    }
+    fn get_at(&self, depth: usize, index: usize) -> Option<Object> {
+        let frame = self.frame.borrow();
+        if depth > 0 {
+            return frame.parent.as_ref()?.get_at(depth - 1, index);
+        }
+        match &frame.symbols {
+            SymbolTable::Slots(_, slots) => match slots.get(index) {
+                Some(Some(binding)) => Some(binding.value.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    fn set_at(&self, depth: usize, index: usize, value: Object, env: &Env) -> Option<Eval> {
+        let target = self.frame_at(depth)?;
+        let binding = match &target.frame.borrow().symbols {
+            SymbolTable::Slots(_, slots) => match slots.get(index) {
+                Some(Some(binding)) => binding.clone(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if let Err(e) = binding.check_assign(&value, env) {
+            return Some(Err(e));
+        }
+        if let SymbolTable::Slots(_, slots) = &mut target.frame.borrow_mut().symbols {
+            if let Some(Some(binding)) = slots.get_mut(index) {
+                binding.value = value.clone();
+            }
+        }
+        Some(Ok(value))
+    }
+    /// Binds slot index of this activation frame.
+    pub fn bind_slot(&self, index: usize, binding: Binding) {
+        match &mut self.frame.borrow_mut().symbols {
+            SymbolTable::Slots(_, slots) => slots[index] = Some(binding),
+            _ => panic!("BUG: binding slot {} outside an activation frame", index),
+        }
+    }
+    /// True if nothing but the call running in this frame refers to it.
+    pub fn is_unshared(&self) -> bool {
+        Rc::strong_count(&self.frame) == 1
+    }
+    fn exited(&self) -> Option<SourceLocation> {
+        self.frame.borrow().exited.clone()
+    }
     fn depth(&self) -> u32 {
         self.frame.borrow().depth
     }
@@ -269,6 +466,29 @@ impl EnvRef {
             },
         }
     }
+    /// Bindings of the method or block activation this frame belongs to and
+    /// the scopes in between, innermost first. Shadowed bindings are left
+    /// out.
+    pub fn locals(&self) -> Vec<(String, Object)> {
+        let mut locals: Vec<(String, Object)> = Vec::new();
+        let mut next = Some(self.clone());
+        while let Some(here) = next {
+            if here.is_toplevel() {
+                break;
+            }
+            let frame = here.frame.borrow();
+            for (name, binding) in frame.iter() {
+                if !locals.iter().any(|(seen, _)| seen == name) {
+                    locals.push((name.clone(), binding.value.clone()));
+                }
+            }
+            if frame.receiver.is_some() {
+                break;
+            }
+            next = frame.parent.clone();
+        }
+        locals
+    }
     fn homeref(&self) -> HomeRef {
         let frame = self.frame.borrow();
         match &frame.home {
@@ -311,9 +531,9 @@ impl EnvRef {
         self.frame.borrow_mut().set_here(name, value.clone());
         Some(Ok(value))
     }
-    fn receiver_class(&self) -> Option<Object> {
+    fn receiver_class(&self, foolang: &Foolang) -> Option<Object> {
         if let Some(receiver) = self.receiver() {
-            receiver.vtable.class.borrow().clone()
+            receiver.vtable(foolang).class.borrow().clone()
         } else {
             None
         }
@@ -322,9 +542,6 @@ impl EnvRef {
         if name == "self" {
             return self.receiver();
         }
-        if name == "Self" {
-            return self.receiver_class();
-        }
         match self.get_binding(name) {
             None => None,
             Some(binding) => Some(binding.value.clone()),
@@ -349,6 +566,17 @@ impl EnvRef {
             self.parent().unwrap().find_global(name)
         }
     }
+    fn globals(&self) -> Vec<(String, Object)> {
+        if self.is_toplevel() {
+            self.frame
+                .borrow()
+                .iter()
+                .map(|(name, binding)| (name.clone(), binding.value.clone()))
+                .collect()
+        } else {
+            self.parent().unwrap().globals()
+        }
+    }
     pub fn import_name(&self, module: &EnvRef, name: &str) -> Result<(), Unwind> {
         match module.get_binding(name) {
             None => {
@@ -455,7 +683,12 @@ impl Env {
         Parser::parse_file(code, root, |parser: &mut Parser| {
             while !parser.at_eof() {
                 match parser.parse()? {
-                    Syntax::Def(def) => self.augment(&def).context(parser.code())?,
+                    Syntax::Def(def) => {
+                        if let Some(coverage) = &self.foo.coverage {
+                            coverage.register(&def);
+                        }
+                        self.augment(&def).context(parser.code())?
+                    }
                     // FIXME: Better error needed here.
                     Syntax::Expr(expr) => {
                         return Unwind::error_at(expr.source_location(), "Expression at toplevel")
@@ -478,7 +711,7 @@ impl Env {
         self.env_ref.has_definition(name)
     }
     /// Returns the receiver of the underlying `EnvFrame`.
-    fn receiver(&self) -> Option<Object> {
+    pub fn receiver(&self) -> Option<Object> {
         self.env_ref.receiver()
     }
     /// Returns the home of the underlying `EnvFrame`.
@@ -486,6 +719,18 @@ impl Env {
         self.env_ref.home()
     }
 
+    /// Environment for bindings of resolved code: a new frame if the
+    /// resolver opened one for them, otherwise this one.
+    fn scope(&self, frame: &Option<SlotNames>) -> Env {
+        match frame {
+            Some(names) => Env {
+                env_ref: self.env_ref.enclose_with(SymbolTable::slots(names)),
+                foo: self.foo.clone(),
+            },
+            None => self.clone(),
+        }
+    }
+
     /// Creates a new environment enclosed by this one, with no additional bindings.
     /// Used to go from toplevel to not-toplevel.
     fn enclose(&self) -> Env {
@@ -530,6 +775,9 @@ impl Env {
     }
 
     pub fn get(&self, name: &str) -> Option<Object> {
+        if name == "Self" {
+            return self.env_ref.receiver_class(&self.foo);
+        }
         self.env_ref.get(name)
     }
 
@@ -541,7 +789,7 @@ impl Env {
         while !parser.at_eof() {
             res = match parser.parse() {
                 Ok(Syntax::Def(def)) => env.augment(&def).context(&source)?,
-                Ok(Syntax::Expr(expr)) => env.eval(&expr).context(&source)?,
+                Ok(Syntax::Expr(expr)) => env.eval_toplevel(&expr).context(&source)?,
                 Err(unwind) => return Err(unwind.with_context(&source)),
             };
         }
@@ -549,17 +797,46 @@ impl Env {
     }
 
     pub fn augment(&self, def: &Def) -> Eval {
+        if self.foo.has_macros() {
+            let mut def = def.clone();
+            self.expand_macros_in_def(&mut def)?;
+            self.augment_expanded(&def)
+        } else {
+            self.augment_expanded(def)
+        }
+    }
+
+    /// Evaluates an expression appearing at toplevel, expanding macros first.
+    pub fn eval_toplevel(&self, expr: &Expr) -> Eval {
+        if self.foo.has_macros() {
+            let mut expr = expr.clone();
+            self.expand_macros(&mut expr)?;
+            self.eval(&expr)
+        } else {
+            self.eval(expr)
+        }
+    }
+
+    fn augment_expanded(&self, def: &Def) -> Eval {
         match def {
             Def::ClassDef(klass) => self.do_class(klass),
             Def::DefineDef(def) => self.do_define(def),
             Def::ExtensionDef(extension) => self.do_extension(extension),
             Def::ImportDef(import) => self.do_import(import),
             Def::InterfaceDef(interface) => self.do_interface(interface),
+            Def::MacroDef(definition) => self.do_macro(definition),
+            Def::OperatorDef(operator) => self.do_operator(operator),
         }
     }
 
     pub fn eval(&self, expr: &Expr) -> Eval {
         use Expr::*;
+        if let Some(coverage) = &self.foo.coverage {
+            coverage.hit(expr);
+        }
+        if let Some(debugger) = &self.foo.debugger {
+            debugger.step(expr, self);
+        }
         match expr {
             Array(array) => self.eval_array(array),
             Assign(assign) => self.eval_assign(assign),
@@ -567,9 +844,12 @@ impl Env {
             Block(block) => self.eval_block(block),
             Cascade(cascade) => self.eval_cascade(cascade),
             Const(constant) => self.eval_constant(constant),
+            Destructure(destructure) => self.eval_destructure(destructure),
             Dictionary(dictionary) => self.eval_dictionary(dictionary),
             Eq(eq) => self.eval_eq(eq),
+            Match(m) => self.eval_match(m),
             Panic(panic) => self.eval_panic(panic),
+            Quote(quote) => self.eval_quote(quote),
             Return(ret) => self.eval_return(ret),
             Chain(chain) => self.eval_chain(chain),
             Seq(seq) => self.eval_seq(&seq),
@@ -578,30 +858,163 @@ impl Env {
         }
     }
 
+    /// Counts a branch to arm for coverage, if it is a block.
+    pub fn count_branch(&self, arm: &Object) {
+        if let (Some(coverage), Datum::Closure(closure)) = (&self.foo.coverage, &arm.datum) {
+            coverage.branch(&closure.body.source_location());
+        }
+    }
+
+    /// Evaluates the body of a closure. The last send of a chain in tail
+    /// position is returned unperformed, see Tail. Tail positions are the
+    /// last expression of a sequence, the body of a let, the last
+    /// expression of the branch taken by ifTrue:ifFalse:, and the value of
+    /// a return from the closure itself.
+    pub fn eval_tail(&self, expr: &Expr) -> Result<Tail, Unwind> {
+        self.eval_tail_in(expr, &self.env_ref)
+    }
+
+    /// Same as eval_tail, in a scope of the closure running in activation.
+    fn eval_tail_in(&self, expr: &Expr, activation: &EnvRef) -> Result<Tail, Unwind> {
+        if let Some(coverage) = &self.foo.coverage {
+            // Other expressions are counted by eval.
+            if let Expr::Seq(_) | Expr::Bind(_) | Expr::Chain(_) | Expr::Return(_) = expr {
+                coverage.hit(expr);
+            }
+        }
+        if let Some(debugger) = &self.foo.debugger {
+            if let Expr::Seq(_) | Expr::Bind(_) | Expr::Chain(_) | Expr::Return(_) = expr {
+                debugger.step(expr, self);
+            }
+        }
+        match expr {
+            Expr::Seq(seq) => match seq.exprs.split_last() {
+                Some((last, init)) => {
+                    for expr in init {
+                        self.eval(expr)?;
+                    }
+                    self.eval_tail_in(last, activation)
+                }
+                None => Ok(Tail::Value(self.foo.make_boolean(false))),
+            },
+            Expr::Bind(bind) => match (bind.slot, &bind.body) {
+                (Some(slot), Some(body)) => {
+                    let (env, _) = self.eval_bind_slot(bind, slot)?;
+                    env.eval_tail_in(body, activation)
+                }
+                _ => Ok(Tail::Value(self.eval_bind(bind)?)),
+            },
+            Expr::Chain(chain) => self.eval_chain_tail(chain),
+            // Returning from the closure running in activation is the same
+            // as leaving it with the value: the closure's finish does the rest.
+            Expr::Return(ret) => match self.home() {
+                Some(home) if &home == activation => self.eval_tail_in(&ret.value, activation),
+                _ => Ok(Tail::Value(self.eval_return(ret)?)),
+            },
+            _ => Ok(Tail::Value(self.eval(expr)?)),
+        }
+    }
+
+    fn eval_chain_tail(&self, chain: &Chain) -> Result<Tail, Unwind> {
+        let (last, init) = match chain.messages.split_last() {
+            Some(split) => split,
+            None => return Ok(Tail::Value(self.eval(&chain.receiver)?)),
+        };
+        let receiver = self.eval_sends(self.eval(&chain.receiver)?, init)?;
+        self.safe_point();
+        let mut args = Vec::with_capacity(last.args.len());
+        for arg in &last.args {
+            args.push(self.eval(arg)?);
+        }
+        if last.selector == "ifTrue:ifFalse:"
+            && receiver.is_boolean()
+            && self.is_builtin(&receiver, last)
+        {
+            let branch = if receiver.boolean() {
+                &args[0]
+            } else {
+                &args[1]
+            };
+            if branch.is_closure() && branch.closure_ref().params.is_empty() {
+                let branch = branch.clone();
+                if self.foo.coverage.is_some() {
+                    self.count_branch(&branch);
+                }
+                drop(args);
+                let res = branch.closure_ref().apply0_tail(None, self);
+                return res.map_err(|mut unwind| {
+                    unwind.add_source_location(&last.source_location);
+                    unwind
+                });
+            }
+        }
+        Ok(Tail::Send(TailSend {
+            receiver,
+            selector: last.selector.clone(),
+            args,
+            source_location: last.source_location.clone(),
+        }))
+    }
+
+    /// True if sending message to receiver would perform the builtin
+    /// primitive untraced: it has not been redefined, wrapped, or traced.
+    /// Primitives cannot be defined in Foolang, so any primitive method is
+    /// the builtin one.
+    fn is_builtin(&self, receiver: &Object, message: &Message) -> bool {
+        let method = receiver.vtable(&self.foo).get(&message.selector);
+        matches!(method, Some(Method::Primitive(_)))
+            && !self.foo.trace.traces(receiver, &message.selector, method.as_ref())
+    }
+
     fn eval_array(&self, array: &Array) -> Eval {
         let mut data = Vec::new();
-        let array_env = self.enclose();
+        let array_env = if self.env_ref.is_activation() {
+            self.clone()
+        } else {
+            self.enclose()
+        };
         for elt in &array.data {
             data.push(array_env.eval(elt)?);
         }
         Ok(self.foo.into_array(data, None))
     }
 
-    fn eval_bind(&self, bind: &Bind) -> Eval {
+    fn eval_binding(&self, bind: &Bind) -> Result<Binding, Unwind> {
         let value = self.eval(&bind.value)?;
-        let binding = match bind.typename {
-            None => Binding::untyped(value),
+        match bind.typename {
+            None => Ok(Binding::untyped(value)),
             Some(ref typename) => {
                 let typed = self.find_type(typename).source(&bind.source_location)?;
                 match Binding::typed(typed, value, self) {
-                    Ok(ok) => ok,
+                    Ok(ok) => Ok(ok),
                     Err(mut unwind) => {
                         unwind.add_source_location(&bind.value.source_location());
-                        return Err(unwind);
+                        Err(unwind)
                     }
                 }
             }
-        };
+        }
+    }
+
+    /// Binds the slot of a resolved let, returning the environment for the
+    /// body and the value.
+    fn eval_bind_slot(&self, bind: &Bind, slot: usize) -> Result<(Env, Object), Unwind> {
+        let binding = self.eval_binding(bind)?;
+        let value = binding.value.clone();
+        let env = self.scope(&bind.frame);
+        env.env_ref.bind_slot(slot, binding);
+        Ok((env, value))
+    }
+
+    fn eval_bind(&self, bind: &Bind) -> Eval {
+        if let Some(slot) = bind.slot {
+            let (env, value) = self.eval_bind_slot(bind, slot)?;
+            return match &bind.body {
+                None => Ok(value),
+                Some(body) => env.eval(body),
+            };
+        }
+        let binding = self.eval_binding(bind)?;
         let tmp = binding.value.clone();
         // FIXME: the toplevel environment be marked as workspace to allow this,
         // or even better this should arrange to return the new environment somehow,
@@ -645,7 +1058,14 @@ impl Env {
             args.push(Arg::new(p.source_location.clone(), p.name.clone()));
             parameter_types.push(&p.typename);
         }
-        self.foo.make_closure(&self, args, (*block.body).clone(), parameter_types, &block.rtype)
+        self.foo.make_closure(
+            &self,
+            args,
+            (*block.body).clone(),
+            block.frame.clone(),
+            parameter_types,
+            &block.rtype,
+        )
     }
 
     fn eval_cascade(&self, cascade: &Cascade) -> Eval {
@@ -736,6 +1156,135 @@ impl Env {
         Ok(self.foo.make_string(&import.path.to_string_lossy()))
     }
 
+    fn do_macro(&self, definition: &MacroDef) -> Eval {
+        let name = &definition.name;
+        self.check_not_defined(name, &definition.source_location)?;
+        let mut params = vec![];
+        let mut parameter_types = vec![];
+        for p in &definition.parameters {
+            params.push(Arg::new(p.source_location.clone(), p.name.clone()));
+            parameter_types.push(&p.typename);
+        }
+        let function = self.foo.make_closure(
+            self,
+            params,
+            definition.body.clone(),
+            None,
+            parameter_types,
+            &None,
+        )?;
+        let value = self.foo.make_macro(name, &definition.selector, function);
+        self.define(name, value.clone());
+        Ok(value)
+    }
+
+    fn expand_macros_in_def(&self, def: &mut Def) -> Result<(), Unwind> {
+        let methods = match def {
+            Def::ClassDef(class) => {
+                vec![&mut class.instance_methods, &mut class.class_methods]
+            }
+            Def::ExtensionDef(extension) => {
+                vec![&mut extension.instance_methods, &mut extension.class_methods]
+            }
+            Def::InterfaceDef(interface) => {
+                vec![&mut interface.instance_methods, &mut interface.class_methods]
+            }
+            Def::DefineDef(definition) => return self.expand_macros(&mut definition.init),
+            Def::MacroDef(definition) => return self.expand_macros(&mut definition.body),
+            Def::ImportDef(_) | Def::OperatorDef(_) => return Ok(()),
+        };
+        for method in methods.into_iter().flatten() {
+            if let Some(ref mut body) = method.body {
+                self.expand_macros(body)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces macro calls in expr with their expansions.
+    pub fn expand_macros(&self, expr: &mut Expr) -> Result<(), Unwind> {
+        self.expand_macros_at_depth(expr, 0)
+    }
+
+    fn expand_macros_at_depth(&self, expr: &mut Expr, depth: usize) -> Result<(), Unwind> {
+        if let Expr::Quote(_) = expr {
+            // Templates are expanded when the macro using them is.
+            return Ok(());
+        }
+        if let Some(expansion) = self.expand_macro_call(expr)? {
+            if depth >= MAX_MACRO_EXPANSION_DEPTH {
+                return Unwind::error_at(expr.source_location(), "Macro expansion too deep");
+            }
+            *expr = expansion;
+            return self.expand_macros_at_depth(expr, depth + 1);
+        }
+        expr.for_each_subexpr_mut(&mut |subexpr| self.expand_macros_at_depth(subexpr, depth))
+    }
+
+    /// If expr is a macro call returns its expansion.
+    fn expand_macro_call(&self, expr: &Expr) -> Result<Option<Expr>, Unwind> {
+        let chain = match expr {
+            Expr::Chain(chain) => chain,
+            _ => return Ok(None),
+        };
+        let name = match &*chain.receiver {
+            Expr::Var(var) => &var.name,
+            _ => return Ok(None),
+        };
+        let function = match self.get(name) {
+            Some(Object {
+                datum: Datum::Macro(ref function),
+                ..
+            }) => function.clone(),
+            _ => return Ok(None),
+        };
+        let message = &chain.messages[0];
+        let mut site = chain.receiver.source_location();
+        site.extend_span_to(message.source_location.end());
+        if message.selector != function.selector {
+            return Unwind::error_at(
+                site,
+                &format!(
+                    "Macro {} expects {}, got: {}",
+                    name, &function.selector, &message.selector
+                ),
+            );
+        }
+        let args: Vec<Object> =
+            message.args.iter().map(|arg| self.foo.into_syntax(arg.clone())).collect();
+        let old_site = self.foo.set_expansion_site(Some(site.clone()));
+        let res = function.function.closure_ref().apply(None, &args, self);
+        self.foo.set_expansion_site(old_site);
+        let result = match res {
+            Ok(result) => result,
+            Err(Unwind::Panic(error, _)) => {
+                return Unwind::error_at(
+                    site,
+                    &format!("Error while expanding macro {}: {}", name, error.what()),
+                )
+            }
+            Err(unwind) => return Err(unwind),
+        };
+        let mut expansion = match &result.datum {
+            Datum::Syntax(syntax) => syntax.expr.clone(),
+            _ => {
+                return Unwind::error_at(
+                    site,
+                    &format!("Macro {} did not return Syntax: {:?}", name, &result),
+                )
+            }
+        };
+        for message in &chain.messages[1..] {
+            expansion = expansion.send(message.clone());
+        }
+        Ok(Some(expansion))
+    }
+
+    fn do_operator(&self, operator: &OperatorDef) -> Eval {
+        // Operator declarations are handled entirely by the parser.
+        Ok(self.foo.make_string(&operator.name))
+    }
+
     fn do_interface(&self, interface: &InterfaceDef) -> Eval {
         let name = &interface.name;
         self.check_not_defined(&interface.name, &interface.source_location)?;
@@ -758,6 +1307,148 @@ impl Env {
         Ok(self.foo.into_dictionary(data))
     }
 
+    fn eval_destructure(&self, destructure: &Destructure) -> Eval {
+        let value = self.eval(&destructure.value)?;
+        let mut bindings = vec![];
+        if !self.match_pattern(&destructure.pattern, &value, &mut bindings)? {
+            return Unwind::error_at(
+                destructure.source_location.clone(),
+                &format!("Pattern {} does not match: {:?}", &destructure.pattern, &value),
+            );
+        }
+        match &destructure.body {
+            None => {
+                // Same as eval_bind: at toplevel the bindings go into the
+                // toplevel environment.
+                let env = if self.is_toplevel() {
+                    self.clone()
+                } else {
+                    self.pattern_env(&destructure.frame)
+                };
+                env.bind_pattern(bindings);
+                Ok(value)
+            }
+            Some(body) => {
+                let env = self.pattern_env(&destructure.frame);
+                env.bind_pattern(bindings);
+                env.eval(body)
+            }
+        }
+    }
+
+    fn eval_match(&self, m: &Match) -> Eval {
+        let value = self.eval(&m.value)?;
+        for arm in &m.arms {
+            let mut bindings = vec![];
+            if !self.match_pattern(&arm.pattern, &value, &mut bindings)? {
+                continue;
+            }
+            let env = self.pattern_env(&arm.frame);
+            env.bind_pattern(bindings);
+            if let Some(guard) = &arm.guard {
+                let test = env.eval(guard)?;
+                if !test.is_boolean() {
+                    return Unwind::error_at(
+                        guard.source_location(),
+                        &format!("Guard did not evaluate to a Boolean: {:?}", &test),
+                    );
+                }
+                if !test.boolean() {
+                    continue;
+                }
+            }
+            return env.eval(&arm.body);
+        }
+        Unwind::error_at(m.source_location.clone(), &format!("No pattern matched: {:?}", &value))
+    }
+
+    /// Environment for the variables of a pattern: resolved code binds them
+    /// in the frame chosen by the resolver.
+    fn pattern_env(&self, frame: &Option<SlotNames>) -> Env {
+        if self.env_ref.is_activation() {
+            self.scope(frame)
+        } else {
+            self.enclose()
+        }
+    }
+
+    fn bind_pattern(&self, bindings: Vec<(&Var, Binding)>) {
+        for (var, binding) in bindings {
+            match var.address {
+                Some(Address::Local {
+                    index,
+                    ..
+                }) => self.env_ref.bind_slot(index, binding),
+                _ => self.ensure_binding(&var.name, binding),
+            }
+        }
+    }
+
+    /// Matches value against pattern, collecting variable bindings.
+    fn match_pattern<'a>(
+        &self,
+        pattern: &'a Pattern,
+        value: &Object,
+        bindings: &mut Vec<(&'a Var, Binding)>,
+    ) -> Result<bool, Unwind> {
+        match pattern {
+            Pattern::Wildcard(_) => Ok(true),
+            Pattern::Var(var) => {
+                let binding = match &var.typename {
+                    None => Binding::untyped(value.clone()),
+                    Some(typename) => {
+                        let typed = self.find_type(typename).source(&var.source_location)?;
+                        let test = typed.send("includes:", std::slice::from_ref(value), self)?;
+                        if !(test.is_boolean() && test.boolean()) {
+                            return Ok(false);
+                        }
+                        Binding::typed(typed, value.clone(), self)?
+                    }
+                };
+                if var.name != "_" {
+                    bindings.push((var, binding));
+                }
+                Ok(true)
+            }
+            Pattern::Literal(constant) => {
+                let test =
+                    self.eval_constant(constant)?.send("==", std::slice::from_ref(value), self)?;
+                Ok(test.is_boolean() && test.boolean())
+            }
+            Pattern::Record(_, fields) => {
+                let record = match &value.datum {
+                    Datum::Record(record) => record,
+                    _ => return Ok(false),
+                };
+                for (key, field_pattern) in fields {
+                    let field = match record.borrow().get(key) {
+                        Some(field) => field.clone(),
+                        None => return Ok(false),
+                    };
+                    if !self.match_pattern(field_pattern, &field, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            Pattern::Array(_, elements) => {
+                let data = match &value.datum {
+                    Datum::Array(array) => array.borrow().clone(),
+                    _ => return Ok(false),
+                };
+                if data.len() != elements.len() {
+                    return Ok(false);
+                }
+                for (element_pattern, element) in elements.iter().zip(data.iter()) {
+                    if !self.match_pattern(element_pattern, element, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+
     fn eval_eq(&self, eq: &Eq) -> Eval {
         if self.eval(&eq.left)? == self.eval(&eq.right)? {
             Ok(self.foo.make_boolean(true))
@@ -799,6 +1490,11 @@ impl Env {
         self.env_ref.find_global(name)
     }
 
+    /// Names defined or imported at the toplevel, and their values.
+    pub fn globals(&self) -> Vec<(String, Object)> {
+        self.env_ref.globals()
+    }
+
     pub fn find_global_or_unwind(&self, name: &str) -> Eval {
         match self.find_global(name) {
             Some(obj) => Ok(obj),
@@ -825,6 +1521,87 @@ impl Env {
         Ok(self.foo.make_boolean(true))
     }
 
+    fn eval_quote(&self, quote: &Quote) -> Eval {
+        let mut template = (*quote.body).clone();
+        let mut renames = HashMap::new();
+        collect_template_bindings(&mut template, &mut |name| {
+            if !renames.contains_key(name) {
+                renames.insert(name.to_string(), self.foo.gensym(name));
+            }
+        });
+        if let Some(site) = self.foo.expansion_site() {
+            template.relocate(&site);
+        }
+        self.instantiate_template(&mut template, &renames)?;
+        Ok(self.foo.into_syntax(template))
+    }
+
+    /// Renames variables bound by the template, and replaces variables bound
+    /// to Syntax objects with their syntax.
+    fn instantiate_template(
+        &self,
+        expr: &mut Expr,
+        renames: &HashMap<String, String>,
+    ) -> Result<(), Unwind> {
+        let rename = |var: &mut Var| {
+            if let Some(name) = renames.get(&var.name) {
+                var.name = name.clone();
+            }
+        };
+        match expr {
+            Expr::Quote(_) => return Ok(()),
+            Expr::Var(var) => {
+                if renames.contains_key(&var.name) {
+                    rename(var);
+                } else if let Some(syntax) = self.get_syntax(&var.name) {
+                    *expr = syntax.expr.clone();
+                }
+                return Ok(());
+            }
+            Expr::Assign(assign) => {
+                if let Some(name) = renames.get(&assign.name) {
+                    assign.name = name.clone();
+                } else if let Some(syntax) = self.get_syntax(&assign.name) {
+                    match &syntax.expr {
+                        Expr::Var(var) => assign.name = var.name.clone(),
+                        _ => {
+                            return Unwind::error_at(
+                                assign.source_location.clone(),
+                                &format!("Cannot assign to {}: not a variable", &assign.name),
+                            )
+                        }
+                    }
+                }
+            }
+            Expr::Bind(bind) => {
+                if let Some(name) = renames.get(&bind.name) {
+                    bind.name = name.clone();
+                }
+            }
+            Expr::Block(block) => block.params.iter_mut().for_each(rename),
+            Expr::Destructure(destructure) => {
+                destructure.pattern.for_each_variable_mut(&mut |var| rename(var))
+            }
+            Expr::Match(m) => {
+                for arm in &mut m.arms {
+                    arm.pattern.for_each_variable_mut(&mut |var| rename(var));
+                }
+            }
+            _ => (),
+        }
+        expr.for_each_subexpr_mut(&mut |subexpr| self.instantiate_template(subexpr, renames))
+    }
+
+    fn get_syntax(&self, name: &str) -> Option<Rc<crate::classes::syntax::Syntax>> {
+        match self.get(name) {
+            Some(Object {
+                datum: Datum::Syntax(syntax),
+                ..
+            }) => Some(syntax),
+            _ => None,
+        }
+    }
+
     fn eval_panic(&self, panic: &Panic) -> Eval {
         Unwind::error_at(panic.source_location.clone(), self.eval(&panic.value)?.string_as_str())
     }
@@ -832,11 +1609,48 @@ impl Env {
     fn eval_return(&self, ret: &Return) -> Eval {
         match self.home() {
             None => Unwind::error_at(ret.source_location.clone(), "Nothing to return from"),
-            Some(env) => Unwind::return_from(env, self.eval(&ret.value)?),
+            Some(env) => {
+                let value = self.eval(&ret.value)?;
+                match env.exited() {
+                    Some(method) => {
+                        Unwind::block_context_expired_at(ret.source_location.clone(), method)
+                    }
+                    None => Unwind::return_from(env, value),
+                }
+            }
         }
     }
 
-    fn eval_sends(&self, mut receiver: Object, messages: &Vec<Message>) -> Eval {
+    /// Collects cycles if the heap wants it, runs pending finalizers, and
+    /// cancels dropped generators.
+    /// Called before the interpreter evaluates sends: any primitive further
+    /// up the stack is waiting for a send to return, so collecting here never
+    /// pulls objects from under it, or runs code in the middle of it.
+    pub fn safe_point(&self) {
+        if self.foo.heap.wants_collection() {
+            self.foo.heap.collect();
+        }
+        if self.foo.heap.has_pending_finalizers() {
+            self.run_finalizers();
+        }
+        if self.foo.heap.has_pending_cancellations() {
+            self.foo.heap.run_cancellations();
+        }
+    }
+
+    /// Runs finalizers of objects which are gone. Errors are reported but
+    /// do not propagate: there is nothing the code running here could do
+    /// about them.
+    pub fn run_finalizers(&self) {
+        for action in self.foo.heap.take_pending_finalizers() {
+            if let Err(err) = action.send("value", &[], self) {
+                eprintln!("ERROR in finalizer: {}", err);
+            }
+        }
+    }
+
+    fn eval_sends(&self, mut receiver: Object, messages: &[Message]) -> Eval {
+        self.safe_point();
         /*
         let orig_receiver = receiver.clone();
         unsafe {
@@ -884,11 +1698,23 @@ impl Env {
 
     fn eval_assign(&self, assign: &Assign) -> Eval {
         let value = self.eval(&assign.value)?;
-        match self.set(&assign.name, value.clone()) {
+        let res = match assign.address {
+            Some(Address::Local {
+                depth,
+                index,
+            }) => self.env_ref.set_at(depth, index, value.clone(), self),
+            Some(Address::Free) => {
+                self.env_ref.outside_activations().set(&assign.name, value.clone(), self)
+            }
+            None => self.set(&assign.name, value.clone()),
+        };
+        match res {
             Some(res) => res.source_expr(&assign.value),
             None => {
                 if let Some(receiver) = self.receiver() {
-                    if let Some(slot) = receiver.slots().iter().find(|s| &s.name == &assign.name) {
+                    if let Some(slot) =
+                        receiver.slots(&self.foo).iter().find(|s| &s.name == &assign.name)
+                    {
                         return write_instance_variable(&receiver, slot, value, self)
                             .source_expr(&assign.value);
                     }
@@ -903,11 +1729,23 @@ impl Env {
     }
 
     fn eval_var(&self, var: &Var) -> Eval {
-        match self.get(&var.name) {
+        let value = match var.address {
+            Some(Address::Local {
+                depth,
+                index,
+            }) => self.env_ref.get_at(depth, index),
+            Some(Address::Free) if var.name != "self" && var.name != "Self" => {
+                self.env_ref.outside_activations().get(&var.name)
+            }
+            _ => self.get(&var.name),
+        };
+        match value {
             Some(value) => return Ok(value),
             None => {
                 if let Some(receiver) = self.receiver() {
-                    if let Some(slot) = receiver.slots().iter().find(|s| &s.name == &var.name) {
+                    if let Some(slot) =
+                        receiver.slots(&self.foo).iter().find(|s| &s.name == &var.name)
+                    {
                         return read_instance_variable(&receiver, slot.index);
                     }
                 }
@@ -926,6 +1764,31 @@ impl Env {
     }
 }
 
+const MAX_MACRO_EXPANSION_DEPTH: usize = 100;
+
+/// Calls fun with each name bound by the template, not including nested
+/// templates.
+fn collect_template_bindings(expr: &mut Expr, fun: &mut dyn FnMut(&str)) {
+    match expr {
+        Expr::Quote(_) => return,
+        Expr::Bind(bind) if !bind.dynamic => fun(&bind.name),
+        Expr::Block(block) => block.params.iter().for_each(|p| fun(&p.name)),
+        Expr::Destructure(destructure) => {
+            destructure.pattern.variables().iter().for_each(|v| fun(&v.name))
+        }
+        Expr::Match(m) => {
+            for arm in &m.arms {
+                arm.pattern.variables().iter().for_each(|v| fun(&v.name))
+            }
+        }
+        _ => (),
+    }
+    let _: Result<(), ()> = expr.for_each_subexpr_mut(&mut |subexpr| {
+        collect_template_bindings(subexpr, fun);
+        Ok(())
+    });
+}
+
 #[cfg(test)]
 pub mod utils {
 
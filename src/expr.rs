@@ -1,3 +1,6 @@
+use std::fmt;
+use std::rc::Rc;
+
 use crate::source_location::{SourceLocation, Span};
 use crate::syntax::Syntax;
 
@@ -27,9 +30,12 @@ pub enum Expr {
     Cascade(Cascade),
     Chain(Chain),
     Const(Const),
+    Destructure(Destructure),
     Dictionary(Dictionary),
     Eq(Eq),
+    Match(Match),
     Panic(Panic),
+    Quote(Quote),
     Return(Return),
     Seq(Seq),
     Typecheck(Typecheck),
@@ -110,9 +116,12 @@ impl Expr {
                 source
             }
             Const(constant) => constant.source_location.clone(),
+            Destructure(destructure) => destructure.source_location.clone(),
             Dictionary(dictionary) => dictionary.source_location.clone(),
             Eq(eq) => eq.source_location.clone(),
+            Match(m) => m.source_location.clone(),
             Panic(panic) => panic.source_location.clone(),
+            Quote(quote) => quote.source_location.clone(),
             Return(ret) => ret.source_location.clone(),
             Seq(seq) => {
                 let mut source_location = seq.exprs[0].source_location();
@@ -142,10 +151,13 @@ impl Expr {
             Cascade(cascade) => cascade.tweak_span(shift, extend),
             Chain(chain) => chain.tweak_span(shift, extend),
             Const(constant) => constant.tweak_span(shift, extend),
+            Destructure(destructure) => destructure.tweak_span(shift, extend),
             Dictionary(dictionary) => dictionary.tweak_span(shift, extend),
             Eq(eq) => eq.tweak_span(shift, extend),
+            Match(m) => m.tweak_span(shift, extend),
             Seq(seq) => seq.tweak_span(shift, extend),
             Panic(panic) => panic.tweak_span(shift, extend),
+            Quote(quote) => quote.tweak_span(shift, extend),
             Return(ret) => ret.tweak_span(shift, extend),
             Typecheck(typecheck) => typecheck.tweak_span(shift, extend),
             Var(var) => {
@@ -153,6 +165,135 @@ impl Expr {
             }
         };
     }
+
+    /// Calls fun on each immediate subexpression, stopping at the first error.
+    pub fn for_each_subexpr_mut<E>(
+        &mut self,
+        fun: &mut dyn FnMut(&mut Expr) -> Result<(), E>,
+    ) -> Result<(), E> {
+        use Expr::*;
+        match self {
+            Array(array) => {
+                for elt in &mut array.data {
+                    fun(elt)?;
+                }
+            }
+            Assign(assign) => fun(&mut assign.value)?,
+            Bind(bind) => {
+                fun(&mut bind.value)?;
+                if let Some(ref mut body) = bind.body {
+                    fun(body)?;
+                }
+            }
+            Block(block) => fun(&mut block.body)?,
+            Cascade(cascade) => {
+                fun(&mut cascade.receiver)?;
+                for chain in &mut cascade.chains {
+                    for message in chain {
+                        for arg in &mut message.args {
+                            fun(arg)?;
+                        }
+                    }
+                }
+            }
+            Chain(chain) => {
+                fun(&mut chain.receiver)?;
+                for message in &mut chain.messages {
+                    for arg in &mut message.args {
+                        fun(arg)?;
+                    }
+                }
+            }
+            Destructure(destructure) => {
+                fun(&mut destructure.value)?;
+                if let Some(ref mut body) = destructure.body {
+                    fun(body)?;
+                }
+            }
+            Dictionary(dictionary) => {
+                for (key, value) in &mut dictionary.assoc {
+                    fun(key)?;
+                    fun(value)?;
+                }
+            }
+            Eq(eq) => {
+                fun(&mut eq.left)?;
+                fun(&mut eq.right)?;
+            }
+            Match(m) => {
+                fun(&mut m.value)?;
+                for arm in &mut m.arms {
+                    if let Some(ref mut guard) = arm.guard {
+                        fun(guard)?;
+                    }
+                    fun(&mut arm.body)?;
+                }
+            }
+            Panic(panic) => fun(&mut panic.value)?,
+            Quote(quote) => fun(&mut quote.body)?,
+            Return(ret) => fun(&mut ret.value)?,
+            Seq(seq) => {
+                for expr in &mut seq.exprs {
+                    fun(expr)?;
+                }
+            }
+            Typecheck(typecheck) => fun(&mut typecheck.expr)?,
+            Const(_) | Var(_) => (),
+        }
+        Ok(())
+    }
+
+    /// Replaces all source locations in the expression with source_location.
+    pub fn relocate(&mut self, source_location: &SourceLocation) {
+        use Expr::*;
+        let set = |loc: &mut SourceLocation| *loc = source_location.clone();
+        match self {
+            Array(array) => set(&mut array.source_location),
+            Assign(assign) => set(&mut assign.source_location),
+            Bind(bind) => set(&mut bind.source_location),
+            Block(block) => {
+                set(&mut block.source_location);
+                for param in &mut block.params {
+                    set(&mut param.source_location);
+                }
+            }
+            Cascade(cascade) => {
+                for chain in &mut cascade.chains {
+                    for message in chain {
+                        set(&mut message.source_location);
+                    }
+                }
+            }
+            Chain(chain) => {
+                for message in &mut chain.messages {
+                    set(&mut message.source_location);
+                }
+            }
+            Const(constant) => set(&mut constant.source_location),
+            Destructure(destructure) => {
+                set(&mut destructure.source_location);
+                destructure.pattern.relocate(source_location);
+            }
+            Dictionary(dictionary) => set(&mut dictionary.source_location),
+            Eq(eq) => set(&mut eq.source_location),
+            Match(m) => {
+                set(&mut m.source_location);
+                for arm in &mut m.arms {
+                    arm.pattern.relocate(source_location);
+                }
+            }
+            Panic(panic) => set(&mut panic.source_location),
+            Quote(quote) => set(&mut quote.source_location),
+            Return(ret) => set(&mut ret.source_location),
+            Seq(_) => (),
+            Typecheck(typecheck) => set(&mut typecheck.source_location),
+            Var(var) => set(&mut var.source_location),
+        }
+        let _: Result<(), ()> = self.for_each_subexpr_mut(&mut |expr| {
+            expr.relocate(source_location);
+            Ok(())
+        });
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -181,6 +322,8 @@ pub struct Assign {
     pub source_location: SourceLocation,
     pub name: String,
     pub value: Box<Expr>,
+    /// Assigned by the resolver.
+    pub address: Option<Address>,
 }
 
 impl Assign {
@@ -189,6 +332,7 @@ impl Assign {
             source_location,
             name,
             value: Box::new(value),
+            address: None,
         })
     }
     fn tweak_span(&mut self, shift: usize, extend: isize) {
@@ -205,6 +349,10 @@ pub struct Bind {
     pub value: Box<Expr>,
     pub body: Option<Box<Expr>>,
     pub dynamic: bool,
+    /// Slot in the frame of the binding, assigned by the resolver.
+    pub slot: Option<usize>,
+    /// Slots of the frame opened by the binding, if any.
+    pub frame: Option<SlotNames>,
 }
 
 impl Bind {
@@ -223,6 +371,8 @@ impl Bind {
             value,
             body,
             dynamic,
+            slot: None,
+            frame: None,
         })
     }
     fn tweak_span(&mut self, shift: usize, extend: isize) {
@@ -240,6 +390,8 @@ pub struct Block {
     pub params: Vec<Var>,
     pub body: Box<Expr>,
     pub rtype: Option<String>,
+    /// Slots of the activation frame, assigned by the resolver.
+    pub frame: Option<SlotNames>,
 }
 
 impl Block {
@@ -254,6 +406,7 @@ impl Block {
             params,
             body,
             rtype,
+            frame: None,
         })
     }
     fn tweak_span(&mut self, shift: usize, extend: isize) {
@@ -336,6 +489,42 @@ impl Const {
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Destructure {
+    pub source_location: SourceLocation,
+    pub pattern: Pattern,
+    pub value: Box<Expr>,
+    pub body: Option<Box<Expr>>,
+    /// Slots of the frame opened for the variables, if any, assigned by
+    /// the resolver.
+    pub frame: Option<SlotNames>,
+}
+
+impl Destructure {
+    pub fn expr(
+        source_location: SourceLocation,
+        pattern: Pattern,
+        value: Box<Expr>,
+        body: Option<Box<Expr>>,
+    ) -> Expr {
+        Expr::Destructure(Destructure {
+            source_location,
+            pattern,
+            value,
+            body,
+            frame: None,
+        })
+    }
+    fn tweak_span(&mut self, shift: usize, extend: isize) {
+        self.source_location.tweak_span(shift, extend);
+        self.pattern.tweak_span(shift, extend);
+        self.value.tweak_span(shift, extend);
+        if let Some(ref mut expr) = self.body {
+            expr.tweak_span(shift, extend);
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Dictionary {
     pub source_location: SourceLocation,
@@ -376,6 +565,44 @@ impl Eq {
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Match {
+    pub source_location: SourceLocation,
+    pub value: Box<Expr>,
+    pub arms: Vec<MatchArm>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+    /// Slots of the frame opened for the variables, if any, assigned by
+    /// the resolver.
+    pub frame: Option<SlotNames>,
+}
+
+impl Match {
+    pub fn expr(source_location: SourceLocation, value: Box<Expr>, arms: Vec<MatchArm>) -> Expr {
+        Expr::Match(Match {
+            source_location,
+            value,
+            arms,
+        })
+    }
+    fn tweak_span(&mut self, shift: usize, extend: isize) {
+        self.source_location.tweak_span(shift, extend);
+        self.value.tweak_span(shift, extend);
+        for arm in &mut self.arms {
+            arm.pattern.tweak_span(shift, extend);
+            if let Some(ref mut guard) = arm.guard {
+                guard.tweak_span(shift, extend);
+            }
+            arm.body.tweak_span(shift, extend);
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Panic {
     pub source_location: SourceLocation,
@@ -395,6 +622,28 @@ impl Panic {
     }
 }
 
+/// Syntax template: evaluates to a Syntax object. Variables bound to Syntax
+/// objects are replaced by their syntax, and variables bound inside the
+/// template are renamed to avoid capturing names at the expansion site.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Quote {
+    pub source_location: SourceLocation,
+    pub body: Box<Expr>,
+}
+
+impl Quote {
+    pub fn expr(source_location: SourceLocation, body: Expr) -> Expr {
+        Expr::Quote(Quote {
+            source_location,
+            body: Box::new(body),
+        })
+    }
+    fn tweak_span(&mut self, shift: usize, extend: isize) {
+        self.source_location.tweak_span(shift, extend);
+        self.body.tweak_span(shift, extend);
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Return {
     pub source_location: SourceLocation,
@@ -453,12 +702,31 @@ impl Typecheck {
     }
 }
 
+/// Where a variable lives, assigned by the resolver.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Address {
+    /// Slot index in the activation frame depth levels up from the current
+    /// one.
+    Local {
+        depth: usize,
+        index: usize,
+    },
+    /// Not a local variable: looked up by name outside the activation frames.
+    Free,
+}
+
+/// Names of the slots of a frame by index, assigned by the resolver: the
+/// frames themselves hold only the bindings.
+pub type SlotNames = Rc<[String]>;
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Var {
     pub source_location: SourceLocation,
     pub name: String,
     pub typename: Option<String>,
     pub dynamic: bool,
+    /// Assigned by the resolver.
+    pub address: Option<Address>,
 }
 
 impl Var {
@@ -468,6 +736,7 @@ impl Var {
             name,
             typename: None,
             dynamic,
+            address: None,
         }
     }
     pub fn typed(
@@ -481,6 +750,166 @@ impl Var {
             name,
             typename: Some(typename),
             dynamic,
+            address: None,
+        }
+    }
+}
+
+/// Patterns used by destructuring let and match:.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    /// Matches anything: _
+    Wildcard(SourceLocation),
+    /// Matches anything, binding it to the variable. If the variable is typed,
+    /// matches only values of that type: x, x::Integer, _::Integer
+    Var(Var),
+    /// Matches values equal to the literal: 42, "foo", #bar
+    Literal(Const),
+    /// Matches records with at least the specified keys: {x: px, y: _}
+    Record(SourceLocation, Vec<(String, Pattern)>),
+    /// Matches arrays of the same length: [a, b, _]
+    Array(SourceLocation, Vec<Pattern>),
+}
+
+impl Pattern {
+    pub fn source_location(&self) -> SourceLocation {
+        match self {
+            Pattern::Wildcard(source_location) => source_location.clone(),
+            Pattern::Var(var) => var.source_location.clone(),
+            Pattern::Literal(constant) => constant.source_location.clone(),
+            Pattern::Record(source_location, _) => source_location.clone(),
+            Pattern::Array(source_location, _) => source_location.clone(),
+        }
+    }
+
+    /// Names of the variables bound by the pattern, in order of appearance.
+    pub fn variables(&self) -> Vec<&Var> {
+        let mut vars = vec![];
+        self.collect_variables(&mut vars);
+        vars
+    }
+
+    fn collect_variables<'a>(&'a self, vars: &mut Vec<&'a Var>) {
+        match self {
+            Pattern::Var(var) => {
+                if var.name != "_" {
+                    vars.push(var)
+                }
+            }
+            Pattern::Record(_, fields) => {
+                for (_, pattern) in fields {
+                    pattern.collect_variables(vars);
+                }
+            }
+            Pattern::Array(_, elements) => {
+                for pattern in elements {
+                    pattern.collect_variables(vars);
+                }
+            }
+            Pattern::Wildcard(_) | Pattern::Literal(_) => (),
+        }
+    }
+
+    /// Replaces all source locations in the pattern with source_location.
+    pub fn relocate(&mut self, source_location: &SourceLocation) {
+        match self {
+            Pattern::Wildcard(loc) => *loc = source_location.clone(),
+            Pattern::Var(var) => var.source_location = source_location.clone(),
+            Pattern::Literal(constant) => constant.source_location = source_location.clone(),
+            Pattern::Record(loc, fields) => {
+                *loc = source_location.clone();
+                for (_, pattern) in fields {
+                    pattern.relocate(source_location);
+                }
+            }
+            Pattern::Array(loc, elements) => {
+                *loc = source_location.clone();
+                for pattern in elements {
+                    pattern.relocate(source_location);
+                }
+            }
+        }
+    }
+
+    /// Calls fun on each variable bound by the pattern.
+    pub fn for_each_variable_mut(&mut self, fun: &mut dyn FnMut(&mut Var)) {
+        match self {
+            Pattern::Var(var) => {
+                if var.name != "_" {
+                    fun(var)
+                }
+            }
+            Pattern::Record(_, fields) => {
+                for (_, pattern) in fields {
+                    pattern.for_each_variable_mut(fun);
+                }
+            }
+            Pattern::Array(_, elements) => {
+                for pattern in elements {
+                    pattern.for_each_variable_mut(fun);
+                }
+            }
+            Pattern::Wildcard(_) | Pattern::Literal(_) => (),
+        }
+    }
+
+    fn tweak_span(&mut self, shift: usize, extend: isize) {
+        match self {
+            Pattern::Wildcard(source_location) => source_location.tweak_span(shift, extend),
+            Pattern::Var(var) => var.source_location.tweak_span(shift, extend),
+            Pattern::Literal(constant) => constant.tweak_span(shift, extend),
+            Pattern::Record(source_location, fields) => {
+                source_location.tweak_span(shift, extend);
+                for (_, pattern) in fields {
+                    pattern.tweak_span(shift, extend);
+                }
+            }
+            Pattern::Array(source_location, elements) => {
+                source_location.tweak_span(shift, extend);
+                for pattern in elements {
+                    pattern.tweak_span(shift, extend);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Wildcard(_) => write!(f, "_"),
+            Pattern::Var(var) => match &var.typename {
+                None => write!(f, "{}", var.name),
+                Some(typename) => write!(f, "{}::{}", var.name, typename),
+            },
+            Pattern::Literal(constant) => match &constant.literal {
+                Literal::Boolean(true) => write!(f, "True"),
+                Literal::Boolean(false) => write!(f, "False"),
+                Literal::Integer(i) => write!(f, "{}", i),
+                Literal::Float(x) => write!(f, "{:?}", x),
+                Literal::String(s) => write!(f, "{:?}", s),
+                Literal::Selector(s) => write!(f, "#{}", s),
+            },
+            Pattern::Record(_, fields) => {
+                write!(f, "{{")?;
+                for (i, (key, pattern)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, pattern)?;
+                }
+                write!(f, "}}")
+            }
+            Pattern::Array(_, elements) => {
+                write!(f, "[")?;
+                for (i, pattern) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", pattern)?;
+                }
+                write!(f, "]")
+            }
         }
     }
 }
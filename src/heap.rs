@@ -0,0 +1,557 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::mem::size_of;
+use std::rc::{Rc, Weak};
+
+use crate::classes::array::Array;
+use crate::classes::byte_array::ByteArray;
+use crate::classes::closure::Closure;
+use crate::classes::dictionary::Dictionary;
+use crate::classes::filestream::FileStream;
+use crate::classes::generator::Suspended;
+use crate::classes::record::Record;
+use crate::eval::{EnvFrame, EnvRef};
+use crate::objects::{Datum, Instance, Object, Vtable};
+
+/// Number of registered objects before the first automatic collection.
+const INITIAL_THRESHOLD: usize = 10_000;
+
+/// Object graph node seen by the collector: the objects in the registry which
+/// can refer to other objects, plus environment frames which are found through
+/// closures.
+enum Node {
+    Array(Rc<Array>),
+    Closure(Rc<Closure>),
+    Dictionary(Rc<Dictionary>),
+    FileStream(Rc<FileStream>),
+    Frame(Rc<RefCell<EnvFrame>>),
+    Instance(Rc<Instance>),
+    Record(Rc<Record>),
+}
+
+impl Node {
+    fn of_object(obj: &Object) -> Option<Node> {
+        match &obj.datum {
+            Datum::Array(x) => Some(Node::Array(x.clone())),
+            Datum::Closure(x) => Some(Node::Closure(x.clone())),
+            Datum::Dictionary(x) => Some(Node::Dictionary(x.clone())),
+            Datum::FileStream(x) => Some(Node::FileStream(x.clone())),
+            Datum::Instance(x) => Some(Node::Instance(x.clone())),
+            Datum::Record(x) => Some(Node::Record(x.clone())),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> usize {
+        match self {
+            Node::Array(x) => Rc::as_ptr(x) as *const () as usize,
+            Node::Closure(x) => Rc::as_ptr(x) as *const () as usize,
+            Node::Dictionary(x) => Rc::as_ptr(x) as *const () as usize,
+            Node::FileStream(x) => Rc::as_ptr(x) as *const () as usize,
+            Node::Frame(x) => Rc::as_ptr(x) as *const () as usize,
+            Node::Instance(x) => Rc::as_ptr(x) as *const () as usize,
+            Node::Record(x) => Rc::as_ptr(x) as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Array(x) => Rc::strong_count(x),
+            Node::Closure(x) => Rc::strong_count(x),
+            Node::Dictionary(x) => Rc::strong_count(x),
+            Node::FileStream(x) => Rc::strong_count(x),
+            Node::Frame(x) => Rc::strong_count(x),
+            Node::Instance(x) => Rc::strong_count(x),
+            Node::Record(x) => Rc::strong_count(x),
+        }
+    }
+
+    /// Calls fun with each node this one holds a strong reference to. Returns
+    /// false if the node is in use and could not be inspected.
+    fn for_each_child(&self, fun: &mut dyn FnMut(Node)) -> bool {
+        let mut object = |obj: &Object| {
+            if let Some(node) = Node::of_object(obj) {
+                fun(node)
+            }
+        };
+        match self {
+            Node::Array(array) => match array.data.try_borrow() {
+                Ok(data) => {
+                    data.iter().for_each(&mut object);
+                    array.etype.iter().for_each(&mut object);
+                }
+                Err(_) => return false,
+            },
+            Node::Closure(closure) => {
+                closure.signature.parameter_types.iter().flatten().for_each(&mut object);
+                closure.signature.return_type.iter().for_each(&mut object);
+                fun(Node::Frame(closure.env_ref.frame().clone()));
+            }
+            Node::Dictionary(dictionary) => match dictionary.try_borrow() {
+                Ok(data) => data.iter().for_each(|(k, v)| {
+                    object(k);
+                    object(v);
+                }),
+                Err(_) => return false,
+            },
+            Node::FileStream(_) => {}
+            Node::Frame(frame) => match frame.try_borrow() {
+                Ok(frame) => {
+                    let mut frames = vec![];
+                    frame.for_each_reference(&mut object, &mut |env_ref: &EnvRef| {
+                        frames.push(env_ref.frame().clone())
+                    });
+                    frames.into_iter().for_each(|frame| fun(Node::Frame(frame)));
+                }
+                Err(_) => return false,
+            },
+            Node::Instance(instance) => match instance.instance_variables.try_borrow() {
+                Ok(data) => data.iter().for_each(&mut object),
+                Err(_) => return false,
+            },
+            Node::Record(record) => match record.try_borrow() {
+                Ok(data) => data.values().for_each(&mut object),
+                Err(_) => return false,
+            },
+        }
+        true
+    }
+
+    /// Drops references held by the node, breaking any cycles through it.
+    fn clear(&self) {
+        // Values are dropped only after the borrows end, in case dropping
+        // them ends up here again.
+        match self {
+            Node::Array(array) => {
+                let _data = array.data.try_borrow_mut().map(|mut data| std::mem::take(&mut *data));
+            }
+            Node::Closure(_) => {
+                // Immutable: any cycle through a closure also goes through
+                // its environment.
+            }
+            Node::Dictionary(dictionary) => {
+                let _data = dictionary.try_borrow_mut().map(|mut data| std::mem::take(&mut *data));
+            }
+            Node::FileStream(stream) => {
+                // Not part of any cycle, but something in one refers to it:
+                // close it now instead of waiting for the last reference.
+                stream.close();
+            }
+            Node::Frame(frame) => {
+                let _refs = frame.try_borrow_mut().map(|mut frame| frame.clear());
+            }
+            Node::Instance(instance) => {
+                let _data = instance
+                    .instance_variables
+                    .try_borrow_mut()
+                    .map(|mut data| std::mem::take(&mut *data));
+            }
+            Node::Record(record) => {
+                let _data = record.try_borrow_mut().map(|mut data| std::mem::take(&mut *data));
+            }
+        }
+    }
+}
+
+/// Reference to an object which does not keep it alive. Objects without
+/// identity of their own, like integers, are held normally.
+pub enum WeakObject {
+    Strong(Object),
+    Weak(Rc<Vtable>, WeakDatum),
+}
+
+pub enum WeakDatum {
+    Array(Weak<Array>),
+    ByteArray(Weak<ByteArray>),
+    Closure(Weak<Closure>),
+    Dictionary(Weak<Dictionary>),
+    FileStream(Weak<FileStream>),
+    Instance(Weak<Instance>),
+    Record(Weak<Record>),
+    String(Weak<String>),
+}
+
+impl WeakDatum {
+    /// Name of the corresponding Datum variant.
+    fn kind(&self) -> &'static str {
+        match self {
+            WeakDatum::Array(_) => "Array",
+            WeakDatum::ByteArray(_) => "ByteArray",
+            WeakDatum::Closure(_) => "Closure",
+            WeakDatum::Dictionary(_) => "Dictionary",
+            WeakDatum::FileStream(_) => "FileStream",
+            WeakDatum::Instance(_) => "Instance",
+            WeakDatum::Record(_) => "Record",
+            WeakDatum::String(_) => "String",
+        }
+    }
+}
+
+impl WeakObject {
+    pub fn new(obj: &Object) -> WeakObject {
+        let vtable = match obj.heap_vtable() {
+            Some(vtable) => vtable.clone(),
+            None => return WeakObject::Strong(obj.clone()),
+        };
+        let datum = match &obj.datum {
+            Datum::Array(x) => WeakDatum::Array(Rc::downgrade(x)),
+            Datum::ByteArray(x) => WeakDatum::ByteArray(Rc::downgrade(x)),
+            Datum::Closure(x) => WeakDatum::Closure(Rc::downgrade(x)),
+            Datum::Dictionary(x) => WeakDatum::Dictionary(Rc::downgrade(x)),
+            Datum::FileStream(x) => WeakDatum::FileStream(Rc::downgrade(x)),
+            Datum::Instance(x) => WeakDatum::Instance(Rc::downgrade(x)),
+            Datum::Record(x) => WeakDatum::Record(Rc::downgrade(x)),
+            Datum::String(x) => WeakDatum::String(Rc::downgrade(x)),
+            _ => return WeakObject::Strong(obj.clone()),
+        };
+        WeakObject::Weak(vtable, datum)
+    }
+
+    /// True if the object can be collected while referenced only by this.
+    pub fn is_weak(&self) -> bool {
+        matches!(self, WeakObject::Weak(..))
+    }
+
+    pub fn is_alive(&self) -> bool {
+        match self {
+            WeakObject::Strong(_) => true,
+            WeakObject::Weak(_, datum) => match datum {
+                WeakDatum::Array(x) => x.strong_count() > 0,
+                WeakDatum::ByteArray(x) => x.strong_count() > 0,
+                WeakDatum::Closure(x) => x.strong_count() > 0,
+                WeakDatum::Dictionary(x) => x.strong_count() > 0,
+                WeakDatum::FileStream(x) => x.strong_count() > 0,
+                WeakDatum::Instance(x) => x.strong_count() > 0,
+                WeakDatum::Record(x) => x.strong_count() > 0,
+                WeakDatum::String(x) => x.strong_count() > 0,
+            },
+        }
+    }
+
+    pub fn upgrade(&self) -> Option<Object> {
+        let (vtable, datum) = match self {
+            WeakObject::Strong(obj) => return Some(obj.clone()),
+            WeakObject::Weak(vtable, datum) => (vtable, datum),
+        };
+        let datum = match datum {
+            WeakDatum::Array(x) => Datum::Array(x.upgrade()?),
+            WeakDatum::ByteArray(x) => Datum::ByteArray(x.upgrade()?),
+            WeakDatum::Closure(x) => Datum::Closure(x.upgrade()?),
+            WeakDatum::Dictionary(x) => Datum::Dictionary(x.upgrade()?),
+            WeakDatum::FileStream(x) => Datum::FileStream(x.upgrade()?),
+            WeakDatum::Instance(x) => Datum::Instance(x.upgrade()?),
+            WeakDatum::Record(x) => Datum::Record(x.upgrade()?),
+            WeakDatum::String(x) => Datum::String(x.upgrade()?),
+        };
+        Some(Object::new(vtable.clone(), datum))
+    }
+}
+
+/// Registry of heap allocated objects, and the cycle collector which reclaims
+/// those kept alive only by reference cycles.
+///
+/// The collector works by trial deletion: references between registered
+/// objects are counted, and objects whose reference count is higher than
+/// that are referenced from elsewhere: from the stack, globals, classes, etc.
+/// Everything reachable from those is live, and the rest is garbage kept alive
+/// only by cycles, which are broken by clearing the objects.
+///
+/// Only objects which can be part of a cycle are registered: strings, byte
+/// arrays and file streams cannot refer to other objects. Collections happen
+/// at safe points, between sends, once enough objects have been registered
+/// since the last one, or when the program asks for one.
+///
+/// Finalizers are actions to run when their object is gone, whether it was
+/// reclaimed by the collector or simply dropped. The collector notices this
+/// and queues the actions, which the interpreter runs at its next
+/// opportunity: never during the collection itself. Generators dropped while
+/// suspended are likewise queued for cancellation, since that runs their
+/// finally: blocks.
+pub struct Heap {
+    objects: RefCell<Vec<WeakObject>>,
+    threshold: Cell<usize>,
+    wants_collection: Cell<bool>,
+    collected: Cell<usize>,
+    allocations: RefCell<HashMap<&'static str, usize>>,
+    finalizers: RefCell<Vec<(WeakObject, Object)>>,
+    pending: RefCell<Vec<Object>>,
+    has_pending: Cell<bool>,
+    cancellations: RefCell<Vec<Suspended>>,
+}
+
+/// Census of registered objects, see Heap::stats.
+#[derive(Debug, Default)]
+pub struct HeapStats {
+    /// Live objects per Datum variant, of the kinds which are registered.
+    pub objects: BTreeMap<&'static str, usize>,
+    /// Live instances per class.
+    pub instances: BTreeMap<String, usize>,
+    /// Approximate size of the contents of arrays.
+    pub bytes: BTreeMap<&'static str, usize>,
+    /// Objects allocated since the previous census, per Datum variant.
+    pub allocations: BTreeMap<&'static str, usize>,
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Heap report:")?;
+        writeln!(f, "  {:<24} {:>10} {:>12} {:>10}", "Objects", "live", "bytes", "allocated")?;
+        let kinds: BTreeSet<&'static str> =
+            self.objects.keys().chain(self.allocations.keys()).copied().collect();
+        for kind in kinds {
+            let count = |map: &BTreeMap<&'static str, usize>| map.get(kind).copied().unwrap_or(0);
+            let bytes = match self.bytes.get(kind) {
+                Some(bytes) => bytes.to_string(),
+                None => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "  {:<24} {:>10} {:>12} {:>10}",
+                kind,
+                count(&self.objects),
+                bytes,
+                count(&self.allocations)
+            )?;
+        }
+        writeln!(f, "  {:<24} {:>10}", "Instances", "live")?;
+        for (class, count) in self.instances.iter() {
+            writeln!(f, "  {:<24} {:>10}", class, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for Heap {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Heap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<Heap {} objects>", self.objects.borrow().len())
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap {
+            objects: RefCell::new(Vec::new()),
+            threshold: Cell::new(INITIAL_THRESHOLD),
+            wants_collection: Cell::new(false),
+            collected: Cell::new(0),
+            allocations: RefCell::new(HashMap::new()),
+            finalizers: RefCell::new(Vec::new()),
+            pending: RefCell::new(Vec::new()),
+            has_pending: Cell::new(false),
+            cancellations: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Counts a newly allocated object, and adds it to the registry if it
+    /// can be part of a cycle. Never collects: see wants_collection.
+    pub fn register(&self, object: &Object) {
+        let (kind, cyclic) = match &object.datum {
+            Datum::Array(_) => ("Array", true),
+            Datum::ByteArray(_) => ("ByteArray", false),
+            Datum::Closure(_) => ("Closure", true),
+            Datum::Dictionary(_) => ("Dictionary", true),
+            Datum::FileStream(_) => ("FileStream", false),
+            Datum::Instance(_) => ("Instance", true),
+            Datum::Record(_) => ("Record", true),
+            Datum::String(_) => ("String", false),
+            _ => return,
+        };
+        *self.allocations.borrow_mut().entry(kind).or_insert(0) += 1;
+        if !cyclic {
+            return;
+        }
+        let mut objects = self.objects.borrow_mut();
+        objects.push(WeakObject::new(object));
+        if objects.len() > self.threshold.get() {
+            self.wants_collection.set(true);
+        }
+    }
+
+    /// True if enough objects have been registered since the last collection
+    /// that the next safe point should collect.
+    pub fn wants_collection(&self) -> bool {
+        self.wants_collection.get()
+    }
+
+    /// Registers action to be sent value after object is gone.
+    pub fn add_finalizer(&self, object: WeakObject, action: Object) {
+        self.finalizers.borrow_mut().push((object, action));
+    }
+
+    /// True if there are finalizers waiting to be run.
+    pub fn has_pending_finalizers(&self) -> bool {
+        self.has_pending.get()
+    }
+
+    /// Returns the finalizers waiting to be run, leaving none.
+    pub fn take_pending_finalizers(&self) -> Vec<Object> {
+        self.has_pending.set(false);
+        std::mem::take(&mut *self.pending.borrow_mut())
+    }
+
+    /// Queues a generator dropped while suspended to be cancelled.
+    pub fn queue_cancellation(&self, generator: Suspended) {
+        self.cancellations.borrow_mut().push(generator);
+    }
+
+    /// True if there are generators waiting to be cancelled.
+    pub fn has_pending_cancellations(&self) -> bool {
+        !self.cancellations.borrow().is_empty()
+    }
+
+    /// Cancels generators queued by queue_cancellation, including any
+    /// dropped by the cancelled ones as they unwind.
+    pub fn run_cancellations(&self) {
+        loop {
+            let cancellations = std::mem::take(&mut *self.cancellations.borrow_mut());
+            if cancellations.is_empty() {
+                break;
+            }
+            cancellations.into_iter().for_each(Suspended::cancel);
+        }
+    }
+
+    fn queue_finalizers(&self) {
+        let mut pending = self.pending.borrow_mut();
+        self.finalizers.borrow_mut().retain(|(object, action)| {
+            if object.is_alive() {
+                true
+            } else {
+                pending.push(action.clone());
+                false
+            }
+        });
+        self.has_pending.set(!pending.is_empty());
+    }
+
+    /// Counts live registered objects, and all objects allocated since the
+    /// previous call. Objects kept alive only by cycles count as live until
+    /// collected.
+    pub fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats::default();
+        for object in self.objects.borrow().iter() {
+            let (vtable, datum) = match object {
+                WeakObject::Weak(vtable, datum) if object.is_alive() => (vtable, datum),
+                _ => continue,
+            };
+            let kind = datum.kind();
+            *stats.objects.entry(kind).or_insert(0) += 1;
+            let bytes = match datum {
+                WeakDatum::Array(x) => x.upgrade().and_then(|array| {
+                    array.data.try_borrow().ok().map(|data| data.len() * size_of::<Object>())
+                }),
+                WeakDatum::Instance(_) => {
+                    *stats.instances.entry(vtable.name.clone()).or_insert(0) += 1;
+                    None
+                }
+                _ => None,
+            };
+            if let Some(bytes) = bytes {
+                *stats.bytes.entry(kind).or_insert(0) += bytes;
+            }
+        }
+        stats.allocations = self.allocations.borrow_mut().drain().collect();
+        stats
+    }
+
+    /// Live objects whose vtable is the given one.
+    pub fn all_instances(&self, vtable: &Rc<Vtable>) -> Vec<Object> {
+        self.objects
+            .borrow()
+            .iter()
+            .filter(|object| match object {
+                WeakObject::Weak(object_vtable, _) => Rc::ptr_eq(object_vtable, vtable),
+                WeakObject::Strong(_) => false,
+            })
+            .filter_map(WeakObject::upgrade)
+            .collect()
+    }
+
+    /// Total number of objects reclaimed by collections so far.
+    pub fn collected(&self) -> usize {
+        self.collected.get()
+    }
+
+    /// Reclaims unreachable cycles, returns the number of objects reclaimed.
+    /// Must only be called at a safe point, see Env::safe_point.
+    pub fn collect(&self) -> usize {
+        self.wants_collection.set(false);
+        // Each node is held once in nodes, on top of the references counted
+        // here.
+        let mut nodes: HashMap<usize, Node> = HashMap::new();
+        let mut internal: HashMap<usize, usize> = HashMap::new();
+        let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut roots = vec![];
+        let mut todo = vec![];
+        self.objects.borrow_mut().retain(|object| match object.upgrade() {
+            Some(obj) => {
+                todo.extend(Node::of_object(&obj));
+                true
+            }
+            None => false,
+        });
+        while let Some(node) = todo.pop() {
+            let id = node.id();
+            if nodes.contains_key(&id) {
+                continue;
+            }
+            let mut ids = vec![];
+            let inspected = node.for_each_child(&mut |child| {
+                let child_id = child.id();
+                *internal.entry(child_id).or_insert(0) += 1;
+                ids.push(child_id);
+                if !nodes.contains_key(&child_id) {
+                    todo.push(child);
+                }
+            });
+            if !inspected {
+                roots.push(id);
+            }
+            children.insert(id, ids);
+            nodes.insert(id, node);
+        }
+        for (id, node) in nodes.iter() {
+            if node.strong_count() > 1 + internal.get(id).copied().unwrap_or(0) {
+                roots.push(*id);
+            }
+        }
+        let mut live = HashSet::new();
+        while let Some(id) = roots.pop() {
+            if live.insert(id) {
+                roots.extend(children[&id].iter().copied());
+            }
+        }
+        let garbage: Vec<Node> =
+            nodes.drain().filter(|(id, _)| !live.contains(id)).map(|(_, node)| node).collect();
+        for node in garbage.iter() {
+            node.clear();
+        }
+        let count = garbage
+            .iter()
+            .filter(|node| !matches!(node, Node::FileStream(_) | Node::Frame(_)))
+            .count();
+        drop(garbage);
+        self.collected.set(self.collected.get() + count);
+        let size = {
+            let mut objects = self.objects.borrow_mut();
+            objects.retain(WeakObject::is_alive);
+            objects.len()
+        };
+        self.threshold.set(std::cmp::max(INITIAL_THRESHOLD, 2 * size));
+        self.queue_finalizers();
+        count
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::benchmark::{run_benchmarks, BenchmarkResult, Options};
+use crate::classes::process::ProcessState;
+use crate::coverage::Coverage;
+use crate::debugger::Debugger;
+use crate::eval::Env;
+use crate::objects::Foolang;
+use crate::test_runner::{run_tests, TestResult};
+use crate::trace::Filter;
+use crate::unwind::Unwind;
+
+/// An interpreter together with its heap. Isolates are not Send: see
+/// Detached for moving one to another thread.
+///
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<foolang::isolate::Isolate>();
+/// ```
+pub struct Isolate {
+    env: Env,
+}
+
+/// An isolate on its way to another thread, see Isolate::detach.
+///
+/// Objects are reference counted with Rc, which is fine as long as no Rc is
+/// shared with anything outside the isolate, so that all its reference counts
+/// move together. Every Rc reachable from an isolate is created by its own
+/// Foolang: the vtables, heap, modules, trace and coverage fields, and the
+/// objects and environments they refer to. What comes from the caller is
+/// owned and Send: the prelude path and roots, the program source and process
+/// state behind Arcs, and streams of Input and Output objects. Methods of
+/// Isolate return only owned data. The one exception is the debugger field,
+/// which holds a frontend supplied by the caller that need not be Send:
+/// isolates with a debugger cannot be detached.
+pub struct Detached {
+    isolate: Isolate,
+}
+
+// SAFETY: see above. Detached is only made by Isolate::detach, which checks
+// that there is no debugger.
+unsafe impl Send for Detached {}
+
+impl Detached {
+    /// The isolate, on the thread it was moved to.
+    pub fn attach(self) -> Isolate {
+        self.isolate
+    }
+}
+
+fn describe(unwind: Unwind) -> String {
+    format!("{}", unwind)
+}
+
+impl Isolate {
+    /// New interpreter with the prelude loaded.
+    pub fn new(prelude: &Path, roots: HashMap<String, PathBuf>) -> Result<Isolate, String> {
+        let foolang = Foolang::new(prelude, roots).map_err(describe)?;
+        Ok(Isolate {
+            env: foolang.toplevel_env(),
+        })
+    }
+
+    /// New interpreter recording coverage of code loaded from files, to be
+    /// written to report.
+    pub fn with_coverage(
+        prelude: &Path,
+        roots: HashMap<String, PathBuf>,
+        report: &Path,
+    ) -> Result<Isolate, String> {
+        let coverage = Rc::new(Coverage::new(report));
+        let foolang = Foolang::with_coverage(prelude, roots, coverage).map_err(describe)?;
+        Ok(Isolate {
+            env: foolang.toplevel_env(),
+        })
+    }
+
+    /// New interpreter which runs its program under debugger.
+    pub fn with_debugger(
+        prelude: &Path,
+        roots: HashMap<String, PathBuf>,
+        debugger: Debugger,
+    ) -> Result<Isolate, String> {
+        let foolang =
+            Foolang::with_debugger(prelude, roots, Rc::new(debugger)).map_err(describe)?;
+        Ok(Isolate {
+            env: foolang.toplevel_env(),
+        })
+    }
+
+    /// Prepares the isolate for moving to another thread. Fails if it has a
+    /// debugger, see Detached.
+    pub fn detach(self) -> Result<Detached, String> {
+        if self.env.foo.debugger.is_some() {
+            return Err("Cannot move an isolate with a debugger to another thread".to_string());
+        }
+        Ok(Detached {
+            isolate: self,
+        })
+    }
+
+    /// New interpreter for process, with the prelude and program loaded.
+    pub fn load(
+        prelude: &Path,
+        roots: HashMap<String, PathBuf>,
+        program: Arc<String>,
+        process: Arc<ProcessState>,
+    ) -> Result<Isolate, String> {
+        let foolang = Foolang::new(prelude, roots).map_err(describe)?.in_process(program, process);
+        let env = foolang.load_program().map_err(describe)?;
+        Ok(Isolate {
+            env,
+        })
+    }
+
+    /// Evaluates source and returns the result as a string.
+    pub fn eval(&self, source: &str) -> Result<String, String> {
+        let value = self.env.eval_all(source).map_err(describe)?;
+        let string = value.send("toString", &[], &self.env).map_err(describe)?;
+        Ok(string.as_str().map_err(describe)?.to_string())
+    }
+
+    /// Runs Main with the given command line arguments.
+    pub fn run(&self, program: &str, args: Vec<String>) -> Result<(), String> {
+        self.run_main(program, args, None)
+    }
+
+    /// Like run, but the output of Main goes to output instead of stdout.
+    pub fn run_with_output(
+        &self,
+        program: &str,
+        args: Vec<String>,
+        output: Box<dyn Write + Send>,
+    ) -> Result<(), String> {
+        self.run_main(program, args, Some(output))
+    }
+
+    fn run_main(
+        &self,
+        program: &str,
+        args: Vec<String>,
+        output: Option<Box<dyn Write + Send>>,
+    ) -> Result<(), String> {
+        let foolang = (*self.env.foo).clone();
+        let command = foolang.into_array(
+            args.iter().map(|arg| foolang.make_string(arg)).collect(),
+            foolang.toplevel_env().find_global("String"),
+        );
+        let output = output.map(|output| foolang.make_output("output", output));
+        foolang.run_with_output(program, command, output).map_err(describe)?;
+        Ok(())
+    }
+
+    /// Runs the test suites in files, see test_runner::run_tests.
+    pub fn test(&self, files: &[PathBuf], filters: &[String]) -> Vec<TestResult> {
+        run_tests(&self.env, files, filters)
+    }
+
+    /// Runs the benchmark suites in files, see benchmark::run_benchmarks.
+    pub fn benchmark(
+        &self,
+        files: &[PathBuf],
+        options: &Options,
+    ) -> Result<Vec<BenchmarkResult>, String> {
+        run_benchmarks(&self.env, files, options).map_err(describe)
+    }
+
+    /// Census of the heap, see Heap::stats.
+    pub fn heap_report(&self) -> String {
+        format!("{}", self.env.foo.heap.stats())
+    }
+
+    /// Writes the coverage report, if recording coverage.
+    pub fn write_coverage(&self) -> Result<(), String> {
+        match &self.env.foo.coverage {
+            Some(coverage) => coverage.write_report().map_err(|err| err.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts tracing sends matching filters, see Filter::parse.
+    pub fn trace(&self, filters: &[String]) -> Result<(), String> {
+        self.env.foo.trace.enable(Filter::parse(filters)?);
+        Ok(())
+    }
+
+    /// Runs class in the process the isolate was loaded for.
+    pub fn run_process(self, class: &str) -> Result<(), String> {
+        self.env.foo.run_process(&self.env, class).map_err(describe)?;
+        Ok(())
+    }
+}
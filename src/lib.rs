@@ -1,13 +1,23 @@
+pub mod benchmark;
 pub mod classes;
+pub mod coverage;
+pub mod debugger;
 pub mod def;
+pub mod dump;
 pub mod eval;
 pub mod expr;
+pub mod heap;
+pub mod isolate;
 pub mod objects;
 pub mod parse;
+pub mod pretty;
+pub mod resolve;
 pub mod source_location;
 pub mod syntax;
+pub mod test_runner;
 pub mod time;
 pub mod tokenstream;
+pub mod trace;
 pub mod unwind;
 
 #[cfg(test)]
@@ -1,7 +1,12 @@
-use clap::{App, Arg};
-use foolang::objects::Foolang;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use foolang::debugger::{Console, Debugger};
+use foolang::dump;
+use foolang::isolate::Isolate;
+use foolang::pretty;
+use foolang::test_runner;
 use foolang::time::TimeInfo;
 use std::collections::HashMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 fn oops<T: std::fmt::Display>(what: T) -> ! {
@@ -64,7 +69,48 @@ fn foo_main() {
                 .value_name("MODULE")
                 .help("Path to a module to use.")
                 .takes_value(true)
-                .multiple(true),
+                .multiple(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("heap-report")
+                .long("heap-report")
+                .help("Print a census of the heap to stderr on exit."),
+        )
+        .arg(
+            Arg::with_name("coverage")
+                .long("coverage")
+                .value_name("FILE")
+                .help("Write an lcov report of the Foolang code run to FILE on exit.")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .value_name("FILTER")
+                .help(
+                    "Trace sends to stderr. FILTER is selector=GLOB, class=GLOB or module=GLOB, \
+                     and can be given multiple times to narrow down the sends traced.",
+                )
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .multiple(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("debug")
+                .long("debug")
+                .help("Run the program under the debugger, reading commands from stdin."),
+        )
+        .arg(
+            Arg::with_name("debug-script")
+                .long("debug-script")
+                .value_name("FILE")
+                .help("Run the program under the debugger, reading commands from FILE.")
+                .takes_value(true)
+                .conflicts_with("debug"),
         )
         .arg(
             Arg::with_name("prelude")
@@ -73,9 +119,61 @@ fn foo_main() {
                 .help("Override standard prelude.")
                 .takes_value(true)
                 .default_value("foo/lang/bootstrap_prelude.foo")
-                .multiple(false),
+                .multiple(false)
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Runs the TestSuite classes in test_*.foo modules.")
+                .arg(
+                    Arg::with_name("path")
+                        .index(1)
+                        .value_name("PATH")
+                        .help("Test module, or directory to search for test_*.foo modules.")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("filter")
+                        .long("filter")
+                        .value_name("NAME")
+                        .help("Run only tests whose Suite.selector name contains NAME.")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Format of the report.")
+                        .takes_value(true)
+                        .possible_values(&["tap", "junit"])
+                        .default_value("tap"),
+                )
+                .arg(
+                    Arg::with_name("report")
+                        .long("report")
+                        .value_name("FILE")
+                        .help("Write the report to FILE instead of stdout.")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(dump_command("dump-tokens", "Prints the tokens of FILE with their spans."))
+        .subcommand(
+            dump_command(
+                "dump-ast",
+                "Prints the syntax tree of FILE with spans, without evaluating it.",
+            )
+            .arg(
+                Arg::with_name("source")
+                    .long("source")
+                    .help("Print the syntax tree back as Foolang source.")
+                    .conflicts_with("json"),
+            ),
         );
-    let matches = app.clone().get_matches();
+    let toplevel = app.clone().get_matches();
+    let matches = toplevel.subcommand_matches("test").unwrap_or(&toplevel);
     let prelude = Path::new(matches.value_of("prelude").unwrap());
     let mut module_roots: HashMap<String, PathBuf> = HashMap::new();
     if let Some(values) = matches.values_of("use") {
@@ -88,6 +186,15 @@ fn foo_main() {
         }
     }
     module_roots.insert(".".to_string(), std::env::current_dir().unwrap());
+    if toplevel.subcommand_matches("test").is_some() {
+        test_main(matches, prelude, module_roots)
+    }
+    if let Some(matches) = toplevel.subcommand_matches("dump-tokens") {
+        dump_tokens_main(matches)
+    }
+    if let Some(matches) = toplevel.subcommand_matches("dump-ast") {
+        dump_ast_main(matches)
+    }
     if let Some(fname) = matches.value_of("program") {
         let (_, root) = find_module_or_abort(fname);
         module_roots.insert(".".to_string(), root);
@@ -99,20 +206,170 @@ fn foo_main() {
                 std::process::exit(1)
             }
         };
-        let foo = match Foolang::new(prelude, module_roots) {
-            Ok(foo) => foo,
+        let isolate = match debugger(matches, fname) {
+            Some(debugger) => Isolate::with_debugger(prelude, module_roots, debugger),
+            None => new_isolate(matches, prelude, module_roots),
+        };
+        let isolate = match isolate {
+            Ok(isolate) => isolate,
             Err(err) => oops(err),
         };
-        let command = foo.into_array(
-            matches
-                .values_of("arg")
-                .map_or(vec![], |args| args.map(|arg| foo.make_string(arg)).collect()),
-            foo.toplevel_env().find_global("String"),
-        );
+        trace(matches, &isolate);
+        let args = matches
+            .values_of("arg")
+            .map_or(vec![], |args| args.map(|arg| arg.to_string()).collect());
         // FIXME: pass in env and argv to run
-        match foo.run(&program, command) {
+        let res = isolate.run(&program, args);
+        if matches.is_present("heap-report") {
+            eprint!("{}", isolate.heap_report());
+        }
+        write_coverage(&isolate);
+        match res {
             Ok(_) => std::process::exit(0),
             Err(err) => oops(err),
         }
     }
 }
+
+fn new_isolate(
+    matches: &ArgMatches,
+    prelude: &Path,
+    module_roots: HashMap<String, PathBuf>,
+) -> Result<Isolate, String> {
+    match matches.value_of("coverage") {
+        Some(report) => Isolate::with_coverage(prelude, module_roots, Path::new(report)),
+        None => Isolate::new(prelude, module_roots),
+    }
+}
+
+fn debugger(matches: &ArgMatches, program: &str) -> Option<Debugger> {
+    let console = if let Some(script) = matches.value_of("debug-script") {
+        match std::fs::File::open(script) {
+            Ok(file) => Console::new(Box::new(std::io::BufReader::new(file)), true),
+            Err(err) => oops(format!("cannot open debugger script '{}': {}", script, err)),
+        }
+    } else if matches.is_present("debug") {
+        Console::new(Box::new(std::io::BufReader::new(std::io::stdin())), false)
+    } else {
+        return None;
+    };
+    Some(Debugger::new(Path::new(program), Box::new(console)))
+}
+
+/// Starts tracing if asked to.
+fn trace(matches: &ArgMatches, isolate: &Isolate) {
+    if matches.is_present("trace") {
+        let filters: Vec<String> = matches
+            .values_of("trace")
+            .map_or(vec![], |filters| filters.map(String::from).collect());
+        if let Err(err) = isolate.trace(&filters) {
+            oops(err)
+        }
+    }
+}
+
+fn write_coverage(isolate: &Isolate) {
+    if let Err(err) = isolate.write_coverage() {
+        oops(format!("cannot write coverage report: {}", err))
+    }
+}
+
+fn test_main(
+    matches: &ArgMatches,
+    prelude: &Path,
+    mut module_roots: HashMap<String, PathBuf>,
+) -> ! {
+    // Suites are run with lib.assert.Assert: default to the lib next to the
+    // prelude.
+    if let Some(root) = prelude.parent().and_then(|lang| lang.parent()) {
+        module_roots.entry("lib".to_string()).or_insert_with(|| root.to_path_buf());
+    }
+    let paths: Vec<PathBuf> = matches.values_of("path").unwrap().map(PathBuf::from).collect();
+    let files = match test_runner::discover(&paths) {
+        Ok(files) => files,
+        Err(err) => oops(err),
+    };
+    let filters: Vec<String> =
+        matches.values_of("filter").map_or(vec![], |filters| filters.map(String::from).collect());
+    let isolate = match new_isolate(matches, prelude, module_roots) {
+        Ok(isolate) => isolate,
+        Err(err) => oops(err),
+    };
+    trace(matches, &isolate);
+    let results = isolate.test(&files, &filters);
+    write_coverage(&isolate);
+    let mut report: Box<dyn Write> = match matches.value_of("report") {
+        Some(file) => match std::fs::File::create(file) {
+            Ok(file) => Box::new(file),
+            Err(err) => oops(format!("cannot create report '{}': {}", file, err)),
+        },
+        None => Box::new(std::io::stdout()),
+    };
+    let res = match matches.value_of("format") {
+        Some("junit") => test_runner::write_junit(&results, &mut report),
+        _ => test_runner::write_tap(&results, &mut report),
+    };
+    if let Err(err) = res.and_then(|_| report.flush()) {
+        oops(err)
+    }
+    if results.iter().all(|result| result.passed()) {
+        std::process::exit(0)
+    } else {
+        std::process::exit(1)
+    }
+}
+
+fn dump_command<'a, 'b>(name: &'a str, about: &'a str) -> App<'a, 'b> {
+    SubCommand::with_name(name)
+        .about(about)
+        .arg(
+            Arg::with_name("file")
+                .index(1)
+                .value_name("FILE")
+                .help("Foolang source file.")
+                .required(true),
+        )
+        .arg(Arg::with_name("json").long("json").help("Print JSON instead of text."))
+}
+
+fn print_dump(matches: &ArgMatches, json: serde_json::Value, text: String) -> ! {
+    if matches.is_present("json") {
+        match serde_json::to_string_pretty(&json) {
+            Ok(json) => println!("{}", json),
+            Err(err) => oops(err),
+        }
+    } else {
+        print!("{}", text);
+    }
+    std::process::exit(0)
+}
+
+fn dump_tokens_main(matches: &ArgMatches) -> ! {
+    let file = matches.value_of("file").unwrap();
+    let source = match std::fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(err) => oops(format!("cannot read '{}': {}", file, err)),
+    };
+    let tokens = match dump::tokens(&source) {
+        Ok(tokens) => tokens,
+        Err(err) => oops(err),
+    };
+    print_dump(matches, dump::tokens_json(&source, &tokens), dump::tokens_text(&source, &tokens))
+}
+
+fn dump_ast_main(matches: &ArgMatches) -> ! {
+    let file = Path::new(matches.value_of("file").unwrap());
+    let syntax = match dump::syntax(file) {
+        Ok(syntax) => syntax,
+        Err(err) => oops(err),
+    };
+    if matches.is_present("source") {
+        let root = file.parent().unwrap_or_else(|| Path::new("."));
+        print!("{}", pretty::module_source(&syntax, root));
+        std::process::exit(0)
+    }
+    let nodes: Vec<dump::Node> = syntax.iter().map(dump::syntax_node).collect();
+    let json = serde_json::Value::Array(nodes.iter().map(dump::Node::to_json).collect());
+    let text: String = nodes.iter().map(dump::Node::to_text).collect();
+    print_dump(matches, json, text)
+}
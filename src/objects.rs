@@ -1,5 +1,5 @@
 use std::borrow::Borrow;
-use std::cell::{Ref, RefCell, RefMut};
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::cmp::Eq;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
@@ -9,13 +9,19 @@ use std::io::Read;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Arc;
 
+use crate::coverage::Coverage;
+use crate::debugger::Debugger;
 use crate::def::*;
-use crate::eval::{Binding, Env, EnvRef};
+use crate::eval::{Binding, Env, EnvRef, Tail};
 use crate::expr::*;
+use crate::heap::Heap;
+use crate::resolve::resolve;
 
 use crate::source_location::SourceLocation;
 use crate::time::TimeInfo;
+use crate::trace::Trace;
 use crate::unwind::Unwind;
 
 use crate::classes;
@@ -94,13 +100,13 @@ impl fmt::Display for Signature {
                 write!(f, ", ")?;
             }
             match t {
-                Some(t) => write!(f, "{}", &t.vtable.name)?,
+                Some(t) => write!(f, "{}", t.vtable_name())?,
                 None => write!(f, "Any")?,
             }
         }
         write!(f, ") -> ")?;
         match &self.return_type {
-            Some(t) => write!(f, "{}", &t.vtable.name)?,
+            Some(t) => write!(f, "{}", t.vtable_name())?,
             None => write!(f, "Any")?,
         }
         Ok(())
@@ -116,6 +122,7 @@ pub enum Method {
     // of required signature.
     Required(Signature),
     Object(Object),
+    Wrapped(Rc<classes::wrapper::Wrapper>),
 }
 
 impl PartialEq for Method {
@@ -135,10 +142,11 @@ impl Method {
     pub fn object(method: &Object) -> Method {
         Method::Object(method.clone())
     }
-    fn closure(method: &MethodDefinition, env: &Env) -> Result<Method, Unwind> {
+    /// Method named like Class#selector, or Class##selector for class methods.
+    fn closure(name: &str, method: &MethodDefinition, env: &Env) -> Result<Method, Unwind> {
         Ok(Method::Interpreter(Rc::new(make_method_closure(
             env,
-            &method.selector,
+            name,
             &method.parameters,
             method.required_body()?,
             &method.return_type,
@@ -161,6 +169,7 @@ impl Method {
             Method::Primitive(_) => Unwind::error("Primitive method has no signature"),
             Method::Reader(_) => Unwind::error("Reader method has no signature"),
             Method::Object(_) => Unwind::error("Object method signature fetching not implemented."),
+            Method::Wrapped(w) => w.original.signature(),
         }
     }
 }
@@ -212,11 +221,17 @@ impl Vtable {
 
     pub fn for_class(name: &str) -> Vtable {
         let vt = Vtable::raw(&format!("{} classOf", name));
+        vt.add_primitive_method_or_panic(
+            "allInstances",
+            classes::class::generic_class_all_instances,
+        );
         vt.add_primitive_method_or_panic("classOf", classes::class::generic_class_class);
         vt.add_primitive_method_or_panic("includes:", classes::class::generic_class_includes_);
         vt.add_primitive_method_or_panic("typecheck:", classes::class::generic_class_typecheck_);
         vt.add_primitive_method_or_panic("name", classes::class::generic_class_name);
         vt.add_primitive_method_or_panic("interfaces", classes::class::generic_class_interfaces);
+        vt.add_primitive_method_or_panic("wrap:with:", classes::wrapper::generic_class_wrap_with_);
+        vt.add_primitive_method_or_panic("unwrap:", classes::wrapper::generic_class_unwrap_);
         vt.add_primitive_method_or_panic(
             "__addDirectMethod:",
             classes::class::generic_class_add_direct_method_,
@@ -322,9 +337,13 @@ impl Hash for Vtable {
     }
 }
 
+/// Immediates (integers, floats, and booleans) are stored unboxed in the
+/// datum without a vtable: theirs is looked up from the tag of the datum, see
+/// Object::vtable. Creating and copying them does not touch any reference
+/// counts.
 #[derive(PartialEq, Clone, Eq, Hash)]
 pub struct Object {
-    pub vtable: Rc<Vtable>,
+    vtable: Option<Rc<Vtable>>,
     pub datum: Datum,
 }
 
@@ -363,7 +382,7 @@ impl Arg {
 
 pub struct Input {
     pub name: String,
-    stream: RefCell<Box<dyn Read>>,
+    stream: RefCell<Box<dyn Read + Send>>,
     buffer: RefCell<Vec<u8>>,
 }
 
@@ -419,11 +438,18 @@ impl Input {
 
 pub struct Instance {
     pub instance_variables: RefCell<Vec<Object>>,
+    /// Instances of value classes are immutable, and compare and hash by
+    /// their contents instead of their identity.
+    pub value: bool,
 }
 
 impl PartialEq for Instance {
     fn eq(&self, other: &Self) -> bool {
-        std::ptr::eq(self, other)
+        if self.value && other.value {
+            *self.instance_variables.borrow() == *other.instance_variables.borrow()
+        } else {
+            std::ptr::eq(self, other)
+        }
     }
 }
 
@@ -431,13 +457,17 @@ impl Eq for Instance {}
 
 impl Hash for Instance {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        std::ptr::hash(self, state);
+        if self.value {
+            self.instance_variables.borrow().hash(state);
+        } else {
+            std::ptr::hash(self, state);
+        }
     }
 }
 
 pub struct Output {
     pub name: String,
-    stream: RefCell<Box<dyn Write>>,
+    stream: RefCell<Box<dyn Write + Send>>,
 }
 
 impl PartialEq for Output {
@@ -486,20 +516,36 @@ pub enum Datum {
     FilePath(Rc<classes::filepath::FilePath>),
     FileStream(Rc<classes::filestream::FileStream>),
     Float(f64),
+    Gen(Rc<classes::property::Gen>),
+    Generator(Rc<classes::generator::Generator>),
     Input(Rc<Input>),
     Instance(Rc<Instance>),
     Integer(i64),
+    Macro(Rc<classes::syntax::Macro>),
+    Original(Rc<classes::wrapper::Original>),
     Output(Rc<Output>),
+    Process(Arc<classes::process::ProcessState>),
     Random(Rc<classes::random::Random>),
     Record(Rc<classes::record::Record>),
     String(Rc<String>),
+    Supervisor(Rc<classes::process::Supervisor>),
+    Syntax(Rc<classes::syntax::Syntax>),
     // XXX: Null?
     System(Rc<System>),
     Time(Rc<TimeInfo>),
+    WeakRef(Rc<classes::weakref::WeakRef>),
+    Yielder(Rc<classes::generator::Yielder>),
 }
 
 impl Eq for Datum {}
 
+impl Datum {
+    /// True for data stored unboxed in an Object, see Object::vtable.
+    pub fn is_immediate(&self) -> bool {
+        matches!(self, Datum::Boolean(_) | Datum::Float(_) | Datum::Integer(_))
+    }
+}
+
 impl Hash for Datum {
     fn hash<H: Hasher>(&self, state: &mut H) {
         use Datum::*;
@@ -516,16 +562,25 @@ impl Hash for Datum {
             FilePath(x) => x.hash(state),
             FileStream(x) => x.hash(state),
             Float(x) => x.to_bits().hash(state),
+            Gen(x) => x.hash(state),
+            Generator(x) => x.hash(state),
             Input(x) => x.hash(state),
             Instance(x) => x.hash(state),
             Integer(x) => x.hash(state),
+            Macro(x) => x.hash(state),
+            Original(x) => x.hash(state),
             Output(x) => x.hash(state),
+            Process(x) => x.hash(state),
             Random(x) => x.hash(state),
             Record(x) => x.hash(state),
             String(x) => x.hash(state),
+            Supervisor(x) => x.hash(state),
+            Syntax(x) => x.hash(state),
             // XXX: Null?
             System(x) => x.hash(state),
             Time(x) => x.hash(state),
+            WeakRef(x) => x.hash(state),
+            Yielder(x) => x.hash(state),
         }
     }
 }
@@ -553,8 +608,14 @@ pub struct Foolang {
     pub filepath_vtable: Rc<Vtable>,
     pub filestream_class_vtable: Rc<Vtable>,
     pub filestream_vtable: Rc<Vtable>,
+    pub finalizer_class_vtable: Rc<Vtable>,
+    pub finalizer_vtable: Rc<Vtable>,
     pub float_class_vtable: Rc<Vtable>,
     pub float_vtable: Rc<Vtable>,
+    pub gen_class_vtable: Rc<Vtable>,
+    pub gen_vtable: Rc<Vtable>,
+    pub generator_class_vtable: Rc<Vtable>,
+    pub generator_vtable: Rc<Vtable>,
     pub input_class_vtable: Rc<Vtable>,
     pub input_vtable: Rc<Vtable>,
     pub integer_class_vtable: Rc<Vtable>,
@@ -562,20 +623,54 @@ pub struct Foolang {
     pub interface_vtable: Rc<Vtable>,
     pub output_class_vtable: Rc<Vtable>,
     pub output_vtable: Rc<Vtable>,
+    pub process_class_vtable: Rc<Vtable>,
+    pub process_vtable: Rc<Vtable>,
+    pub property_class_vtable: Rc<Vtable>,
+    pub property_vtable: Rc<Vtable>,
     pub random_class_vtable: Rc<Vtable>,
     pub random_vtable: Rc<Vtable>,
     pub record_class_vtable: Rc<Vtable>,
     pub record_vtable: Rc<Vtable>,
+    pub macro_vtable: Rc<Vtable>,
+    pub original_vtable: Rc<Vtable>,
     pub string_class_vtable: Rc<Vtable>,
     pub string_vtable: Rc<Vtable>,
+    pub supervisor_class_vtable: Rc<Vtable>,
+    pub supervisor_vtable: Rc<Vtable>,
+    pub syntax_class_vtable: Rc<Vtable>,
+    pub syntax_vtable: Rc<Vtable>,
     pub time_class_vtable: Rc<Vtable>,
     pub time_vtable: Rc<Vtable>,
+    pub weakref_class_vtable: Rc<Vtable>,
+    pub weakref_vtable: Rc<Vtable>,
+    pub yielder_vtable: Rc<Vtable>,
     /// Holds the toplevel builtin environment, including prelude.
     builtin_env_ref: EnvRef,
     /// Used to ensure we load each module only once.
     pub modules: Rc<RefCell<HashMap<PathBuf, Env>>>,
     /// Map from toplevel module names to their paths
     pub roots: HashMap<String, PathBuf>,
+    /// Prelude loaded into the builtin environment.
+    pub prelude: PathBuf,
+    /// Allocated objects, and the cycle collector.
+    pub heap: Rc<Heap>,
+    /// Source of the program being run, so that new processes can load it.
+    pub program: Option<Arc<String>>,
+    /// Mailbox of the process this interpreter is running.
+    pub process: Arc<classes::process::ProcessState>,
+    /// Records evaluated expressions when running with --coverage.
+    pub coverage: Option<Rc<Coverage>>,
+    /// Pauses at breakpoints when running with --debug.
+    pub debugger: Option<Rc<Debugger>>,
+    /// Traces sends when running with --trace, or after System#trace:.
+    pub trace: Rc<Trace>,
+    /// Source location of the macro call being expanded, if any.
+    expansion_site: RefCell<Option<SourceLocation>>,
+    /// Used to generate fresh names for macro hygiene.
+    gensym_counter: Cell<usize>,
+    /// Set when the first macro is defined: until then there is nothing
+    /// to expand.
+    has_macros: Cell<bool>,
 }
 
 impl Foolang {
@@ -603,20 +698,65 @@ impl Foolang {
             "FileStream",
             Class::object(&self.filestream_class_vtable, &self.filestream_vtable),
         );
+        env.define(
+            "Finalizer",
+            Class::object(&self.finalizer_class_vtable, &self.finalizer_vtable),
+        );
         env.define("Float", Class::object(&self.float_class_vtable, &self.float_vtable));
+        env.define("Gen", Class::object(&self.gen_class_vtable, &self.gen_vtable));
+        env.define(
+            "Generator",
+            Class::object(&self.generator_class_vtable, &self.generator_vtable),
+        );
         env.define("Input", Class::object(&self.input_class_vtable, &self.input_vtable));
         env.define("Integer", Class::object(&self.integer_class_vtable, &self.integer_vtable));
         env.define("Interface", Class::object(&self.interface_vtable, &self.interface_vtable));
         env.define("Output", Class::object(&self.output_class_vtable, &self.output_vtable));
+        env.define("Process", Class::object(&self.process_class_vtable, &self.process_vtable));
+        env.define("Property", Class::object(&self.property_class_vtable, &self.property_vtable));
         env.define("Random", Class::object(&self.random_class_vtable, &self.random_vtable));
         env.define("Record", Class::object(&self.record_class_vtable, &self.record_vtable));
         env.define("String", Class::object(&self.string_class_vtable, &self.string_vtable));
+        env.define(
+            "Supervisor",
+            Class::object(&self.supervisor_class_vtable, &self.supervisor_vtable),
+        );
+        env.define("Syntax", Class::object(&self.syntax_class_vtable, &self.syntax_vtable));
         env.define("Time", Class::object(&self.time_class_vtable, &self.time_vtable));
+        env.define("WeakRef", Class::object(&self.weakref_class_vtable, &self.weakref_vtable));
         // println!("INIT OK");
         self
     }
 
     pub fn new(prelude: &Path, roots: HashMap<String, PathBuf>) -> Result<Foolang, Unwind> {
+        Foolang::bare(prelude, roots).init_builtins().load_prelude(prelude)
+    }
+
+    /// Like new, but records coverage of everything loaded from files,
+    /// starting with the prelude.
+    pub fn with_coverage(
+        prelude: &Path,
+        roots: HashMap<String, PathBuf>,
+        coverage: Rc<Coverage>,
+    ) -> Result<Foolang, Unwind> {
+        let mut foolang = Foolang::bare(prelude, roots);
+        foolang.coverage = Some(coverage);
+        foolang.init_builtins().load_prelude(prelude)
+    }
+
+    /// Like new, but reports activations and evaluated expressions to
+    /// debugger once the prelude has been loaded.
+    pub fn with_debugger(
+        prelude: &Path,
+        roots: HashMap<String, PathBuf>,
+        debugger: Rc<Debugger>,
+    ) -> Result<Foolang, Unwind> {
+        let mut foolang = Foolang::new(prelude, roots)?;
+        foolang.debugger = Some(debugger);
+        Ok(foolang)
+    }
+
+    fn bare(prelude: &Path, roots: HashMap<String, PathBuf>) -> Foolang {
         Foolang {
             array_class_vtable: Rc::new(classes::array::class_vtable()),
             array_vtable: Rc::new(classes::array::instance_vtable()),
@@ -639,8 +779,14 @@ impl Foolang {
             filepath_vtable: Rc::new(classes::filepath::instance_vtable()),
             filestream_class_vtable: Rc::new(classes::filestream::class_vtable()),
             filestream_vtable: Rc::new(classes::filestream::instance_vtable()),
+            finalizer_class_vtable: Rc::new(classes::weakref::finalizer_class_vtable()),
+            finalizer_vtable: Rc::new(classes::weakref::finalizer_instance_vtable()),
             float_class_vtable: Rc::new(classes::float::class_vtable()),
             float_vtable: Rc::new(classes::float::instance_vtable()),
+            gen_class_vtable: Rc::new(classes::property::class_vtable()),
+            gen_vtable: Rc::new(classes::property::instance_vtable()),
+            generator_class_vtable: Rc::new(classes::generator::class_vtable()),
+            generator_vtable: Rc::new(classes::generator::instance_vtable()),
             input_class_vtable: Rc::new(Vtable::for_class("Input")),
             input_vtable: Rc::new(classes::input::vtable()),
             integer_class_vtable: Rc::new(Vtable::for_class("Integer")),
@@ -648,21 +794,53 @@ impl Foolang {
             interface_vtable: Rc::new(classes::class::interface_vtable()),
             output_class_vtable: Rc::new(classes::output::class_vtable()),
             output_vtable: Rc::new(classes::output::instance_vtable()),
+            process_class_vtable: Rc::new(classes::process::class_vtable()),
+            process_vtable: Rc::new(classes::process::instance_vtable()),
+            property_class_vtable: Rc::new(classes::property::property_class_vtable()),
+            property_vtable: Rc::new(classes::property::property_instance_vtable()),
             random_class_vtable: Rc::new(classes::random::class_vtable()),
             random_vtable: Rc::new(classes::random::instance_vtable()),
             record_class_vtable: Rc::new(classes::record::class_vtable()),
             record_vtable: Rc::new(classes::record::instance_vtable()),
+            macro_vtable: Rc::new(classes::syntax::macro_vtable()),
+            original_vtable: Rc::new(classes::wrapper::instance_vtable()),
             string_class_vtable: Rc::new(classes::string::class_vtable()),
             string_vtable: Rc::new(classes::string::instance_vtable()),
+            supervisor_class_vtable: Rc::new(classes::process::supervisor_class_vtable()),
+            supervisor_vtable: Rc::new(classes::process::supervisor_instance_vtable()),
+            syntax_class_vtable: Rc::new(classes::syntax::class_vtable()),
+            syntax_vtable: Rc::new(classes::syntax::instance_vtable()),
             time_class_vtable: Rc::new(classes::time::class_vtable()),
             time_vtable: Rc::new(classes::time::instance_vtable()),
+            weakref_class_vtable: Rc::new(classes::weakref::class_vtable()),
+            weakref_vtable: Rc::new(classes::weakref::instance_vtable()),
+            yielder_vtable: Rc::new(classes::generator::yielder_vtable()),
             // Other
             builtin_env_ref: EnvRef::new(),
             modules: Rc::new(RefCell::new(HashMap::new())),
             roots,
+            prelude: prelude.to_path_buf(),
+            heap: Rc::new(Heap::new()),
+            program: None,
+            process: classes::process::ProcessState::new("Main"),
+            coverage: None,
+            debugger: None,
+            trace: Rc::new(Trace::default()),
+            expansion_site: RefCell::new(None),
+            gensym_counter: Cell::new(0),
+            has_macros: Cell::new(false),
+        }
+    }
+
+    /// Exits the process, writing the coverage report first if there is
+    /// one.
+    pub fn exit(&self, code: i32) -> ! {
+        if let Some(coverage) = &self.coverage {
+            if let Err(err) = coverage.write_report() {
+                eprintln!("ERROR - cannot write coverage report: {}", err);
+            }
         }
-        .init_builtins()
-        .load_prelude(prelude)
+        std::process::exit(code)
     }
 
     #[cfg(test)]
@@ -677,9 +855,53 @@ impl Foolang {
     }
 
     pub fn run(self, program: &str, command: Object) -> Eval {
+        self.run_with_output(program, command, None)
+    }
+
+    /// Like run, but System output of Main is output if given instead of
+    /// stdout.
+    pub fn run_with_output(
+        mut self,
+        program: &str,
+        command: Object,
+        output: Option<Object>,
+    ) -> Eval {
+        self.program = Some(Arc::new(program.to_string()));
         let env = self.builtin_env().load_code(program, self.root())?;
         let main = env.find_global_or_unwind("Main")?;
-        Ok(main.send("run:in:", &[command, self.make_system(None)], &env).context(&program)?)
+        if let Some(debugger) = &self.debugger {
+            debugger.start(&env);
+        }
+        Ok(main.send("run:in:", &[command, self.make_system(output)], &env).context(&program)?)
+    }
+
+    /// Prepares for running program in process.
+    pub fn in_process(
+        mut self,
+        program: Arc<String>,
+        process: Arc<classes::process::ProcessState>,
+    ) -> Foolang {
+        self.program = Some(program);
+        self.process = process;
+        self
+    }
+
+    pub fn load_program(&self) -> Result<Env, Unwind> {
+        match &self.program {
+            Some(program) => self.builtin_env().load_code(program, self.root()),
+            None => Unwind::error("No program to load"),
+        }
+    }
+
+    /// Sends run:in: to class, passing in the current process.
+    pub fn run_process(&self, env: &Env, class: &str) -> Eval {
+        let agent = env.find_global_or_unwind(class)?;
+        let process = classes::process::into_process(self, self.process.clone());
+        let res = agent.send("run:in:", &[process, self.make_system(None)], env);
+        match &self.program {
+            Some(program) => res.context(program),
+            None => res,
+        }
     }
 
     fn load_prelude(self, path: &Path) -> Result<Foolang, Unwind> {
@@ -728,17 +950,18 @@ impl Foolang {
     }
 
     pub fn make_boolean(&self, x: bool) -> Object {
-        Object {
-            vtable: Rc::clone(&self.boolean_vtable),
-            datum: Datum::Boolean(x),
-        }
+        Object::immediate(Datum::Boolean(x))
     }
 
     // FIXME: inconsistent return type vs other make_foo methods.
     // Should others be Eval as well?
     // FIXME: duplicates logic in classes::class::class_new_
     pub fn make_class(&self, def: &ClassDef, env: &Env) -> Eval {
-        let class_object = Class::new_class(&def.name);
+        let class_object = if def.value {
+            Class::new_value_class(&def.name)
+        } else {
+            Class::new_class(&def.name)
+        };
         let class = class_object.as_class_ref()?;
         for (i, var) in def.instance_variables.iter().enumerate() {
             class.add_slot(&var.name, i, env.maybe_type(&var.typename)?)?;
@@ -774,28 +997,31 @@ impl Foolang {
     }
 
     pub fn make_clock(&self) -> Object {
-        Object {
-            vtable: Rc::clone(&self.clock_vtable),
-            datum: Datum::Clock,
-        }
+        Object::new(Rc::clone(&self.clock_vtable), Datum::Clock)
     }
 
+    /// Slots are None if the body has not been resolved yet.
     pub fn make_closure(
         &self,
         env: &Env,
         params: Vec<Arg>,
-        body: Expr,
+        mut body: Expr,
+        slots: Option<SlotNames>,
         parameter_type_names: Vec<&Option<String>>,
         return_type_name: &Option<String>,
     ) -> Eval {
+        let slots = match slots {
+            Some(slots) => slots,
+            None => resolve(params.iter().map(|p| p.name.as_str()), &mut body),
+        };
         let mut parameter_types = vec![];
         for name in parameter_type_names {
             parameter_types.push(env.maybe_type(name)?);
         }
         let return_type = env.maybe_type(return_type_name)?;
-        Ok(Object {
-            vtable: Rc::clone(&self.closure_vtable),
-            datum: Datum::Closure(Rc::new(Closure {
+        let closure = Object::new(
+            Rc::clone(&self.closure_vtable),
+            Datum::Closure(Rc::new(Closure {
                 name: "block".to_string(),
                 env_ref: env.env_ref.clone(),
                 params,
@@ -804,8 +1030,42 @@ impl Foolang {
                     parameter_types,
                     return_type,
                 },
+                slots,
             })),
-        })
+        );
+        self.heap.register(&closure);
+        Ok(closure)
+    }
+
+    /// Source location of the macro call currently being expanded.
+    pub fn expansion_site(&self) -> Option<SourceLocation> {
+        self.expansion_site.borrow().clone()
+    }
+
+    /// Sets the expansion site, returning the previous one.
+    pub fn set_expansion_site(&self, site: Option<SourceLocation>) -> Option<SourceLocation> {
+        self.expansion_site.replace(site)
+    }
+
+    pub fn has_macros(&self) -> bool {
+        self.has_macros.get()
+    }
+
+    pub fn make_macro(&self, name: &str, selector: &str, function: Object) -> Object {
+        self.has_macros.set(true);
+        classes::syntax::make_macro(self, name, selector, function)
+    }
+
+    pub fn into_syntax(&self, expr: Expr) -> Object {
+        classes::syntax::into_syntax(self, expr)
+    }
+
+    /// Returns a fresh name based on name. The result is not a valid
+    /// identifier, so it cannot clash with names in source.
+    pub fn gensym(&self, name: &str) -> String {
+        let n = self.gensym_counter.get() + 1;
+        self.gensym_counter.set(n);
+        format!("{}#{}", name, n)
     }
 
     pub fn make_compiler(&self) -> Object {
@@ -817,38 +1077,32 @@ impl Foolang {
     }
 
     pub fn make_float(&self, x: f64) -> Object {
-        Object {
-            vtable: Rc::clone(&self.float_vtable),
-            datum: Datum::Float(x),
-        }
+        Object::immediate(Datum::Float(x))
     }
 
-    pub fn make_input(&self, name: &str, input: Box<dyn Read>) -> Object {
-        Object {
-            vtable: Rc::clone(&self.input_vtable),
-            datum: Datum::Input(Rc::new(Input {
+    pub fn make_input(&self, name: &str, input: Box<dyn Read + Send>) -> Object {
+        Object::new(
+            Rc::clone(&self.input_vtable),
+            Datum::Input(Rc::new(Input {
                 name: name.to_string(),
                 stream: RefCell::new(input),
                 buffer: RefCell::new(Vec::new()),
             })),
-        }
+        )
     }
 
     pub fn make_integer(&self, x: i64) -> Object {
-        Object {
-            vtable: Rc::clone(&self.integer_vtable),
-            datum: Datum::Integer(x),
-        }
+        Object::immediate(Datum::Integer(x))
     }
 
-    pub fn make_output(&self, name: &str, output: Box<dyn Write>) -> Object {
-        Object {
-            vtable: Rc::clone(&self.output_vtable),
-            datum: Datum::Output(Rc::new(Output {
+    pub fn make_output(&self, name: &str, output: Box<dyn Write + Send>) -> Object {
+        Object::new(
+            Rc::clone(&self.output_vtable),
+            Datum::Output(Rc::new(Output {
                 name: name.to_string(),
                 stream: RefCell::new(output),
             })),
-        }
+        )
     }
 
     pub fn make_string(&self, string: &str) -> Object {
@@ -856,30 +1110,72 @@ impl Foolang {
     }
 
     pub fn into_string(&self, string: String) -> Object {
-        Object {
-            vtable: Rc::clone(&self.string_vtable),
-            datum: Datum::String(Rc::new(string)),
-        }
+        let string = Object::new(Rc::clone(&self.string_vtable), Datum::String(Rc::new(string)));
+        self.heap.register(&string);
+        string
     }
 
     pub fn make_system(&self, output: Option<Object>) -> Object {
-        Object {
-            vtable: Rc::new(classes::system::vtable()),
-            datum: Datum::System(Rc::new(System {
+        Object::new(
+            Rc::new(classes::system::vtable()),
+            Datum::System(Rc::new(System {
                 output,
             })),
-        }
+        )
     }
 
     pub fn make_time(&self, timeinfo: TimeInfo) -> Object {
-        Object {
-            vtable: Rc::clone(&self.time_vtable),
-            datum: Datum::Time(Rc::new(timeinfo)),
-        }
+        Object::new(Rc::clone(&self.time_vtable), Datum::Time(Rc::new(timeinfo)))
     }
 }
 
 impl Object {
+    pub fn new(vtable: Rc<Vtable>, datum: Datum) -> Object {
+        debug_assert!(!datum.is_immediate(), "BUG: immediate with a vtable");
+        Object {
+            vtable: Some(vtable),
+            datum,
+        }
+    }
+
+    fn immediate(datum: Datum) -> Object {
+        Object {
+            vtable: None,
+            datum,
+        }
+    }
+
+    /// Vtable of the object: for immediates it is looked up from the tag.
+    pub fn vtable<'a>(&'a self, foolang: &'a Foolang) -> &'a Rc<Vtable> {
+        match &self.vtable {
+            Some(vtable) => vtable,
+            None => match self.datum {
+                Datum::Boolean(_) => &foolang.boolean_vtable,
+                Datum::Float(_) => &foolang.float_vtable,
+                Datum::Integer(_) => &foolang.integer_vtable,
+                _ => unreachable!("BUG: object without a vtable"),
+            },
+        }
+    }
+
+    /// Vtable of a heap allocated object, None for immediates.
+    pub fn heap_vtable(&self) -> Option<&Rc<Vtable>> {
+        self.vtable.as_ref()
+    }
+
+    /// Name of the vtable, for use where the interpreter is not at hand.
+    pub fn vtable_name(&self) -> &str {
+        match &self.vtable {
+            Some(vtable) => &vtable.name,
+            None => match self.datum {
+                Datum::Boolean(_) => "Boolean",
+                Datum::Float(_) => "Float",
+                Datum::Integer(_) => "Integer",
+                _ => unreachable!("BUG: object without a vtable"),
+            },
+        }
+    }
+
     pub fn as_mut_vec<T>(
         &self,
         fun: impl FnOnce(RefMut<Vec<Object>>) -> Result<T, Unwind>,
@@ -921,11 +1217,12 @@ impl Object {
         }
     }
 
-    pub fn is_type(&self, typevt: &Rc<Vtable>) -> bool {
-        if typevt == &self.vtable {
+    pub fn is_type(&self, typevt: &Rc<Vtable>, foolang: &Foolang) -> bool {
+        let vtable = self.vtable(foolang);
+        if typevt == vtable {
             return true;
         }
-        for vt in self.vtable.interfaces().iter() {
+        for vt in vtable.interfaces().iter() {
             if typevt == vt {
                 return true;
             }
@@ -937,8 +1234,8 @@ impl Object {
         false
     }
 
-    pub fn typecheck(&self, typevt: &Rc<Vtable>) -> Eval {
-        if self.is_type(typevt) {
+    pub fn typecheck(&self, typevt: &Rc<Vtable>, foolang: &Foolang) -> Eval {
+        if self.is_type(typevt, foolang) {
             Ok(self.clone())
         } else {
             Unwind::type_error(self.clone(), typevt.name.clone())
@@ -958,8 +1255,8 @@ impl Object {
         Ok(self.clone())
     }
 
-    pub fn slots(&self) -> Ref<Vec<Slot>> {
-        self.vtable.slots()
+    pub fn slots<'a>(&'a self, foolang: &'a Foolang) -> Ref<'a, Vec<Slot>> {
+        self.vtable(foolang).slots()
     }
 
     pub fn closure_ref(&self) -> &Closure {
@@ -1060,8 +1357,7 @@ impl Object {
         selector: &str,
         method: MethodFunction,
     ) -> Result<(), Unwind> {
-        self.as_class_ref()?;
-        self.vtable.add_method(selector, Method::primitive(method))?;
+        self.as_class_ref()?.class_vtable.add_method(selector, Method::primitive(method))?;
         Ok(())
     }
 
@@ -1071,9 +1367,9 @@ impl Object {
         // Add interface direct methods
         let interface_obj = env.find_interface(name)?;
         let interface = interface_obj.as_class_ref()?;
-        for (selector, method) in interface_obj.vtable.methods().iter() {
-            if !self.vtable.has(selector) {
-                self.vtable.add_method(selector, method.clone())?;
+        for (selector, method) in interface.class_vtable.methods().iter() {
+            if !class.class_vtable.has(selector) {
+                class.class_vtable.add_method(selector, method.clone())?;
             }
         }
         let instance_vt = &class.instance_vtable;
@@ -1098,6 +1394,9 @@ impl Object {
                 Some(Method::Reader(_)) => {
                     // FIXME: signature not checked!
                 }
+                Some(Method::Wrapped(_)) => {
+                    // FIXME: signature not checked!
+                }
                 Some(_) => {
                     return Unwind::error(&format!(
                     "{}#{} is an interface method, non-vanilla implementations not supporte yet",
@@ -1139,9 +1438,9 @@ impl Object {
             return Ok(());
         }
         // Add interface direct methods
-        for (selector, method) in interface_obj.vtable.methods().iter() {
-            if !self.vtable.has(selector) {
-                self.vtable.add_method(selector, method.clone())?;
+        for (selector, method) in interface.class_vtable.methods().iter() {
+            if !class.class_vtable.has(selector) {
+                class.class_vtable.add_method(selector, method.clone())?;
             }
         }
         // Add interface to instance vtable
@@ -1169,6 +1468,9 @@ impl Object {
                 Some(Method::Reader(_)) => {
                     // FIXME: signature not checked!
                 }
+                Some(Method::Wrapped(_)) => {
+                    // FIXME: signature not checked!
+                }
                 Some(_) => {
                     return Unwind::error(&format!(
                     "{}#{} is an interface method, non-vanilla implementations not supporte yet",
@@ -1199,7 +1501,8 @@ impl Object {
         let class = self.as_class_ref()?;
         let binding = Binding::untyped(self.clone());
         let env = env.bind(&class.instance_vtable.name, binding.clone());
-        self.vtable.add_method(&method.selector, Method::closure(method, &env)?)?;
+        let name = format!("{}##{}", class.instance_vtable.name, method.selector);
+        class.class_vtable.add_method(&method.selector, Method::closure(&name, method, &env)?)?;
         Ok(())
     }
 
@@ -1211,7 +1514,10 @@ impl Object {
         let class = self.as_class_ref()?;
         let binding = Binding::untyped(self.clone());
         let env = env.bind(&class.instance_vtable.name, binding.clone());
-        class.instance_vtable.add_method(&method.selector, Method::closure(method, &env)?)?;
+        let name = format!("{}#{}", class.instance_vtable.name, method.selector);
+        class
+            .instance_vtable
+            .add_method(&method.selector, Method::closure(&name, method, &env)?)?;
         Ok(())
     }
 
@@ -1317,27 +1623,17 @@ impl Object {
     // SEND
 
     pub fn send(&self, selector: &str, args: &[Object], env: &Env) -> Eval {
-        if false
-            && selector != "typecheck:"
-            && !self.is_closure()
-            && !self.is_boolean()
-            && !self.is_integer()
-        {
-            let s = format!("send: {} #{} {:?}", self, selector, args);
-            println!("{}", &s[0..std::cmp::min(80, s.len())]);
-        }
-        match self.vtable.get(selector) {
-            Some(m) => match &m {
-                Method::Primitive(method) => method(self, args, env),
-                Method::Interpreter(closure) => closure.apply(Some(self), args, env),
-                Method::Reader(index) => read_instance_variable(self, *index),
-                Method::Required(_) => {
-                    Unwind::error(&format!("Required method '{}' unimplemented", selector))
-                }
-                Method::Object(method) => {
-                    method.send("invoke:on:", &[env.foo.make_array(args), self.clone()], env)
-                }
-            },
+        if env.foo.trace.is_enabled() {
+            return env.foo.trace.send(self, selector, args, env);
+        }
+        self.dispatch(selector, args, env)
+    }
+
+    /// Performs the method for selector, see send.
+    pub(crate) fn dispatch(&self, selector: &str, args: &[Object], env: &Env) -> Eval {
+        let vtable = self.vtable(&env.foo);
+        match vtable.get(selector) {
+            Some(m) => self.invoke(&m, selector, args, env),
             None if selector == "__atSlot:" => {
                 read_instance_variable(self, args[0].as_usize("#__atSlot:")?)
             }
@@ -1349,9 +1645,9 @@ impl Object {
             None if selector == "__doSelectors:" => generic_do_selectors(self, args, env),
             None if selector == "__toString" => generic_to_string(self, args, env),
             None => {
-                // println!("known: {:?}", self.vtable.selectors());
+                // println!("known: {:?}", vtable.selectors());
                 let not_understood = vec![env.foo.make_string(selector), env.foo.make_array(args)];
-                match self.vtable.get("perform:with:") {
+                match vtable.get("perform:with:") {
                     Some(m) => match &m {
                         Method::Primitive(method) => method(self, &not_understood, env),
                         Method::Interpreter(closure) => {
@@ -1361,7 +1657,9 @@ impl Object {
                         Method::Required(_) => {
                             Unwind::error(&format!("Required method '{}' unimplemented", selector))
                         }
-                        Method::Object(_) => self.send("perform:with:", &not_understood, env),
+                        Method::Object(_) | Method::Wrapped(_) => {
+                            self.send("perform:with:", &not_understood, env)
+                        }
                     },
                     None => {
                         Unwind::message_error(self, selector, args)
@@ -1371,6 +1669,36 @@ impl Object {
             }
         }
     }
+
+    /// Performs method, found for selector, with self as the receiver.
+    pub fn invoke(&self, method: &Method, selector: &str, args: &[Object], env: &Env) -> Eval {
+        match method {
+            Method::Primitive(method) => method(self, args, env),
+            Method::Interpreter(closure) => closure.apply(Some(self), args, env),
+            Method::Reader(index) => read_instance_variable(self, *index),
+            Method::Required(_) => {
+                Unwind::error(&format!("Required method '{}' unimplemented", selector))
+            }
+            Method::Object(method) => {
+                method.send("invoke:on:", &[env.foo.make_array(args), self.clone()], env)
+            }
+            Method::Wrapped(wrapper) => wrapper.invoke(self, args, env),
+        }
+    }
+
+    /// Like send, but a tail send of an interpreted method is returned
+    /// instead of performed, see Tail.
+    pub fn send_tail(&self, selector: &str, args: &[Object], env: &Env) -> Result<Tail, Unwind> {
+        let method = self.vtable(&env.foo).get(selector);
+        match &method {
+            Some(Method::Interpreter(closure))
+                if !env.foo.trace.traces(self, selector, method.as_ref()) =>
+            {
+                closure.apply_tail(Some(self), args, env)
+            }
+            _ => Ok(Tail::Value(self.send(selector, args, env)?)),
+        }
+    }
 }
 
 pub fn make_method_closure(
@@ -1389,15 +1717,18 @@ pub fn make_method_closure(
             None => parameter_types.push(None),
         }
     }
+    let mut body = body.to_owned();
+    let slots = resolve(params.iter().map(|p| p.name.as_str()), &mut body);
     Ok(Closure {
         name: name.to_string(),
         env_ref: env.env_ref.clone(),
         params: args,
-        body: body.to_owned(),
+        body,
         signature: Signature {
             parameter_types,
             return_type: env.maybe_type(&return_type)?,
         },
+        slots,
     })
 }
 
@@ -1408,7 +1739,7 @@ impl fmt::Display for Object {
             Datum::Boolean(true) => write!(f, "True"),
             Datum::Boolean(false) => write!(f, "False"),
             Datum::ByteArray(byte_array) => write!(f, "{:?}", byte_array),
-            Datum::Class(_class) => write!(f, "{}", self.vtable.name),
+            Datum::Class(_class) => write!(f, "{}", self.vtable_name()),
             Datum::Clock => write!(f, "#<Clock>"),
             Datum::Closure(x) => write!(f, "#<closure {:?}>", x.params),
             Datum::Compiler(_) => write!(f, "#<Compiler>"),
@@ -1423,19 +1754,28 @@ impl fmt::Display for Object {
                     write!(f, "{}", x)
                 }
             }
+            Datum::Gen(gen) => write!(f, "#<{}>", gen),
+            Datum::Generator(_) => write!(f, "#<Generator>"),
             Datum::Input(input) => write!(f, "#<Input {}>", &input.name),
-            Datum::Instance(_) => write!(f, "#<instance {}>", self.vtable.name),
+            Datum::Instance(_) => write!(f, "#<instance {}>", self.vtable_name()),
             Datum::Integer(x) => write!(f, "{}", x),
+            Datum::Macro(x) => write!(f, "#<Macro {} {}>", &x.name, &x.selector),
+            Datum::Original(x) => write!(f, "#<Original {}>", &x.selector),
             Datum::Output(output) => write!(f, "#<Output {}>", &output.name),
+            Datum::Process(process) => write!(f, "#<Process {}>", &process.name),
             Datum::Random(_) => write!(f, "#<Random>"),
             Datum::Record(r) => write!(f, "{:?}", r),
             Datum::String(s) => write!(f, "{}", s),
+            Datum::Supervisor(_) => write!(f, "#<Supervisor>"),
+            Datum::Syntax(_) => write!(f, "#<Syntax>"),
             Datum::System(_) => write!(f, "#<System>"),
             Datum::Time(time) => write!(
                 f,
                 "#<Time real: {}, system: {}, user: {}>",
                 time.real, time.system, time.user
             ),
+            Datum::WeakRef(_) => write!(f, "#<WeakRef>"),
+            Datum::Yielder(_) => write!(f, "#<Yielder>"),
         }
     }
 }
@@ -1453,8 +1793,8 @@ impl fmt::Debug for Object {
                 }
             }
             Datum::Closure(x) => write!(f, "#<Closure {:?}>", x.params),
-            Datum::Class(_) => write!(f, "{}", self.vtable.name),
-            Datum::Instance(_) => write!(f, "{}", self.vtable.name),
+            Datum::Class(_) => write!(f, "{}", self.vtable_name()),
+            Datum::Instance(_) => write!(f, "{}", self.vtable_name()),
             Datum::String(s) => write!(f, "{:?}", s),
             _ => write!(f, "{}", self),
         }
@@ -1485,14 +1825,14 @@ fn generic_to_string(receiver: &Object, _args: &[Object], env: &Env) -> Eval {
                 }
                 info.push_str(format!("{}", var).as_str());
             }
-            Ok(env.foo.into_string(format!("#<{}{}>", &receiver.vtable.name, info)))
+            Ok(env.foo.into_string(format!("#<{}{}>", receiver.vtable(&env.foo).name, info)))
         }
         _ => Ok(env.foo.into_string(format!("{}", receiver))),
     }
 }
 
 fn generic_do_selectors(receiver: &Object, args: &[Object], env: &Env) -> Eval {
-    let methods = receiver.vtable.methods.borrow();
+    let methods = receiver.vtable(&env.foo).methods.borrow();
     let selector = env.find_global_or_unwind("Selector")?;
     for method in methods.keys() {
         args[0].send(
@@ -1512,11 +1852,20 @@ pub fn read_instance_variable(receiver: &Object, index: usize) -> Eval {
 
 pub fn unsafe_write_instance_variable(receiver: &Object, value: &Object, index: usize) -> Eval {
     let instance = receiver.instance()?;
+    if instance.value {
+        return Unwind::error(&format!("Cannot modify value object: {}", receiver));
+    }
     instance.instance_variables.borrow_mut()[index] = value.clone();
     Ok(value.clone())
 }
 
 pub fn write_instance_variable(receiver: &Object, slot: &Slot, value: Object, env: &Env) -> Eval {
+    if receiver.instance()?.value {
+        return Unwind::error(&format!(
+            "Cannot assign to {}: {} is a value object",
+            &slot.name, receiver
+        ));
+    }
     let ok = if let Some(typed) = &slot.typed {
         typed.send("typecheck:", &[value], env)?
     } else {
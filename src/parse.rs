@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, VecDeque};
 use std::convert::Into;
 use std::path::{Path, PathBuf};
@@ -78,11 +78,16 @@ pub struct Parser<'a> {
     source: &'a str,
     path: Option<Rc<PathBuf>>,
     token_table: TokenTable,
-    name_table: NameTable,
+    // Mutable so that operator declarations can extend it for the rest of the
+    // source being parsed.
+    name_table: RefCell<NameTable>,
     state: RefCell<ParserState<'a>>,
     // Directory to use for relative imports. Normally the directory of
     // the source file, but different in REPL, etc.
     root: PathBuf,
+    // Words which terminate the current expression instead of being parsed
+    // as unary messages. Used by comprehensions for 'for' and 'where'.
+    stop_words: Cell<&'static [&'static str]>,
 }
 
 impl<'a> Parser<'a> {
@@ -108,13 +113,14 @@ impl<'a> Parser<'a> {
             source,
             path: None,
             token_table: make_token_table(),
-            name_table: make_name_table(),
+            name_table: RefCell::new(make_name_table()),
             state: RefCell::new(ParserState {
                 tokenstream: TokenStream::new(source),
                 lookahead: VecDeque::new(),
                 span: 0..0,
             }),
             root: root.as_ref().to_path_buf(),
+            stop_words: Cell::new(&[]),
         }
     }
 
@@ -127,16 +133,21 @@ impl<'a> Parser<'a> {
             source,
             path: Some(Rc::new(path.to_path_buf())),
             token_table: make_token_table(),
-            name_table: make_name_table(),
+            name_table: RefCell::new(make_name_table()),
             state: RefCell::new(ParserState {
                 tokenstream: TokenStream::new(source),
                 lookahead: VecDeque::new(),
                 span: 0..0,
             }),
             root: root.as_ref().to_path_buf(),
+            stop_words: Cell::new(&[]),
         }
     }
 
+    fn name_syntax(&self, name: &str) -> Option<ParserSyntax> {
+        self.name_table.borrow().get(name).cloned()
+    }
+
     pub(crate) fn parse(&mut self) -> Parse {
         self._parse()
     }
@@ -254,9 +265,12 @@ impl<'a> Parser<'a> {
     fn parse_prefix_syntax(&self, syntax: &ParserSyntax) -> Parse {
         match syntax {
             ParserSyntax::General(prefix, _, _) => prefix(self),
-            ParserSyntax::Operator(_, _, _) => {
+            ParserSyntax::Operator(is_prefix, _, _) => {
                 let operator = self.tokenstring();
                 let source_location = self.source_location();
+                if !is_prefix {
+                    return self.error(&format!("Not a prefix operator: {}", operator));
+                }
                 Ok(Syntax::Expr(self.parse_expr(PREFIX_PRECEDENCE)?.send(Message {
                     source_location,
                     selector: format!("prefix{}", operator),
@@ -269,9 +283,12 @@ impl<'a> Parser<'a> {
     fn parse_suffix_syntax(&self, syntax: &ParserSyntax, left: Expr) -> ExprParse {
         match syntax {
             ParserSyntax::General(_, suffix, precedence) => suffix(self, left, *precedence),
-            ParserSyntax::Operator(_, _, precedence) => {
+            ParserSyntax::Operator(_, is_binary, precedence) => {
                 let operator = self.tokenstring();
                 let mut source = self.source_location();
+                if !is_binary {
+                    return self.error(&format!("Not a binary operator: {}", operator));
+                }
                 let arg = self.parse_expr(*precedence)?;
                 source.extend_span_to(arg.span().end);
                 Ok(left.send(Message {
@@ -386,6 +403,17 @@ impl<'a> Parser<'a> {
     pub(crate) fn error<T>(&self, problem: &str) -> Result<T, Unwind> {
         Unwind::error_at(self.source_location(), problem)
     }
+
+    fn with_stop_words<T>(
+        &self,
+        words: &'static [&'static str],
+        fun: impl FnOnce() -> Result<T, Unwind>,
+    ) -> Result<T, Unwind> {
+        let old = self.stop_words.replace(words);
+        let res = fun();
+        self.stop_words.set(old);
+        res
+    }
 }
 
 impl ParserSyntax {
@@ -453,6 +481,8 @@ fn make_name_table() -> NameTable {
     ParserSyntax::def(t, "extend", extend_prefix, invalid_suffix, precedence_0);
     ParserSyntax::def(t, "import", import_prefix, invalid_suffix, precedence_0);
     ParserSyntax::def(t, "interface", interface_prefix, invalid_suffix, precedence_0);
+    ParserSyntax::def(t, "macro", macro_prefix, unary_suffix, precedence_1000);
+    ParserSyntax::def(t, "operator", operator_def_prefix, unary_suffix, precedence_1000);
     ParserSyntax::def(t, ",", invalid_prefix, invalid_suffix, precedence_0);
     ParserSyntax::def(t, "->", invalid_prefix, invalid_suffix, precedence_0);
     ParserSyntax::def(t, "defaultConstructor", invalid_prefix, invalid_suffix, precedence_0);
@@ -463,6 +493,7 @@ fn make_name_table() -> NameTable {
     ParserSyntax::def(t, "let", let_prefix, invalid_suffix, precedence_3);
     ParserSyntax::def(t, "return", return_prefix, invalid_suffix, precedence_3);
     ParserSyntax::def(t, "panic", panic_prefix, invalid_suffix, precedence_3);
+    ParserSyntax::def(t, "quote", quote_prefix, unary_suffix, precedence_1000);
     ParserSyntax::def(t, ";", invalid_prefix, cascade_suffix, precedence_3);
     ParserSyntax::def(t, "=", invalid_prefix, assign_suffix, precedence_4);
     ParserSyntax::def(t, "is", invalid_prefix, is_suffix, precedence_10);
@@ -561,6 +592,16 @@ fn invalid_suffix(parser: &Parser, _: Expr, _: PrecedenceFunction) -> ExprParse
     )
 }
 
+/// Suffix of words which are keywords only in value position: elsewhere
+/// they are unary messages like any other word.
+fn unary_suffix(parser: &Parser, left: Expr, _: PrecedenceFunction) -> ExprParse {
+    Ok(left.send(Message {
+        source_location: parser.source_location(),
+        selector: parser.tokenstring(),
+        args: vec![],
+    }))
+}
+
 fn array_prefix(parser: &Parser) -> Parse {
     let mut source_location = parser.source_location();
     let (token, next) = parser.lookahead()?;
@@ -572,7 +613,21 @@ fn array_prefix(parser: &Parser) -> Parse {
     } else {
         let mut data = vec![];
         loop {
-            data.push(parser.parse_expr(1)?);
+            if data.is_empty() {
+                let first = parser.with_stop_words(&["for"], || parser.parse_expr(1))?;
+                if let (Token::WORD, span) = parser.lookahead()? {
+                    if parser.slice_at(span) == "for" {
+                        return Ok(Syntax::Expr(parse_comprehension(
+                            parser,
+                            source_location,
+                            first,
+                        )?));
+                    }
+                }
+                data.push(first);
+            } else {
+                data.push(parser.with_stop_words(&[], || parser.parse_expr(1))?);
+            }
             let token = parser.next_token()?;
             if token == Token::SIGIL && parser.slice() == "]" {
                 source_location.extend_span_to(parser.span().end);
@@ -597,6 +652,271 @@ fn array_prefix(parser: &Parser) -> Parse {
     Ok(Syntax::Expr(Array::expr(source_location, data)))
 }
 
+enum ComprehensionClause {
+    Generator(Var, Expr),
+    Filter(SourceLocation, Expr),
+}
+
+/// Parses the rest of a comprehension after the collected expression:
+///
+///   [ expr for var in iterable, var in iterable where test ... ]
+///
+/// Generators and filters nest in the order written. Desugars into
+/// nested Iterable#do: sends, adding each collected value to a List
+/// and answering it as an Array.
+fn parse_comprehension(
+    parser: &Parser,
+    mut source_location: SourceLocation,
+    collect: Expr,
+) -> ExprParse {
+    const CLAUSE_WORDS: &[&str] = &["for", "where"];
+    let mut clauses = vec![];
+    loop {
+        match parser.next_token()? {
+            Token::WORD if parser.slice() == "for" => loop {
+                if parser.next_token()? != Token::WORD {
+                    return parser.error("Expected variable name after 'for'");
+                }
+                let var = parse_var(parser, false)?;
+                if !(parser.next_token()? == Token::WORD && parser.slice() == "in") {
+                    return parser.error("Expected 'in' after comprehension variable");
+                }
+                let iterable = parser.with_stop_words(CLAUSE_WORDS, || parser.parse_expr(1))?;
+                clauses.push(ComprehensionClause::Generator(var, iterable));
+                match parser.lookahead()? {
+                    (Token::SIGIL, span) if parser.slice_at(span.clone()) == "," => {
+                        parser.next_token()?;
+                        continue;
+                    }
+                    _ => break,
+                }
+            },
+            Token::WORD if parser.slice() == "where" => {
+                let where_location = parser.source_location();
+                let test = parser.with_stop_words(CLAUSE_WORDS, || parser.parse_expr(1))?;
+                clauses.push(ComprehensionClause::Filter(where_location, test));
+            }
+            Token::SIGIL if parser.slice() == "]" => break,
+            Token::EOF => {
+                return parser.eof_error("Unexpected EOF in comprehension: expected ]");
+            }
+            _ => return parser.error("Expected 'for', 'where', or ] in comprehension"),
+        }
+    }
+    source_location.extend_span_to(parser.span().end);
+    // Not a valid identifier, so cannot capture user variables.
+    let result = "#comprehension".to_string();
+    let result_var = || Expr::Var(Var::untyped(source_location.clone(), result.clone(), false));
+    let mut body = result_var().send(Message {
+        source_location: collect.source_location(),
+        selector: "add:".to_string(),
+        args: vec![collect],
+    });
+    for clause in clauses.into_iter().rev() {
+        body = match clause {
+            ComprehensionClause::Generator(var, iterable) => {
+                let var_location = var.source_location.clone();
+                iterable.send(Message {
+                    source_location: var_location.clone(),
+                    selector: "do:".to_string(),
+                    args: vec![Block::expr(var_location, vec![var], Box::new(body), None)],
+                })
+            }
+            ComprehensionClause::Filter(where_location, test) => test.send(Message {
+                source_location: where_location.clone(),
+                selector: "ifTrue:".to_string(),
+                args: vec![Block::expr(where_location, vec![], Box::new(body), None)],
+            }),
+        };
+    }
+    let collector = Block::expr(
+        source_location.clone(),
+        vec![Var::untyped(source_location.clone(), result.clone(), false)],
+        Box::new(Seq::expr(vec![
+            body,
+            result_var().send(Message {
+                source_location: source_location.clone(),
+                selector: "asArray".to_string(),
+                args: vec![],
+            }),
+        ])),
+        None,
+    );
+    let list = Expr::Var(Var::untyped(source_location.clone(), "List".to_string(), false));
+    Ok(collector.send(Message {
+        source_location: source_location.clone(),
+        selector: "value:".to_string(),
+        args: vec![list.send(Message {
+            source_location,
+            selector: "new".to_string(),
+            args: vec![],
+        })],
+    }))
+}
+
+/// Parses the arms of a match expression, starting from the opening brace:
+///
+///   value match: { case pattern -> expr
+///                  case pattern where test -> expr }
+fn parse_match(parser: &Parser, mut source_location: SourceLocation, value: Expr) -> ExprParse {
+    parser.next_token()?;
+    let mut arms = vec![];
+    loop {
+        match parser.next_token()? {
+            Token::WORD if parser.slice() == "case" => {
+                let token = parser.next_token()?;
+                let pattern = parse_pattern(parser, token)?;
+                let guard = match parser.lookahead()? {
+                    (Token::WORD, span) if parser.slice_at(span.clone()) == "where" => {
+                        parser.next_token()?;
+                        Some(parser.with_stop_words(&[], || parser.parse_expr(1))?)
+                    }
+                    _ => None,
+                };
+                if !(parser.next_token()? == Token::SIGIL && parser.slice() == "->") {
+                    return parser.error("Expected -> after pattern");
+                }
+                let body = parser.with_stop_words(&["case"], || parser.parse_seq())?;
+                arms.push(MatchArm {
+                    pattern,
+                    guard,
+                    body,
+                    frame: None,
+                });
+            }
+            Token::SIGIL if parser.slice() == "}" => break,
+            Token::EOF => return parser.eof_error("Unexpected EOF in match: expected }"),
+            _ => return parser.error("Expected 'case' or } in match"),
+        }
+    }
+    source_location.extend_span_to(parser.span().end);
+    Ok(Match::expr(source_location, Box::new(value), arms))
+}
+
+/// Parses a pattern starting from the current token, rejecting patterns
+/// that bind the same variable twice.
+fn parse_pattern(parser: &Parser, token: Token) -> Result<Pattern, Unwind> {
+    let pattern = parse_subpattern(parser, token)?;
+    let vars = pattern.variables();
+    for (i, var) in vars.iter().enumerate() {
+        if vars[..i].iter().any(|v| v.name == var.name) {
+            return Unwind::error_at(
+                var.source_location.clone(),
+                &format!("Variable bound twice in pattern: {}", var.name),
+            );
+        }
+    }
+    Ok(pattern)
+}
+
+fn parse_subpattern(parser: &Parser, token: Token) -> Result<Pattern, Unwind> {
+    let mut source_location = parser.source_location();
+    let slice = parser.slice();
+    let pattern = match token {
+        Token::WORD if slice == "True" => Pattern::Literal(Const {
+            source_location,
+            literal: Literal::Boolean(true),
+        }),
+        Token::WORD if slice == "False" => Pattern::Literal(Const {
+            source_location,
+            literal: Literal::Boolean(false),
+        }),
+        Token::WORD => {
+            let var = parse_var(parser, false)?;
+            if var.name == "_" && var.typename.is_none() {
+                Pattern::Wildcard(source_location)
+            } else {
+                Pattern::Var(var)
+            }
+        }
+        Token::HEX_INTEGER
+        | Token::BIN_INTEGER
+        | Token::DEC_INTEGER
+        | Token::SINGLE_FLOAT
+        | Token::DOUBLE_FLOAT => match number_prefix(parser)? {
+            Syntax::Expr(Expr::Const(constant)) => Pattern::Literal(constant),
+            _ => panic!("BUG: number is not a constant"),
+        },
+        Token::STRING => match string_prefix(parser)? {
+            Syntax::Expr(Expr::Const(constant)) => Pattern::Literal(constant),
+            _ => return Unwind::error_at(source_location, "Interpolated string in pattern"),
+        },
+        Token::SIGIL if slice == "#" => match literal_prefix(parser)? {
+            Syntax::Expr(Expr::Const(constant)) => Pattern::Literal(constant),
+            _ => panic!("BUG: selector literal is not a constant"),
+        },
+        Token::SIGIL if slice == "-" => {
+            let token = parser.next_token()?;
+            match parse_subpattern(parser, token)? {
+                Pattern::Literal(Const {
+                    source_location: number_location,
+                    literal: Literal::Integer(i),
+                }) => {
+                    source_location.extend_span_to(number_location.end());
+                    Pattern::Literal(Const {
+                        source_location,
+                        literal: Literal::Integer(-i),
+                    })
+                }
+                Pattern::Literal(Const {
+                    source_location: number_location,
+                    literal: Literal::Float(x),
+                }) => {
+                    source_location.extend_span_to(number_location.end());
+                    Pattern::Literal(Const {
+                        source_location,
+                        literal: Literal::Float(-x),
+                    })
+                }
+                _ => {
+                    return Unwind::error_at(source_location, "Expected number after - in pattern")
+                }
+            }
+        }
+        Token::SIGIL if slice == "{" => {
+            let mut fields = vec![];
+            loop {
+                match parser.next_token()? {
+                    Token::KEYWORD => {
+                        let key = parser.slice();
+                        let key = key[0..key.len() - 1].to_string();
+                        let token = parser.next_token()?;
+                        fields.push((key, parse_subpattern(parser, token)?));
+                        match parser.next_token()? {
+                            Token::SIGIL if "," == parser.slice() => continue,
+                            Token::SIGIL if "}" == parser.slice() => break,
+                            _ => return parser.error("Malformed record pattern"),
+                        }
+                    }
+                    Token::SIGIL if "}" == parser.slice() => break,
+                    _ => return parser.error("Malformed record pattern"),
+                }
+            }
+            source_location.extend_span_to(parser.span().end);
+            Pattern::Record(source_location, fields)
+        }
+        Token::SIGIL if slice == "[" => {
+            let mut elements = vec![];
+            loop {
+                match parser.next_token()? {
+                    Token::SIGIL if "]" == parser.slice() => break,
+                    token => elements.push(parse_subpattern(parser, token)?),
+                }
+                match parser.next_token()? {
+                    Token::SIGIL if "," == parser.slice() => continue,
+                    Token::SIGIL if "]" == parser.slice() => break,
+                    _ => return parser.error("Malformed array pattern"),
+                }
+            }
+            source_location.extend_span_to(parser.span().end);
+            Pattern::Array(source_location, elements)
+        }
+        Token::EOF => return parser.eof_error("Unexpected EOF in pattern"),
+        _ => return parser.error("Invalid pattern"),
+    };
+    Ok(pattern)
+}
+
 fn assign_suffix(
     parser: &Parser,
     left: Expr,
@@ -677,16 +997,20 @@ fn false_prefix(parser: &Parser) -> Parse {
 }
 
 fn identifier_precedence(parser: &Parser, span: Span) -> Result<usize, Unwind> {
-    match parser.name_table.get(parser.slice_at(span.clone())) {
-        Some(syntax) => parser.syntax_precedence(syntax, span),
+    let name = parser.slice_at(span.clone());
+    if parser.stop_words.get().contains(&name) {
+        return Ok(0);
+    }
+    match parser.name_syntax(name) {
+        Some(syntax) => parser.syntax_precedence(&syntax, span),
         None => return Ok(1000), // unary messages
     }
 }
 
 fn identifier_prefix(parser: &Parser) -> Parse {
     let name = parser.slice();
-    match parser.name_table.get(name) {
-        Some(syntax) => parser.parse_prefix_syntax(syntax),
+    match parser.name_syntax(name) {
+        Some(syntax) => parser.parse_prefix_syntax(&syntax),
         None => {
             name.chars().next().expect("BUG: empty identifier");
             Ok(Syntax::Expr(Expr::Var(Var::untyped(
@@ -700,8 +1024,8 @@ fn identifier_prefix(parser: &Parser) -> Parse {
 
 fn identifier_suffix(parser: &Parser, left: Expr, _: PrecedenceFunction) -> Result<Expr, Unwind> {
     let name = parser.slice();
-    match parser.name_table.get(name) {
-        Some(syntax) => parser.parse_suffix_syntax(syntax, left),
+    match parser.name_syntax(name) {
+        Some(syntax) => parser.parse_suffix_syntax(&syntax, left),
         None => {
             // Unary message
             Ok(left.send(Message {
@@ -728,6 +1052,13 @@ fn keyword_suffix(
     let mut selector = parser.tokenstring();
     let mut args = vec![];
     let mut source_location = parser.source_location();
+    if selector == "match:" {
+        if let ((Token::SIGIL, span1), (Token::WORD, span2)) = parser.lookahead2()? {
+            if parser.slice_at(span1) == "{" && parser.slice_at(span2) == "case" {
+                return parse_match(parser, source_location, left);
+            }
+        }
+    }
     loop {
         args.push(parser.parse_expr(precedence)?);
         // Two-element lookahead.
@@ -754,22 +1085,22 @@ fn keyword_suffix(
 
 fn operator_precedence(parser: &Parser, span: Span) -> Result<usize, Unwind> {
     let slice = parser.slice_at(span.clone());
-    let syntax = parser.name_table.get(slice).unwrap_or(&UNKNOWN_OPERATOR_SYNTAX);
-    parser.syntax_precedence(syntax, span)
+    let syntax = parser.name_syntax(slice).unwrap_or(UNKNOWN_OPERATOR_SYNTAX);
+    parser.syntax_precedence(&syntax, span)
 }
 
 fn operator_prefix(parser: &Parser) -> Parse {
-    let syntax = parser.name_table.get(parser.slice()).unwrap_or(&UNKNOWN_OPERATOR_SYNTAX);
-    parser.parse_prefix_syntax(syntax)
+    let syntax = parser.name_syntax(parser.slice()).unwrap_or(UNKNOWN_OPERATOR_SYNTAX);
+    parser.parse_prefix_syntax(&syntax)
 }
 
 fn operator_suffix(parser: &Parser, left: Expr, _: PrecedenceFunction) -> Result<Expr, Unwind> {
-    let syntax = parser.name_table.get(parser.slice()).unwrap_or(&UNKNOWN_OPERATOR_SYNTAX);
-    parser.parse_suffix_syntax(syntax, left)
+    let syntax = parser.name_syntax(parser.slice()).unwrap_or(UNKNOWN_OPERATOR_SYNTAX);
+    parser.parse_suffix_syntax(&syntax, left)
 }
 
 fn paren_prefix(parser: &Parser) -> Parse {
-    let expr = parser.parse_seq()?;
+    let expr = parser.with_stop_words(&[], || parser.parse_seq())?;
     let token = parser.next_token()?;
     if token == Token::SIGIL && parser.slice() == ")" {
         Ok(Syntax::Expr(expr))
@@ -813,7 +1144,8 @@ fn sequence_suffix(
             || text == "method"
             || text == "end"
             || text == "class"
-            || text == "is"))
+            || text == "is"
+            || parser.stop_words.get().contains(&text)))
         || token == Token::EOF
     {
         return Ok(left);
@@ -908,7 +1240,7 @@ fn parse_block_or_dictionary(parser: &Parser) -> Result<Expr, Unwind> {
     let mut params = vec![];
     let mut rtype = None;
     let (token, span) = parser.lookahead()?;
-    if token == Token::SIGIL && parser.slice_at(span) == "|" {
+    if token == Token::SIGIL && parser.slice_at(span.clone()) == "|" {
         parser.next_token()?;
         loop {
             let token = parser.next_token()?;
@@ -921,6 +1253,9 @@ fn parse_block_or_dictionary(parser: &Parser) -> Result<Expr, Unwind> {
             }
             return parser.error("Not valid as block parameter");
         }
+    } else if token == Token::SIGIL && parser.slice_at(span) == "||" {
+        // Explicitly empty parameter list.
+        parser.next_token()?;
     }
     let (token, span) = parser.lookahead()?;
     if token == Token::SIGIL && parser.slice_at(span) == "->" {
@@ -972,10 +1307,10 @@ fn block_prefix(parser: &Parser) -> Parse {
     // Otherwise either Block or Dictionary. Easier to diverge
     // later than figure out up front.
     //
-    let res = match parser.lookahead() {
-        Ok((Token::KEYWORD, _)) => parse_record(parser)?,
-        _ => parse_block_or_dictionary(parser)?,
-    };
+    let res = parser.with_stop_words(&[], || match parser.lookahead() {
+        Ok((Token::KEYWORD, _)) => parse_record(parser),
+        _ => parse_block_or_dictionary(parser),
+    })?;
     Ok(Syntax::Expr(res))
 }
 
@@ -1040,6 +1375,83 @@ fn import_prefix(parser: &Parser) -> Parse {
     }
 }
 
+/// Parses an operator declaration:
+///
+///   operator <+> binary precedence: 4
+///   operator ~ prefix
+///
+/// and adds the operator to the name table for the rest of the source.
+/// Source level precedences run from 1 to 10, and map onto the builtin
+/// operator precedences from 10 to 100.
+fn operator_def_prefix(parser: &Parser) -> Parse {
+    let mut source_location = parser.source_location();
+    if parser.lookahead()?.0 != Token::SIGIL {
+        // Not a declaration: just a variable named operator.
+        return Ok(Syntax::Expr(Expr::Var(Var::untyped(
+            source_location,
+            parser.tokenstring(),
+            false,
+        ))));
+    }
+    parser.next_token()?;
+    let name = parser.tokenstring();
+    if let Some(ParserSyntax::General(..)) = parser.name_syntax(&name) {
+        return parser.error(&format!("Cannot declare {} as an operator", name));
+    }
+    let mut is_prefix = false;
+    let mut is_binary = false;
+    while let (Token::WORD, span) = parser.lookahead()? {
+        match parser.slice_at(span) {
+            "prefix" if !is_prefix => is_prefix = true,
+            "binary" if !is_binary => is_binary = true,
+            _ => break,
+        }
+        parser.next_token()?;
+    }
+    if !(is_prefix || is_binary) {
+        return Unwind::error_at(
+            parser.source_location(),
+            "Expected prefix or binary in operator declaration",
+        );
+    }
+    let precedence = match parser.lookahead()? {
+        (Token::KEYWORD, span) if parser.slice_at(span.clone()) == "precedence:" => {
+            parser.next_token()?;
+            if !is_binary {
+                return parser.error("Precedence declared for a non-binary operator");
+            }
+            parser.next_token()?;
+            let precedence = match parser.slice().parse::<usize>() {
+                Ok(n) if (1..=10).contains(&n) => n,
+                _ => return parser.error("Operator precedence must be an integer from 1 to 10"),
+            };
+            source_location.extend_span_to(parser.span().end);
+            precedence
+        }
+        _ if is_binary => {
+            return Unwind::error_at(
+                parser.source_location(),
+                "Expected precedence: for binary operator",
+            )
+        }
+        _ => {
+            source_location.extend_span_to(parser.span().end);
+            1
+        }
+    };
+    parser
+        .name_table
+        .borrow_mut()
+        .insert(name.clone(), ParserSyntax::Operator(is_prefix, is_binary, precedence * 10));
+    Ok(Syntax::Def(Def::OperatorDef(OperatorDef {
+        source_location,
+        name,
+        is_prefix,
+        is_binary,
+        precedence,
+    })))
+}
+
 fn interface_prefix(parser: &Parser) -> Parse {
     // FIXME: span is the span of the interface, but maybe it would be better if these
     // had all their own spans.
@@ -1185,6 +1597,13 @@ fn class_prefix(parser: &Parser) -> Parse {
             }
             continue;
         }
+        if next == Token::WORD && parser.slice() == "value" {
+            if class.value {
+                return parser.error("Multiple value declarations");
+            }
+            class.value = true;
+            continue;
+        }
         if next == Token::WORD && parser.slice() == "is" {
             if let Token::WORD = parser.next_token()? {
                 class.add_interface(parser.slice());
@@ -1225,6 +1644,84 @@ fn define_prefix(parser: &Parser) -> Parse {
     })))
 }
 
+/// Parses a macro definition:
+///
+///   macro Name keyword: param ...
+///       body!
+///
+/// The body is evaluated with the unevaluated arguments bound to the
+/// parameters as Syntax objects, and must return Syntax.
+fn macro_prefix(parser: &Parser) -> Parse {
+    if parser.lookahead()?.0 != Token::WORD {
+        // Not a definition: just a variable named macro.
+        return Ok(Syntax::Expr(Expr::Var(Var::untyped(
+            parser.source_location(),
+            parser.tokenstring(),
+            false,
+        ))));
+    }
+    parser.next_token()?;
+    let name = parser.tokenstring();
+    if !name.chars().next().expect("BUG: empty identifier").is_uppercase() {
+        return parser.error("Macro names must start with an uppercase letter");
+    }
+    let source_location = parser.source_location();
+    let mut selector = String::new();
+    let mut parameters = vec![];
+    match parser.next_token()? {
+        Token::WORD => selector.push_str(parser.slice()),
+        Token::KEYWORD => loop {
+            selector.push_str(parser.slice());
+            if Token::WORD != parser.next_token()? {
+                return parser.error("Expected macro parameter name");
+            }
+            parameters.push(parse_var(parser, false)?);
+            if Token::KEYWORD == parser.lookahead()?.0 {
+                parser.next_token()?;
+            } else {
+                break;
+            }
+        },
+        _ => return parser.error("Expected macro selector"),
+    }
+    let body = parser.parse_seq()?;
+    parser.next_token()?;
+    if "!" != parser.slice() {
+        return parser.error(&format!("Expected '!' after macro, got: '{}'", parser.slice()));
+    }
+    Ok(Syntax::Def(Def::MacroDef(MacroDef {
+        source_location,
+        name,
+        selector,
+        parameters,
+        body,
+    })))
+}
+
+/// Parses a syntax template: quote { expr }
+fn quote_prefix(parser: &Parser) -> Parse {
+    let mut source_location = parser.source_location();
+    match parser.lookahead()? {
+        (Token::SIGIL, span) if parser.slice_at(span.clone()) == "{" => parser.next_token()?,
+        _ => {
+            // Not a template: just a variable named quote.
+            return Ok(Syntax::Expr(Expr::Var(Var::untyped(
+                source_location,
+                parser.tokenstring(),
+                false,
+            ))));
+        }
+    };
+    let body = parser.with_stop_words(&[], || parser.parse_seq())?;
+    match parser.next_token()? {
+        Token::SIGIL if parser.slice() == "}" => (),
+        Token::EOF => return parser.eof_error("Unexpected EOF in quote: expected }"),
+        _ => return parser.error("Expected } after quoted expression"),
+    }
+    source_location.extend_span_to(parser.span().end);
+    Ok(Syntax::Expr(Quote::expr(source_location, body)))
+}
+
 fn extend_prefix(parser: &Parser) -> Parse {
     // FIXME: span is the span of the extension, but maybe it would be better if
     // these had all their own spans.
@@ -1284,6 +1781,21 @@ fn let_prefix(parser: &Parser) -> Parse {
     let mut next = parser.next_token()?;
     let mut source_location = parser.source_location();
 
+    if Token::SIGIL == next && ("{" == parser.slice() || "[" == parser.slice()) {
+        let pattern = parse_pattern(parser, next)?;
+        if !(parser.next_token()? == Token::SIGIL && parser.slice() == "=") {
+            return parser.error("Expected = in let");
+        }
+        let value = parser.parse_single()?;
+        let body = parse_let_body(parser)?;
+        return Ok(Syntax::Expr(Destructure::expr(
+            pattern.source_location(),
+            pattern,
+            Box::new(value),
+            body,
+        )));
+    }
+
     let dynamic = if Token::SIGIL == next && "$" == parser.slice() {
         next = parser.next_token()?;
         true
@@ -1308,6 +1820,11 @@ fn let_prefix(parser: &Parser) -> Parse {
     }
 
     let value = parser.parse_single()?;
+    let body = parse_let_body(parser)?;
+    Ok(Syntax::Expr(Bind::expr(source_location, name, typename, Box::new(value), body, dynamic)))
+}
+
+fn parse_let_body(parser: &Parser) -> Result<Option<Box<Expr>>, Unwind> {
     let mut eof = match parser.next_token()? {
         Token::SIGIL if parser.slice() == "." => false,
         Token::EOF => true,
@@ -1324,12 +1841,11 @@ fn let_prefix(parser: &Parser) -> Parse {
     }
     // FIXME: Should just return false here instead of playing with
     // an Option for body.
-    let body = if eof {
-        None
+    if eof {
+        Ok(None)
     } else {
-        Some(Box::new(parser.parse_seq()?))
-    };
-    Ok(Syntax::Expr(Bind::expr(source_location, name, typename, Box::new(value), body, dynamic)))
+        Ok(Some(Box::new(parser.parse_seq()?)))
+    }
 }
 
 fn ignore_prefix(parser: &Parser) -> Parse {
@@ -0,0 +1,614 @@
+use std::path::Path;
+
+use crate::def::*;
+use crate::expr::*;
+use crate::syntax::Syntax;
+
+// How loosely a printed expression binds, mirroring the precedences of the
+// parser: an expression is printed in parentheses where it would otherwise
+// bind less tightly than its position needs.
+//
+// Sequences and lets swallow everything up to the end of their sequence,
+// return and panic everything up to the next dot, and the rest up to the
+// first operator of at most their own precedence.
+const SEQ: usize = 0;
+const LET: usize = 1;
+const PREFIX: usize = 2;
+const CASCADE: usize = 3;
+const ASSIGN: usize = 4;
+const KEYWORD: usize = 9;
+const IS: usize = 10;
+const UNARY: usize = 1000;
+const PRIMARY: usize = 1001;
+
+/// Operators whose precedence isn't known here, because they are declared in
+/// source, are printed as if they bound less tightly than any other: at least
+/// as loosely as an undeclared operator.
+const UNKNOWN_OPERATOR: usize = 10;
+
+const INDENT: usize = 4;
+
+/// Foolang source for the toplevel definitions and expressions of a module.
+/// Relative imports are printed relative to root, as given to the parser.
+pub fn module_source(syntax: &[Syntax], root: &Path) -> String {
+    let mut source = String::new();
+    let mut previous = None;
+    for item in syntax {
+        // Imports are kept together, other items separated by a blank line.
+        let import = matches!(item, Syntax::Def(Def::ImportDef(_)));
+        match previous {
+            Some(true) if import => source.push('\n'),
+            Some(_) => source.push_str("\n\n"),
+            None => (),
+        }
+        source.push_str(&syntax_source(item, root));
+        previous = Some(import);
+    }
+    source.push('\n');
+    source
+}
+
+pub fn syntax_source(syntax: &Syntax, root: &Path) -> String {
+    match syntax {
+        Syntax::Def(def) => def_source(def, root),
+        Syntax::Expr(expr) => expr_source(expr),
+    }
+}
+
+pub fn def_source(def: &Def, root: &Path) -> String {
+    match def {
+        Def::ClassDef(class) => {
+            let mut vars: Vec<String> = class.instance_variables.iter().map(var).collect();
+            let mut head = vec![];
+            for interface in &class.interfaces {
+                head.push(format!("is {}", interface));
+            }
+            if class.value {
+                head.push("value".to_string());
+            }
+            if let Some(ctor) = &class.default_constructor {
+                head.push(format!("defaultConstructor {}", ctor));
+            }
+            if !vars.is_empty() {
+                vars.insert(0, String::new());
+                vars.push(String::new());
+            }
+            let methods = vec![
+                methods("direct method", &class.class_methods),
+                methods("method", &class.instance_methods),
+            ];
+            members(format!("class {} {{{}}}", class.name, vars.join(" ")), head, methods)
+        }
+        Def::DefineDef(define) => {
+            let init = body(&define.init, INDENT);
+            if init.contains('\n') {
+                format!("define {}\n{:indent$}{}!", define.name, "", init, indent = INDENT)
+            } else {
+                format!("define {} {}!", define.name, init)
+            }
+        }
+        Def::ExtensionDef(ext) => members(
+            format!("extend {}", ext.name),
+            ext.interfaces.iter().map(|interface| format!("is {}", interface)).collect(),
+            vec![
+                methods("direct method", &ext.class_methods),
+                methods("method", &ext.instance_methods),
+            ],
+        ),
+        Def::ImportDef(import) => format!("import {}", import_spec(import, root)),
+        Def::InterfaceDef(interface) => members(
+            format!("interface {}", interface.name),
+            interface.interfaces.iter().map(|interface| format!("is {}", interface)).collect(),
+            vec![
+                interface
+                    .required_methods
+                    .iter()
+                    .map(|method| format!("required method {}", signature(method)))
+                    .collect(),
+                methods("direct method", &interface.class_methods),
+                methods("method", &interface.instance_methods),
+            ],
+        ),
+        Def::MacroDef(mac) => {
+            let mut head = format!("macro {}", mac.name);
+            if mac.parameters.is_empty() {
+                head.push(' ');
+                head.push_str(&mac.selector);
+            } else {
+                for (part, param) in keywords(&mac.selector).iter().zip(&mac.parameters) {
+                    head.push_str(&format!(" {} {}", part, var(param)));
+                }
+            }
+            format!("{}\n{:indent$}{}!", head, "", body(&mac.body, INDENT), indent = INDENT)
+        }
+        Def::OperatorDef(op) => {
+            let mut source = format!("operator {}", op.name);
+            if op.is_prefix {
+                source.push_str(" prefix");
+            }
+            if op.is_binary {
+                source.push_str(&format!(" binary precedence: {}", op.precedence));
+            }
+            source
+        }
+    }
+}
+
+/// Foolang source for an expression, as it would be written in a method body.
+pub fn expr_source(expr: &Expr) -> String {
+    body(expr, 0)
+}
+
+fn members(head: String, lines: Vec<String>, groups: Vec<Vec<String>>) -> String {
+    let mut source = head;
+    for line in lines {
+        source.push_str(&format!("\n{:indent$}{}", "", line, indent = INDENT));
+    }
+    for member in groups.into_iter().flatten() {
+        source.push_str(&format!("\n\n{:indent$}{}", "", member, indent = INDENT));
+    }
+    source.push_str("\nend");
+    source
+}
+
+fn methods(kind: &str, methods: &[MethodDefinition]) -> Vec<String> {
+    methods
+        .iter()
+        .map(|method| {
+            let mut source = format!("{} {}", kind, signature(method));
+            if let Some(method_body) = &method.body {
+                source.push_str(&format!(
+                    "\n{:indent$}{}!",
+                    "",
+                    body(method_body, 2 * INDENT),
+                    indent = 2 * INDENT
+                ));
+            }
+            source
+        })
+        .collect()
+}
+
+fn signature(method: &MethodDefinition) -> String {
+    let selector = &method.selector;
+    let mut source = if selector.ends_with(':') {
+        let parts = keywords(selector);
+        let params = parts.iter().zip(&method.parameters);
+        params.map(|(part, param)| format!("{} {}", part, var(param))).collect::<Vec<_>>().join(" ")
+    } else if let Some(param) = method.parameters.first() {
+        format!("{} {}", selector, var(param))
+    } else {
+        selector.to_string()
+    };
+    if let Some(rtype) = &method.return_type {
+        source.push_str(&format!(" -> {}", rtype));
+    }
+    source
+}
+
+/// The name as written after import: the parser has already turned it into
+/// a path, from root for relative imports.
+fn import_spec(import: &ImportDef, root: &Path) -> String {
+    let (mut spec, path) = match import.path.strip_prefix(root) {
+        Ok(path) => (".".to_string(), path),
+        Err(_) => (String::new(), import.path.as_path()),
+    };
+    let path = path.with_extension("");
+    let parts: Vec<String> =
+        path.components().map(|part| part.as_os_str().to_string_lossy().to_string()).collect();
+    spec.push_str(&parts.join("."));
+    if let Some(name) = &import.name {
+        spec.push('.');
+        spec.push_str(name);
+    }
+    spec
+}
+
+fn keywords(selector: &str) -> Vec<String> {
+    selector.split_terminator(':').map(|part| format!("{}:", part)).collect()
+}
+
+fn var(var: &Var) -> String {
+    match &var.typename {
+        Some(typename) => format!("{}::{}", var.name, typename),
+        None => var.name.clone(),
+    }
+}
+
+/// Expression in a position that takes a whole sequence: method and block
+/// bodies and the like.
+fn body(expr: &Expr, indent: usize) -> String {
+    operand(expr, SEQ, indent)
+}
+
+/// Expression in a position that needs it to bind at least as tightly as
+/// need, parenthesized if it doesn't.
+fn operand(expr: &Expr, need: usize, indent: usize) -> String {
+    let (source, binding) = source(expr, indent);
+    parenthesize(source, binding < need)
+}
+
+fn parenthesize(source: String, parens: bool) -> String {
+    if parens {
+        format!("({})", source)
+    } else {
+        source
+    }
+}
+
+fn newline(indent: usize) -> String {
+    format!("\n{:indent$}", "", indent = indent)
+}
+
+/// Source for expr, and how loosely it binds.
+fn source(expr: &Expr, indent: usize) -> (String, usize) {
+    match expr {
+        Expr::Array(array) => {
+            let data: Vec<String> =
+                array.data.iter().map(|elt| operand(elt, PREFIX, indent)).collect();
+            (format!("[{}]", data.join(", ")), PRIMARY)
+        }
+        Expr::Assign(assign) => {
+            (format!("{} = {}", assign.name, operand(&assign.value, ASSIGN + 1, indent)), ASSIGN)
+        }
+        Expr::Bind(bind) => {
+            let name = match &bind.typename {
+                Some(typename) => format!("{}::{}", bind.name, typename),
+                None => bind.name.clone(),
+            };
+            let value = operand(&bind.value, PREFIX, indent);
+            (let_source(format!("let {} = {}", name, value), &bind.body, indent), LET)
+        }
+        Expr::Block(block) => {
+            let mut head = "{".to_string();
+            if !block.params.is_empty() {
+                let params: Vec<String> = block.params.iter().map(var).collect();
+                head.push_str(&format!(" |{}|", params.join(" ")));
+            }
+            if let Some(rtype) = &block.rtype {
+                head.push_str(&format!(" -> {}", rtype));
+            }
+            (braces(head, &block.body, indent), PRIMARY)
+        }
+        Expr::Cascade(cascade) => {
+            let mut source = operand(&cascade.receiver, ASSIGN, indent);
+            for chain in &cascade.chains {
+                source.push_str("; ");
+                source.push_str(&send_all(None, chain, indent).0);
+            }
+            (source, CASCADE)
+        }
+        Expr::Chain(chain) => chain_source(&chain.receiver, &chain.messages, indent),
+        Expr::Const(constant) => (literal(&constant.literal), PRIMARY),
+        Expr::Destructure(destructure) => {
+            let value = operand(&destructure.value, PREFIX, indent);
+            let head = format!("let {} = {}", pattern(&destructure.pattern), value);
+            (let_source(head, &destructure.body, indent), LET)
+        }
+        Expr::Dictionary(dictionary) => {
+            let assoc: Vec<String> = dictionary
+                .assoc
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{} -> {}",
+                        operand(key, PREFIX, indent),
+                        operand(value, PREFIX, indent)
+                    )
+                })
+                .collect();
+            (format!("{{{}}}", assoc.join(", ")), PRIMARY)
+        }
+        Expr::Eq(eq) => (
+            format!("{} is {}", operand(&eq.left, IS, indent), operand(&eq.right, IS + 1, indent)),
+            IS,
+        ),
+        Expr::Match(m) => {
+            let mut source = format!("{} match: {{", operand(&m.value, KEYWORD + 1, indent));
+            let indent = indent + INDENT;
+            for arm in &m.arms {
+                source.push_str(&newline(indent));
+                source.push_str(&format!("case {}", pattern(&arm.pattern)));
+                if let Some(guard) = &arm.guard {
+                    source.push_str(&format!(" where {}", operand(guard, PREFIX, indent)));
+                }
+                source.push_str(&format!(" -> {}", body(&arm.body, indent + INDENT)));
+            }
+            source.push_str(" }");
+            (source, KEYWORD)
+        }
+        Expr::Panic(panic) => (format!("panic {}", operand(&panic.value, PREFIX, indent)), PREFIX),
+        Expr::Quote(quote) => (braces("quote {".to_string(), &quote.body, indent), PRIMARY),
+        Expr::Return(ret) => (format!("return {}", operand(&ret.value, PREFIX, indent)), PREFIX),
+        Expr::Seq(seq) => {
+            let last = seq.exprs.len() - 1;
+            let exprs: Vec<String> = seq
+                .exprs
+                .iter()
+                .enumerate()
+                .map(|(i, expr)| {
+                    operand(
+                        expr,
+                        if i == last {
+                            LET
+                        } else {
+                            PREFIX
+                        },
+                        indent,
+                    )
+                })
+                .collect();
+            (exprs.join(&format!(".{}", newline(indent))), SEQ)
+        }
+        Expr::Typecheck(typecheck) => {
+            // $x::Type would be a typed dynamic variable instead.
+            let (source, binding) = source(&typecheck.expr, indent);
+            let dynamic = matches!(&*typecheck.expr, Expr::Var(var) if var.dynamic);
+            let source = parenthesize(source, binding < UNARY || dynamic);
+            (format!("{}::{}", source, typecheck.typename), UNARY)
+        }
+        Expr::Var(v) => (var(v), PRIMARY),
+    }
+}
+
+fn let_source(head: String, body: &Option<Box<Expr>>, indent: usize) -> String {
+    match body {
+        Some(body) => format!("{}.{}{}", head, newline(indent), operand(body, SEQ, indent)),
+        None => head,
+    }
+}
+
+/// Braces around body after head, on one line if the body fits on one.
+fn braces(head: String, body: &Expr, indent: usize) -> String {
+    let inner = operand(body, SEQ, indent + INDENT);
+    if inner.contains('\n') {
+        format!("{}{}{} }}", head, newline(indent + INDENT), inner)
+    } else {
+        format!("{} {} }}", head, inner)
+    }
+}
+
+fn literal(literal: &Literal) -> String {
+    match literal {
+        Literal::Boolean(true) => "True".to_string(),
+        Literal::Boolean(false) => "False".to_string(),
+        Literal::Integer(i) => i.to_string(),
+        Literal::Float(x) => format!("{:?}", x),
+        Literal::String(s) => format!("\"{}\"", escape(s)),
+        Literal::Selector(s) => format!("#{}", s),
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '{' => escaped.push_str("\\{"),
+            '}' => escaped.push_str("\\}"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn pattern(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Wildcard(_) => "_".to_string(),
+        Pattern::Var(v) => var(v),
+        Pattern::Literal(constant) => literal(&constant.literal),
+        Pattern::Record(_, fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key, self::pattern(value)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Pattern::Array(_, elements) => {
+            let elements: Vec<String> = elements.iter().map(self::pattern).collect();
+            format!("[{}]", elements.join(", "))
+        }
+    }
+}
+
+/// Source for messages sent in order to receiver. The parser desugars
+/// records, string interpolation, and comprehensions into message sends:
+/// those are printed back as written.
+fn chain_source(receiver: &Expr, messages: &[Message], indent: usize) -> (String, usize) {
+    if messages.is_empty() {
+        return source(receiver, indent);
+    }
+    let (start, rest) = match receiver {
+        Expr::Var(var) if var.name == "Record" && var.typename.is_none() => {
+            match record(&messages[0], indent) {
+                Some(record) => (record, &messages[1..]),
+                None => (source(receiver, indent), messages),
+            }
+        }
+        Expr::Const(Const {
+            literal: Literal::String(first),
+            ..
+        }) => {
+            let (interpolation, n) = interpolation(first, messages, indent);
+            ((interpolation, PRIMARY), &messages[n..])
+        }
+        Expr::Block(block) => match comprehension(block, &messages[0], indent) {
+            Some(comprehension) => ((comprehension, PRIMARY), &messages[1..]),
+            None => (source(receiver, indent), messages),
+        },
+        _ => (source(receiver, indent), messages),
+    };
+    send_all(Some(start), rest, indent)
+}
+
+/// Sends messages to receiver, or the receiver of a cascade if none.
+fn send_all(
+    receiver: Option<(String, usize)>,
+    messages: &[Message],
+    indent: usize,
+) -> (String, usize) {
+    let mut receiver = receiver;
+    for message in messages {
+        receiver = Some(send(receiver, message, indent));
+    }
+    receiver.unwrap_or_else(|| (String::new(), PRIMARY))
+}
+
+fn send(receiver: Option<(String, usize)>, message: &Message, indent: usize) -> (String, usize) {
+    let selector = &message.selector;
+    let args = &message.args;
+    // Receivers of cascades cannot be parenthesized, they're implicit.
+    let left = |need: usize| match &receiver {
+        Some((source, binding)) => format!("{} ", parenthesize(source.clone(), *binding < need)),
+        None => String::new(),
+    };
+    if selector.ends_with(':') {
+        let parts = keywords(selector);
+        let args = parts
+            .iter()
+            .zip(args)
+            .map(|(part, arg)| format!("{} {}", part, operand(arg, KEYWORD + 1, indent)));
+        (format!("{}{}", left(KEYWORD + 1), args.collect::<Vec<_>>().join(" ")), KEYWORD)
+    } else if let Some(arg) = args.first() {
+        let (left_need, right, binding) = match operator_precedence(selector) {
+            Some(precedence) => (precedence, precedence + 1, precedence),
+            None => (UNARY, UNARY, UNKNOWN_OPERATOR),
+        };
+        (format!("{}{} {}", left(left_need), selector, operand(arg, right, indent)), binding)
+    } else if let Some(operator) = prefix_operator(selector) {
+        let (source, binding) = receiver.unwrap_or_else(|| (String::new(), PRIMARY));
+        // Also avoids gluing the operators together, as in --x.
+        let parens = binding < PRIMARY || source.starts_with(is_operator_char);
+        (format!("{}{}", operator, parenthesize(source, parens)), PRIMARY)
+    } else {
+        (format!("{}{}", left(UNARY), selector), UNARY)
+    }
+}
+
+fn is_operator_char(c: char) -> bool {
+    !(c.is_alphanumeric() || c == '_' || c.is_whitespace() || "()[]{},.;$!#\"".contains(c))
+}
+
+fn prefix_operator(selector: &str) -> Option<&str> {
+    match selector.strip_prefix("prefix") {
+        Some(operator) if operator.starts_with(is_operator_char) => Some(operator),
+        _ => None,
+    }
+}
+
+/// Precedence of the builtin binary operators, as in the parser.
+fn operator_precedence(operator: &str) -> Option<usize> {
+    let precedence = match operator {
+        "^" => 100,
+        "*" | "/" | "%" => 90,
+        "+" | "-" => 80,
+        "<<" | ">>" => 70,
+        "&" | "|" => 60,
+        "<" | "<=" | ">" | ">=" | "==" | "!=" => 50,
+        "&&" => 40,
+        "||" => 30,
+        _ => return None,
+    };
+    Some(precedence)
+}
+
+/// {x: 1, y: 2} is parsed as Record x: 1 y: 2.
+fn record(message: &Message, indent: usize) -> Option<(String, usize)> {
+    if !message.selector.ends_with(':') {
+        return None;
+    }
+    let fields: Vec<String> = keywords(&message.selector)
+        .iter()
+        .zip(&message.args)
+        .map(|(key, value)| format!("{} {}", key, operand(value, PREFIX, indent)))
+        .collect();
+    Some((format!("{{{}}}", fields.join(", ")), PRIMARY))
+}
+
+/// "a{x}b" is parsed as "a" append: x toString append: "b" toString.
+/// Returns the string and the number of messages it covers.
+fn interpolation(first: &str, messages: &[Message], indent: usize) -> (String, usize) {
+    let mut source = format!("\"{}", escape(first));
+    let mut n = 0;
+    while n + 1 < messages.len() {
+        let interpolated = match to_string_of(&messages[n]) {
+            Some((receiver, sends)) => chain_source(receiver, sends, indent).0,
+            None => break,
+        };
+        // Interpolated expressions cannot contain strings: the string would
+        // end at the first quote.
+        if interpolated.contains('"') || interpolated.contains('\\') {
+            break;
+        }
+        let literal = match to_string_of(&messages[n + 1]) {
+            Some((
+                Expr::Const(Const {
+                    literal: Literal::String(literal),
+                    ..
+                }),
+                [],
+            )) => literal,
+            _ => break,
+        };
+        source.push_str(&format!("{{{}}}{}", interpolated, escape(literal)));
+        n += 2;
+    }
+    source.push('"');
+    (source, n)
+}
+
+/// Receiver and the other messages of x in append: x toString.
+fn to_string_of(message: &Message) -> Option<(&Expr, &[Message])> {
+    if message.selector != "append:" {
+        return None;
+    }
+    match &message.args[0] {
+        Expr::Chain(chain) => match chain.messages.split_last() {
+            Some((last, sends)) if last.selector == "toString" => Some((&chain.receiver, sends)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// [expr for x in xs where test] is parsed as a block collecting the values
+/// in a list, called with List new.
+fn comprehension(block: &Block, message: &Message, indent: usize) -> Option<String> {
+    const RESULT: &str = "#comprehension";
+    let is_result = |expr: &Expr| matches!(expr, Expr::Var(var) if var.name == RESULT);
+    if block.params.len() != 1 || block.params[0].name != RESULT || message.selector != "value:" {
+        return None;
+    }
+    let mut expr = match &*block.body {
+        Expr::Seq(seq) if seq.exprs.len() == 2 => &seq.exprs[0],
+        _ => return None,
+    };
+    let mut clauses = String::new();
+    let collect = loop {
+        let chain = match expr {
+            Expr::Chain(chain) => chain,
+            _ => return None,
+        };
+        let (last, sends) = chain.messages.split_last()?;
+        if is_result(&chain.receiver) && sends.is_empty() && last.selector == "add:" {
+            break operand(&last.args[0], PREFIX, indent);
+        }
+        let inner = match last.args.first() {
+            Some(Expr::Block(inner)) if inner.rtype.is_none() => inner,
+            _ => return None,
+        };
+        let (clause, binding) = chain_source(&chain.receiver, sends, indent);
+        let clause = parenthesize(clause, binding < PREFIX);
+        match (last.selector.as_str(), inner.params.as_slice()) {
+            ("do:", [param]) => clauses.push_str(&format!(" for {} in {}", var(param), clause)),
+            ("ifTrue:", []) => clauses.push_str(&format!(" where {}", clause)),
+            _ => return None,
+        }
+        expr = &inner.body;
+    };
+    Some(format!("[{}{}]", collect, clauses))
+}
@@ -0,0 +1,214 @@
+use crate::expr::*;
+
+/// Frame of an activation of a method or block, or a scope frame opened by
+/// a binding inside one.
+struct Frame {
+    /// Names in scope and their slots, innermost last.
+    scope: Vec<(String, usize)>,
+    /// Names of the slots allocated so far, by index.
+    names: Vec<String>,
+    /// True once a block which captures the frame can exist: later bindings
+    /// go into a new frame, so that the block does not keep them alive.
+    captured: bool,
+}
+
+/// Assigns lexical addresses to the variables of a method or block body:
+/// each parameter and binding gets a slot in a flat frame, and each
+/// variable reference the depth and index of its slot.
+///
+/// Bindings share the frame of their activation unless a block was created
+/// in it first: a block captures its whole frame, which previously held
+/// only the bindings in scope where the block appears.
+struct Resolver {
+    frames: Vec<Frame>,
+}
+
+/// Resolves the body of a method or block with the given parameters, which
+/// get the first slots. Returns the slots its frame needs.
+pub fn resolve<'a>(params: impl IntoIterator<Item = &'a str>, body: &mut Expr) -> SlotNames {
+    let mut resolver = Resolver {
+        frames: Vec::new(),
+    };
+    resolver.activation(params, body)
+}
+
+impl Resolver {
+    fn activation<'a>(
+        &mut self,
+        params: impl IntoIterator<Item = &'a str>,
+        body: &mut Expr,
+    ) -> SlotNames {
+        self.push();
+        for param in params {
+            self.declare(param);
+        }
+        self.expr(body);
+        self.frames.pop().expect("BUG: no frame").names.into()
+    }
+
+    fn push(&mut self) {
+        self.frames.push(Frame {
+            scope: Vec::new(),
+            names: Vec::new(),
+            captured: false,
+        });
+    }
+
+    fn current(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("BUG: no frame")
+    }
+
+    /// Opens a scope frame if the current frame has been captured.
+    fn open(&mut self) -> bool {
+        if self.current().captured {
+            self.push();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Closes the scope frame opened last, returning its slots.
+    fn close(&mut self) -> SlotNames {
+        let frame = self.frames.pop().expect("BUG: no frame");
+        if frame.captured {
+            self.current().captured = true;
+        }
+        frame.names.into()
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        let frame = self.current();
+        let slot = frame.names.len();
+        frame.names.push(name.to_string());
+        frame.scope.push((name.to_string(), slot));
+        slot
+    }
+
+    fn mark(&mut self) -> usize {
+        self.current().scope.len()
+    }
+
+    fn release(&mut self, mark: usize) {
+        self.current().scope.truncate(mark);
+    }
+
+    fn lookup(&self, name: &str) -> Address {
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            if let Some((_, index)) = frame.scope.iter().rev().find(|(n, _)| n == name) {
+                return Address::Local {
+                    depth,
+                    index: *index,
+                };
+            }
+        }
+        Address::Free
+    }
+
+    /// Declares the variables of pattern, and resolves body in their scope.
+    /// Returns the slots of the frame opened for them, if any.
+    fn pattern(
+        &mut self,
+        pattern: &mut Pattern,
+        body: &mut dyn FnMut(&mut Self),
+    ) -> Option<SlotNames> {
+        let opened = self.open();
+        let mark = self.mark();
+        pattern.for_each_variable_mut(&mut |var| {
+            var.address = Some(Address::Local {
+                depth: 0,
+                index: self.declare(&var.name),
+            })
+        });
+        body(self);
+        self.release(mark);
+        if opened {
+            Some(self.close())
+        } else {
+            None
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Array(array) => {
+                let mark = self.mark();
+                for elt in &mut array.data {
+                    self.expr(elt);
+                }
+                self.release(mark);
+            }
+            Expr::Assign(assign) => {
+                self.expr(&mut assign.value);
+                assign.address = Some(self.lookup(&assign.name));
+            }
+            Expr::Bind(bind) => {
+                self.expr(&mut bind.value);
+                if bind.dynamic {
+                    if let Some(body) = &mut bind.body {
+                        self.expr(body);
+                    }
+                    return;
+                }
+                let opened = self.open();
+                let mark = self.mark();
+                bind.slot = Some(self.declare(&bind.name));
+                if let Some(body) = &mut bind.body {
+                    self.expr(body);
+                }
+                self.release(mark);
+                if opened {
+                    bind.frame = Some(self.close());
+                }
+            }
+            Expr::Block(block) => {
+                for (index, param) in block.params.iter_mut().enumerate() {
+                    param.address = Some(Address::Local {
+                        depth: 0,
+                        index,
+                    });
+                }
+                let params: Vec<String> = block.params.iter().map(|p| p.name.clone()).collect();
+                let slots = self.activation(params.iter().map(|p| p.as_str()), &mut block.body);
+                block.frame = Some(slots);
+                self.current().captured = true;
+            }
+            Expr::Destructure(destructure) => {
+                self.expr(&mut destructure.value);
+                let body = &mut destructure.body;
+                destructure.frame = self.pattern(&mut destructure.pattern, &mut |resolver| {
+                    if let Some(body) = body {
+                        resolver.expr(body);
+                    }
+                });
+            }
+            Expr::Match(m) => {
+                self.expr(&mut m.value);
+                for arm in &mut m.arms {
+                    let guard = &mut arm.guard;
+                    let body = &mut arm.body;
+                    arm.frame = self.pattern(&mut arm.pattern, &mut |resolver| {
+                        if let Some(guard) = guard {
+                            resolver.expr(guard);
+                        }
+                        resolver.expr(body);
+                    });
+                }
+            }
+            // Templates are resolved as part of the code they are spliced
+            // into.
+            Expr::Quote(_) => (),
+            Expr::Var(var) => {
+                if !var.dynamic {
+                    var.address = Some(self.lookup(&var.name));
+                }
+            }
+            _ => {
+                let _: Result<(), ()> = expr.for_each_subexpr_mut(&mut |subexpr| {
+                    self.expr(subexpr);
+                    Ok(())
+                });
+            }
+        }
+    }
+}
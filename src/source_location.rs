@@ -49,6 +49,12 @@ impl SourceLocation {
             SourceLocation::Path(path) => path.span.clone(),
         }
     }
+    pub fn file(&self) -> Option<&Rc<PathBuf>> {
+        match &self {
+            SourceLocation::Span(_) => None,
+            SourceLocation::Path(path) => Some(&path.path),
+        }
+    }
     pub fn set_span(&mut self, span: &Span) {
         *self = match &self {
             SourceLocation::Span(_) => SourceLocation::Span(span.clone()),
@@ -99,3 +105,39 @@ impl SourcePath {
         self.span.tweak(shift, extend)
     }
 }
+
+/// Maps offsets in a file to line numbers.
+pub struct Lines {
+    starts: Vec<usize>,
+}
+
+impl Lines {
+    pub fn new(code: &str) -> Lines {
+        let mut starts = vec![0];
+        starts.extend(code.match_indices('\n').map(|(i, _)| i + 1));
+        Lines {
+            starts,
+        }
+    }
+
+    /// One-based line of offset.
+    pub fn line(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    /// One-based column of offset.
+    pub fn column(&self, offset: usize) -> usize {
+        offset - self.starts[self.line(offset) - 1] + 1
+    }
+
+    /// Offset where the one-based line starts, if there is such a line.
+    pub fn start(&self, line: usize) -> Option<usize> {
+        match line {
+            0 => None,
+            _ => self.starts.get(line - 1).copied(),
+        }
+    }
+}
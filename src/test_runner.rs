@@ -0,0 +1,346 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::eval::Env;
+use crate::objects::{Datum, Object};
+use crate::unwind::Unwind;
+
+/// Module holding the Assert class the test suites are run with.
+const ASSERT_MODULE: &str = "lib/assert.foo";
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Pass,
+    /// Some assertion failed: the details are in the output of the test.
+    Fail,
+    /// The test panicked, or could not be run.
+    Error(String),
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub file: PathBuf,
+    pub suite: String,
+    pub name: String,
+    pub time: Duration,
+    pub outcome: Outcome,
+    /// Everything the test and its assertions printed.
+    pub output: String,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.outcome == Outcome::Pass
+    }
+}
+
+/// Finds the test modules in paths: files named test_*.foo in directories
+/// and their subdirectories which mention TestSuite, in order. Files given
+/// directly are included whatever their name and contents.
+pub fn discover(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            discover_in(path, &mut files)?;
+        } else if path.is_file() {
+            files.push(path.clone());
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no such file or directory: {}", path.display()),
+            ));
+        }
+    }
+    Ok(files)
+}
+
+fn discover_in(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        entries.push(entry?.path());
+    }
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            discover_in(&path, files)?;
+        } else if is_test_module(&path)? {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Other test_*.foo files are programs used by tests, which need not even
+/// load: they are skipped without loading them.
+fn is_test_module(path: &Path) -> io::Result<bool> {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    if !(name.starts_with("test_") && name.ends_with(".foo")) {
+        return Ok(false);
+    }
+    Ok(fs::read_to_string(path)?.contains("TestSuite"))
+}
+
+/// Runs the tests of every class implementing TestSuite in files, one test
+/// method at a time. A test is run if its full name, Suite.selector,
+/// contains one of filters, or if there are no filters.
+///
+/// Each test gets a new instance of its suite, made with #assert:, and a
+/// System whose output is captured along with that of the assertions.
+pub fn run_tests(env: &Env, files: &[PathBuf], filters: &[String]) -> Vec<TestResult> {
+    let mut runner = Runner {
+        env,
+        filters,
+        seen: Vec::new(),
+        results: Vec::new(),
+    };
+    for file in files {
+        if let Err(unwind) = runner.run_module(file) {
+            runner.results.push(TestResult {
+                file: file.clone(),
+                suite: file.display().to_string(),
+                name: "load".to_string(),
+                time: Duration::default(),
+                outcome: Outcome::Error(unwind.to_string()),
+                output: String::new(),
+            });
+        }
+    }
+    runner.results
+}
+
+struct Runner<'a> {
+    env: &'a Env,
+    filters: &'a [String],
+    /// Suites already run: a suite imported by another test module is
+    /// only run once.
+    seen: Vec<Object>,
+    results: Vec<TestResult>,
+}
+
+impl<'a> Runner<'a> {
+    fn run_module(&mut self, file: &Path) -> Result<(), Unwind> {
+        let path = match fs::canonicalize(file) {
+            Ok(path) => path,
+            Err(err) => return Unwind::error(&format!("Cannot load {}: {}", file.display(), err)),
+        };
+        let module = self.env.load_module(path)?;
+        let assert = self.env.load_module(ASSERT_MODULE)?.find_global_or_unwind("Assert")?;
+        for (name, suite) in self.suites(&module)? {
+            let mut tests: Vec<String> = match &suite.datum {
+                Datum::Class(class) => class
+                    .instance_vtable
+                    .selectors()
+                    .into_iter()
+                    .filter(|selector| selector.starts_with("test") && !selector.contains(':'))
+                    .collect(),
+                _ => unreachable!(),
+            };
+            tests.sort();
+            for test in tests {
+                let full_name = format!("{}.{}", name, test);
+                if !self.filters.is_empty()
+                    && !self.filters.iter().any(|filter| full_name.contains(filter.as_str()))
+                {
+                    continue;
+                }
+                let result = self.run_test(file, &name, &suite, &test, &assert)?;
+                self.results.push(result);
+            }
+        }
+        Ok(())
+    }
+
+    /// Concrete classes implementing TestSuite in module, by name.
+    fn suites(&mut self, module: &Env) -> Result<Vec<(String, Object)>, Unwind> {
+        let env = self.env;
+        let test_suite = env.find_global_or_unwind("TestSuite")?;
+        let mut suites = Vec::new();
+        for (name, value) in module.globals() {
+            match &value.datum {
+                Datum::Class(class) if !class.interface && !name.contains('.') => (),
+                _ => continue,
+            }
+            if self.seen.contains(&value) {
+                continue;
+            }
+            let interfaces = value.send("interfaces", &[], env)?;
+            if interfaces.as_vec(|vec| Ok(vec.contains(&test_suite)))? {
+                self.seen.push(value.clone());
+                suites.push((name, value));
+            }
+        }
+        suites.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(suites)
+    }
+
+    fn run_test(
+        &self,
+        file: &Path,
+        name: &str,
+        suite: &Object,
+        test: &str,
+        assert: &Object,
+    ) -> Result<TestResult, Unwind> {
+        let env = self.env;
+        let output = env.find_global_or_unwind("StringOutput")?.send("new", &[], env)?;
+        let assert = assert.send("reportingTo:", std::slice::from_ref(&output), env)?;
+        let system = env.foo.make_system(Some(output.clone()));
+        let start = Instant::now();
+        let res = suite.send("assert:", std::slice::from_ref(&assert), env).and_then(|instance| {
+            instance.send("system:", &[system], env)?;
+            instance.send(test, &[], env)
+        });
+        let time = start.elapsed();
+        let outcome = match res {
+            Err(unwind) => Outcome::Error(unwind.to_string()),
+            Ok(_) if assert.send("passed", &[], env)?.boolean() => Outcome::Pass,
+            Ok(_) => Outcome::Fail,
+        };
+        Ok(TestResult {
+            file: file.to_path_buf(),
+            suite: name.to_string(),
+            name: test.to_string(),
+            time,
+            outcome,
+            output: output.send("content", &[], env)?.as_str()?.trim().to_string(),
+        })
+    }
+}
+
+/// Writes results in the Test Anything Protocol, version 13. Output of
+/// failed tests goes into their YAML diagnostics.
+pub fn write_tap(results: &[TestResult], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "TAP version 13")?;
+    writeln!(out, "1..{}", results.len())?;
+    for (i, result) in results.iter().enumerate() {
+        let status = if result.passed() {
+            "ok"
+        } else {
+            "not ok"
+        };
+        writeln!(
+            out,
+            "{} {} - {}.{} # time={:.3}s",
+            status,
+            i + 1,
+            result.suite,
+            result.name,
+            result.time.as_secs_f64()
+        )?;
+        let message = match &result.outcome {
+            Outcome::Pass => continue,
+            Outcome::Fail => "assertion failed",
+            Outcome::Error(_) => "error",
+        };
+        writeln!(out, "  ---")?;
+        writeln!(out, "  message: {}", message)?;
+        writeln!(out, "  file: {}", result.file.display())?;
+        if let Outcome::Error(what) = &result.outcome {
+            write_yaml_block(out, "error", what)?;
+        }
+        if !result.output.is_empty() {
+            write_yaml_block(out, "output", &result.output)?;
+        }
+        writeln!(out, "  ...")?;
+    }
+    Ok(())
+}
+
+fn write_yaml_block(out: &mut dyn Write, key: &str, text: &str) -> io::Result<()> {
+    writeln!(out, "  {}: |", key)?;
+    for line in text.trim_end().lines() {
+        writeln!(out, "    {}", line)?;
+    }
+    Ok(())
+}
+
+/// Writes results as JUnit XML, with a testsuite element per suite.
+pub fn write_junit(results: &[TestResult], out: &mut dyn Write) -> io::Result<()> {
+    let failures = results.iter().filter(|r| r.outcome == Outcome::Fail).count();
+    let errors = results.iter().filter(|r| matches!(r.outcome, Outcome::Error(_))).count();
+    let time: Duration = results.iter().map(|r| r.time).sum();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<testsuites tests="{}" failures="{}" errors="{}" time="{:.3}">"#,
+        results.len(),
+        failures,
+        errors,
+        time.as_secs_f64()
+    )?;
+    let mut start = 0;
+    while start < results.len() {
+        let suite = &results[start].suite;
+        let end = start + results[start..].iter().take_while(|r| &r.suite == suite).count();
+        write_junit_suite(&results[start..end], out)?;
+        start = end;
+    }
+    writeln!(out, "</testsuites>")
+}
+
+fn write_junit_suite(results: &[TestResult], out: &mut dyn Write) -> io::Result<()> {
+    let first = &results[0];
+    let failures = results.iter().filter(|r| r.outcome == Outcome::Fail).count();
+    let errors = results.iter().filter(|r| matches!(r.outcome, Outcome::Error(_))).count();
+    let time: Duration = results.iter().map(|r| r.time).sum();
+    writeln!(
+        out,
+        r#"  <testsuite name="{}" file="{}" tests="{}" failures="{}" errors="{}" time="{:.3}">"#,
+        xml_escape(&first.suite),
+        xml_escape(&first.file.display().to_string()),
+        results.len(),
+        failures,
+        errors,
+        time.as_secs_f64()
+    )?;
+    for result in results {
+        write!(
+            out,
+            r#"    <testcase classname="{}" name="{}" time="{:.3}""#,
+            xml_escape(&result.suite),
+            xml_escape(&result.name),
+            result.time.as_secs_f64()
+        )?;
+        if result.passed() && result.output.is_empty() {
+            writeln!(out, "/>")?;
+            continue;
+        }
+        writeln!(out, ">")?;
+        match &result.outcome {
+            Outcome::Pass => (),
+            Outcome::Fail => writeln!(
+                out,
+                r#"      <failure message="assertion failed">{}</failure>"#,
+                xml_escape(&result.output)
+            )?,
+            Outcome::Error(what) => writeln!(
+                out,
+                r#"      <error message="{}">{}</error>"#,
+                xml_escape(what.lines().next().unwrap_or("error")),
+                xml_escape(what)
+            )?,
+        }
+        if result.outcome != Outcome::Fail && !result.output.is_empty() {
+            writeln!(out, "      <system-out>{}</system-out>", xml_escape(&result.output))?;
+        }
+        writeln!(out, "    </testcase>")?;
+    }
+    writeln!(out, "  </testsuite>")
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
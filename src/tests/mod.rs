@@ -1,11 +1,23 @@
 mod test_array;
+mod test_benchmark;
 mod test_block;
 mod test_boolean;
 mod test_clock;
 mod test_compiler;
+mod test_debugger;
+mod test_dump;
 mod test_eval;
 mod test_float;
+mod test_generator;
+mod test_heap;
 mod test_integer;
+mod test_isolate;
 mod test_parser;
+mod test_pretty;
+mod test_property;
+mod test_resolve;
 mod test_string;
+mod test_tail_call;
 mod test_tokenstream;
+mod test_trace;
+mod test_wrapper;
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::benchmark::{from_json, regressions, to_json, BenchmarkResult, Options, Stats};
+use crate::isolate::Isolate;
+
+fn millis(times: &[u64]) -> Vec<Duration> {
+    times.iter().map(|ms| Duration::from_millis(*ms)).collect()
+}
+
+fn result(name: &str, median: f64) -> BenchmarkResult {
+    BenchmarkResult {
+        file: PathBuf::from("suite.foo"),
+        name: name.to_string(),
+        stats: Stats {
+            iterations: 1,
+            mean: median,
+            median,
+            stddev: 0.0,
+        },
+    }
+}
+
+#[test]
+fn test_stats() {
+    let stats = Stats::from_times(&millis(&[4, 1, 3, 2]));
+    assert_eq!(stats.iterations, 4);
+    assert!((stats.mean - 0.0025).abs() < 1e-9);
+    assert!((stats.median - 0.0025).abs() < 1e-9);
+    assert!((stats.stddev - 0.0012909944).abs() < 1e-9);
+    let stats = Stats::from_times(&millis(&[5, 1, 3]));
+    assert!((stats.median - 0.003).abs() < 1e-9);
+    assert_eq!(Stats::from_times(&millis(&[7])).stddev, 0.0);
+}
+
+#[test]
+fn test_baseline_json() {
+    let results = vec![result("A", 0.5), result("B", 0.25)];
+    assert_eq!(from_json(&to_json(&results)), Ok(results));
+    assert!(from_json(&serde_json::json!({})).is_err());
+    assert!(from_json(&serde_json::json!({"benchmarks": [{"name": "A"}]})).is_err());
+}
+
+#[test]
+fn test_regressions() {
+    let baseline = vec![result("Same", 1.0), result("Slower", 1.0), result("Faster", 1.0)];
+    let results = vec![
+        result("Same", 1.05),
+        result("Slower", 1.2),
+        result("Faster", 0.5),
+        result("New", 9.0),
+    ];
+    let slower: Vec<&str> =
+        regressions(&results, &baseline, 0.1).iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(slower, vec!["Slower"]);
+    assert!(regressions(&results, &baseline, 0.5).is_empty());
+}
+
+#[test]
+fn test_run_benchmarks() {
+    let mut roots = HashMap::new();
+    roots.insert(".".to_string(), std::env::current_dir().unwrap());
+    let isolate = Isolate::new(Path::new("foo/lang/bootstrap_prelude.foo"), roots).unwrap();
+    let options = Options {
+        warmup: 0,
+        iterations: 3,
+        filters: vec!["Fact".to_string(), "Fib".to_string()],
+        ..Options::default()
+    };
+    let results =
+        isolate.benchmark(&[PathBuf::from("foo/tests/test_benchmarks.foo")], &options).unwrap();
+    let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["Factorial", "Fibonacci"]);
+    assert!(results.iter().all(|r| r.stats.iterations == 3 && r.stats.median > 0.0));
+    assert!(isolate.benchmark(&[PathBuf::from("foo/tests/nonexistent.foo")], &options).is_err());
+}
+
+#[test]
+fn test_benchmark_files_are_canonical() {
+    let mut roots = HashMap::new();
+    roots.insert(".".to_string(), std::env::current_dir().unwrap());
+    let isolate = Isolate::new(Path::new("foo/lang/bootstrap_prelude.foo"), roots).unwrap();
+    let options = Options {
+        warmup: 0,
+        iterations: 1,
+        filters: vec!["Fact".to_string()],
+        ..Options::default()
+    };
+    let files = [
+        PathBuf::from("foo/tests/test_benchmarks.foo"),
+        PathBuf::from("./foo/tests/test_benchmarks.foo"),
+        PathBuf::from("foo/lib/../tests/test_benchmarks.foo"),
+        std::env::current_dir().unwrap().join("foo/tests/test_benchmarks.foo"),
+    ];
+    let results = isolate.benchmark(&files, &options).unwrap();
+    assert_eq!(results.len(), 4);
+    assert!(results.iter().all(|r| r.file == Path::new("foo/tests/test_benchmarks.foo")));
+    let baseline = from_json(&to_json(&results[..1])).unwrap();
+    assert_eq!(regressions(&results[1..], &baseline, -1.0).len(), 3);
+}
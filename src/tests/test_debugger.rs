@@ -0,0 +1,17 @@
+use std::path::PathBuf;
+
+use crate::debugger::Breakpoint;
+
+#[test]
+fn test_parse_breakpoint() {
+    assert_eq!(
+        Breakpoint::parse("foo/lib/assert.foo:12"),
+        Ok(Breakpoint::Line(PathBuf::from("foo/lib/assert.foo"), 12))
+    );
+    assert_eq!(
+        Breakpoint::parse(" Assert##that:is:equal: "),
+        Ok(Breakpoint::Method("Assert##that:is:equal:".to_string()))
+    );
+    assert!(Breakpoint::parse("foo/lib/assert.foo").is_err());
+    assert!(Breakpoint::parse("foo/lib/assert.foo:x").is_err());
+}
@@ -0,0 +1,76 @@
+use crate::dump::{self, expr_node};
+use crate::parse::Parser;
+use crate::syntax::Syntax;
+use crate::tokenstream::Token;
+
+fn parse(source: &str) -> Syntax {
+    Parser::new(source, "test/").parse().unwrap()
+}
+
+#[test]
+fn test_dump_tokens() {
+    let source = "x foo: 1.5 -- done";
+    let tokens = dump::tokens(source).unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            (Token::WORD, 0..1),
+            (Token::KEYWORD, 2..6),
+            (Token::DOUBLE_FLOAT, 7..10),
+            (Token::COMMENT, 11..18),
+            (Token::EOF, 18..18),
+        ]
+    );
+    assert_eq!(dump::tokens_text(source, &tokens[..2]), "0..1 WORD \"x\"\n2..6 KEYWORD \"foo:\"\n");
+    assert_eq!(
+        dump::tokens_json(source, &tokens[1..2]),
+        serde_json::json!([{"token": "KEYWORD", "span": [2, 6], "slice": "foo:"}])
+    );
+}
+
+#[test]
+fn test_dump_expr() {
+    let node = match parse("let a = [1, #b:]. { |x| a } value: a") {
+        Syntax::Expr(expr) => expr_node(&expr),
+        Syntax::Def(def) => panic!("unexpected {:?}", def),
+    };
+    assert_eq!(
+        node.to_text(),
+        "Bind 4..5 name=\"a\" dynamic=false
+  value: Array 8..16
+    data:
+      Const 9..10 literal=\"Integer\" value=1
+      Const 12..15 literal=\"Selector\" value=\"b:\"
+  body: Chain 18..36
+    receiver: Block 18..27
+      params:
+        Var 21..22 name=\"x\" dynamic=false
+      body: Var 24..25 name=\"a\" dynamic=false
+    messages:
+      Message 28..36 selector=\"value:\"
+        args:
+          Var 35..36 name=\"a\" dynamic=false
+"
+    );
+    let json = node.to_json();
+    assert_eq!(json["kind"], "Bind");
+    assert_eq!(json["typename"], serde_json::Value::Null);
+    assert_eq!(json["value"]["data"][1]["value"], "b:");
+    assert_eq!(json["body"]["messages"][0]["span"], serde_json::json!([28, 36]));
+}
+
+#[test]
+fn test_dump_def() {
+    let node = dump::syntax_node(&parse(
+        "interface Shape
+             required method area
+             method double
+                 self area * 2!
+         end",
+    ));
+    let json = node.to_json();
+    assert_eq!(json["kind"], "InterfaceDef");
+    assert_eq!(json["required_methods"][0]["selector"], "area");
+    assert_eq!(json["required_methods"][0].get("body"), None);
+    assert_eq!(json["instance_methods"][0]["body"]["kind"], "Chain");
+}
@@ -1,5 +1,6 @@
 use crate::eval::utils::{eval_obj, eval_ok, eval_str};
 use crate::objects::Slot;
+use crate::source_location::SourceLocation;
 use crate::unwind::Unwind;
 use crate::unwind::{Error, Location, SimpleError};
 
@@ -471,3 +472,484 @@ fn test_method_declares_class_as_argtype() {
         123
     );
 }
+
+#[test]
+fn test_value_class_equality() {
+    assert_eq!(
+        eval_ok(
+            "class Point { x y }
+                value
+             end
+             (Point x: 1 y: 2) is (Point x: 1 y: 2)"
+        )
+        .boolean(),
+        true
+    );
+    assert_eq!(
+        eval_ok(
+            "class Point { x y }
+                value
+             end
+             (Point x: 1 y: 2) is (Point x: 2 y: 1)"
+        )
+        .boolean(),
+        false
+    );
+    assert_eq!(
+        eval_ok(
+            "class Point { x y }
+             end
+             (Point x: 1 y: 2) is (Point x: 1 y: 2)"
+        )
+        .boolean(),
+        false
+    );
+}
+
+#[test]
+fn test_value_class_dictionary_key() {
+    assert_eq!(
+        eval_ok(
+            "class Point { x y }
+                value
+             end
+             let d = Dictionary new.
+             d put: 42 at: (Point x: 1 y: 2).
+             d at: (Point x: 1 y: 2) ifNone: { 0 }"
+        )
+        .integer(),
+        42
+    );
+}
+
+#[test]
+fn test_value_class_immutable() {
+    assert_eq!(
+        eval_str(
+            "class Point { x y }
+                value
+                method bump
+                   x = x + 1!
+             end
+             (Point x: 1 y: 2) bump"
+        )
+        .unwrap_err()
+        .to_string()
+        .contains("Cannot assign to x: #<instance Point> is a value object"),
+        true
+    );
+}
+
+#[test]
+fn test_value_class_copy_semantics() {
+    assert_eq!(
+        eval_ok(
+            "class Point { x y }
+                value
+             end
+             class Box { point }
+                method point: p
+                   point = p!
+             end
+             let a = Point x: 1 y: 2.
+             let box = Box point: a.
+             box point: (Point x: 1 y: 2).
+             box point is a"
+        )
+        .boolean(),
+        true
+    );
+}
+
+#[test]
+fn test_comprehension1() {
+    assert_eq!(format!("{:?}", eval_ok("[x * x for x in [1, 2, 3, 4]]")), "[1, 4, 9, 16]");
+}
+
+#[test]
+fn test_comprehension2() {
+    assert_eq!(format!("{:?}", eval_ok("[x * 10 for x in [1, 2, 3, 4] where x > 2]")), "[30, 40]");
+}
+
+#[test]
+fn test_comprehension3() {
+    assert_eq!(
+        format!(
+            "{:?}",
+            eval_ok(
+                "[[a, b, c] for a in 1 to: 20,
+                               b in 1 to: 20,
+                               c in 1 to: 20
+                            where b < c
+                            where a*a == (b*b + c*c)]"
+            )
+        ),
+        "[[5, 3, 4], [10, 6, 8], [13, 5, 12], [15, 9, 12], [17, 8, 15], [20, 12, 16]]"
+    );
+}
+
+#[test]
+fn test_comprehension4() {
+    assert_eq!(
+        format!(
+            "{:?}",
+            eval_ok(
+                "let xs = [1, 2, 3].
+                 [[x, y] for x in xs where x > 1 for y in xs where y < x]"
+            )
+        ),
+        "[[2, 1], [3, 1], [3, 2]]"
+    );
+}
+
+#[test]
+fn test_comprehension_nested() {
+    assert_eq!(
+        format!("{:?}", eval_ok("[x + 1 for x in [y * 2 for y in [1, 2, 3]]]")),
+        "[3, 5, 7]"
+    );
+}
+
+#[test]
+fn test_comprehension_does_not_capture() {
+    assert_eq!(
+        format!(
+            "{:?}",
+            eval_ok(
+                "let result = 100.
+                 [x + result for x in [1, 2]]"
+            )
+        ),
+        "[101, 102]"
+    );
+}
+
+#[test]
+fn test_comprehension_missing_in() {
+    assert_eq!(
+        eval_str("[x for x on [1, 2]]"),
+        Err(Unwind::Panic(
+            Error::SimpleError(SimpleError {
+                what: "Expected 'in' after comprehension variable".to_string(),
+            }),
+            Location::from(
+                9..11,
+                concat!(
+                    "001 [x for x on [1, 2]]\n",
+                    "             ^^ Expected 'in' after comprehension variable\n"
+                )
+            )
+        ))
+    );
+}
+
+#[test]
+fn test_comprehension_bad_clause() {
+    assert_eq!(
+        eval_str("[x for x in [1, 2] ) x]"),
+        Err(Unwind::Panic(
+            Error::SimpleError(SimpleError {
+                what: "Expected 'for', 'where', or ] in comprehension".to_string(),
+            }),
+            Location::from(
+                19..20,
+                concat!(
+                    "001 [x for x in [1, 2] ) x]\n",
+                    "                       ^ Expected 'for', 'where', or ] in comprehension\n"
+                )
+            )
+        ))
+    );
+}
+
+#[test]
+fn test_destructure_record() {
+    assert_eq!(
+        eval_ok(
+            "let {ok: ok, stdout: out} = {ok: True, stdout: 42, stderr: 0}.
+             ok ifTrue: { out + 1 } ifFalse: { 0 }"
+        )
+        .integer(),
+        43
+    );
+}
+
+#[test]
+fn test_destructure_array() {
+    assert_eq!(eval_ok("let [a, b, c] = [1, 20, 300]. a + b + c").integer(), 321);
+}
+
+#[test]
+fn test_destructure_nested() {
+    assert_eq!(eval_ok("let [{x: x}, [_, y]] = [{x: 1, y: 2}, [3, 4]]. x + y").integer(), 5);
+}
+
+#[test]
+fn test_destructure_toplevel() {
+    let (_, env) = eval_obj("let [a, b] = [1, 2]");
+    assert_eq!(env.get("a").unwrap().integer(), 1);
+    assert_eq!(env.get("b").unwrap().integer(), 2);
+}
+
+#[test]
+fn test_destructure_mismatch() {
+    assert_eq!(
+        eval_str("let [a, b] = [1, 2, 3]. a"),
+        Err(Unwind::Panic(
+            Error::SimpleError(SimpleError {
+                what: "Pattern [a, b] does not match: [1, 2, 3]".to_string(),
+            }),
+            Location::from(
+                4..10,
+                concat!(
+                    "001 let [a, b] = [1, 2, 3]. a\n",
+                    "        ^^^^^^ Pattern [a, b] does not match: [1, 2, 3]\n"
+                )
+            )
+        ))
+    );
+}
+
+#[test]
+fn test_destructure_duplicate_variable() {
+    assert_eq!(
+        eval_str("let [a, a] = [1, 2]. a"),
+        Err(Unwind::Panic(
+            Error::SimpleError(SimpleError {
+                what: "Variable bound twice in pattern: a".to_string(),
+            }),
+            Location::from(
+                8..9,
+                concat!(
+                    "001 let [a, a] = [1, 2]. a\n",
+                    "            ^ Variable bound twice in pattern: a\n"
+                )
+            )
+        ))
+    );
+}
+
+#[test]
+fn test_match_literals() {
+    let source = "define Describe
+                      { |x| x match: { case 0 -> \"zero\"
+                                       case -1 -> \"minus one\"
+                                       case \"foo\" -> \"foo\"
+                                       case True -> \"true\"
+                                       case #bar -> \"bar\"
+                                       case _ -> \"other\" } }!
+                  [Describe value: 0, Describe value: -1, Describe value: \"foo\",
+                   Describe value: True, Describe value: #bar, Describe value: 42]";
+    assert_eq!(
+        format!("{:?}", eval_ok(source)),
+        r#"["zero", "minus one", "foo", "true", "bar", "other"]"#
+    );
+}
+
+#[test]
+fn test_match_typecheck() {
+    let source = "define Describe
+                      { |x| x match: { case n::Integer -> n + 1
+                                       case s::String -> s size
+                                       case _ -> 0 } }!
+                  [Describe value: 41, Describe value: \"foo\", Describe value: 1.0]";
+    assert_eq!(format!("{:?}", eval_ok(source)), "[42, 3, 0]");
+}
+
+#[test]
+fn test_match_record_and_array() {
+    let source = "define Describe
+                      { |x| x match: { case {ok: True, stdout: out} -> out
+                                       case {ok: False} -> -1
+                                       case [a, b] -> a * b
+                                       case [] -> 0 } }!
+                  [Describe value: {ok: True, stdout: 10, stderr: 0},
+                   Describe value: {ok: False},
+                   Describe value: [6, 7],
+                   Describe value: []]";
+    assert_eq!(format!("{:?}", eval_ok(source)), "[10, -1, 42, 0]");
+}
+
+#[test]
+fn test_match_guard() {
+    let source = "define Sign
+                      { |x| x match: { case n where n < 0 -> -1
+                                       case 0 -> 0
+                                       case _ -> 1 } }!
+                  [Sign value: -10, Sign value: 0, Sign value: 10]";
+    assert_eq!(format!("{:?}", eval_ok(source)), "[-1, 0, 1]");
+}
+
+#[test]
+fn test_match_sequence_body() {
+    assert_eq!(
+        eval_ok(
+            "42 match: { case x -> let y = x + 1. y * 2
+                         case _ -> 0 }"
+        )
+        .integer(),
+        86
+    );
+}
+
+#[test]
+fn test_match_no_match() {
+    assert_eq!(
+        eval_str("[1, 2] match: { case [x] -> x }"),
+        Err(Unwind::Panic(
+            Error::SimpleError(SimpleError {
+                what: "No pattern matched: [1, 2]".to_string(),
+            }),
+            Location::from(
+                7..31,
+                concat!(
+                    "001 [1, 2] match: { case [x] -> x }\n",
+                    "           ^^^^^^^^^^^^^^^^^^^^^^^^ No pattern matched: [1, 2]\n"
+                )
+            )
+        ))
+    );
+}
+
+#[test]
+fn test_macro_hygiene() {
+    assert_eq!(
+        eval_ok(
+            "macro Swap a: x with: y
+                 quote { let tmp = x. x = y. y = tmp }!
+             let tmp = 1.
+             let other = 10.
+             Swap a: tmp with: other.
+             tmp * 100 + other"
+        )
+        .integer(),
+        1001
+    );
+}
+
+#[test]
+fn test_macro_splices_blocks() {
+    assert_eq!(
+        eval_ok(
+            "macro Unless cond: c do: body
+                 quote { c ifTrue: { 0 } ifFalse: body }!
+             Unless cond: 1 > 2 do: { 42 }"
+        )
+        .integer(),
+        42
+    );
+}
+
+#[test]
+fn test_macro_literal_syntax() {
+    assert_eq!(
+        eval_ok(
+            "macro Double x: x
+                 Syntax literal: x value * 2!
+             Double x: 21"
+        )
+        .integer(),
+        42
+    );
+}
+
+#[test]
+fn test_macro_chain() {
+    assert_eq!(
+        eval_ok(
+            "macro Id x: x
+                 x!
+             Id x: 41; + 1"
+        )
+        .integer(),
+        42
+    );
+}
+
+#[test]
+fn test_macro_error_at_call_site() {
+    assert_eq!(
+        eval_str(
+            "macro Bad x: x
+                 x value!
+             Bad x: y"
+        ),
+        Err(Unwind::Panic(
+            Error::SimpleError(SimpleError {
+                what: "Error while expanding macro Bad: Syntax#value: not a literal".to_string(),
+            }),
+            Location::from(
+                54..62,
+                concat!(
+                    "002                  x value!\n",
+                    "003              Bad x: y\n",
+                    "                 ^^^^^^^^ Error while expanding macro Bad: Syntax#value: not a literal\n"
+                )
+            )
+        ))
+    );
+}
+
+#[test]
+fn test_macro_not_syntax() {
+    assert_eq!(
+        eval_str(
+            "macro Bad x: x
+                 42!
+             Bad x: 1"
+        ),
+        Err(Unwind::Panic(
+            Error::SimpleError(SimpleError {
+                what: "Macro Bad did not return Syntax: 42".to_string(),
+            }),
+            Location::from(
+                49..57,
+                concat!(
+                    "002                  42!\n",
+                    "003              Bad x: 1\n",
+                    "                 ^^^^^^^^ Macro Bad did not return Syntax: 42\n"
+                )
+            )
+        ))
+    );
+}
+
+#[test]
+fn test_return_from_expired_method() {
+    let source = "class Foo {}
+                      direct method escaper
+                          return { return 42 }!
+                  end
+                  Foo escaper value";
+    match eval_str(source) {
+        Err(Unwind::Panic(Error::BlockContextExpired(expired), location)) => {
+            let block = source.find("return 42").unwrap();
+            let method = source.find("return {").unwrap();
+            assert_eq!(location.source_location, Some(SourceLocation::span(&(block..block + 6))));
+            assert_eq!(
+                expired.method.source_location,
+                Some(SourceLocation::span(&(method..method + 1)))
+            );
+        }
+        other => panic!("Expected BlockContextExpired, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_return_from_live_method_through_stored_block() {
+    assert_eq!(
+        eval_ok(
+            "class Foo { block }
+                 direct method test
+                     let foo = Foo block: { return 42 }.
+                     foo run.
+                     0!
+                 method run
+                     block value!
+             end
+             Foo test"
+        )
+        .integer(),
+        42
+    );
+}
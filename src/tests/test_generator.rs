@@ -0,0 +1,138 @@
+use crate::eval::utils::*;
+use crate::unwind::Unwind;
+
+fn eval_error(source: &str) -> String {
+    match eval_str(source) {
+        Err(Unwind::Panic(error, _)) => error.what(),
+        other => panic!("Expected an error, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_generator_next() {
+    assert_eq!(
+        eval_ok(
+            "let g = Generator new: { |yield| yield value: 1. yield value: 2. 3 }.
+             let a = g next.
+             let b = g next.
+             [a, b, g isDone] == [1, 2, True]"
+        )
+        .boolean(),
+        true
+    );
+}
+
+#[test]
+fn test_generator_is_done_does_not_lose_values() {
+    assert_eq!(
+        eval_ok(
+            "let g = Generator new: { |yield| yield value: 1 }.
+             let a = g isDone.
+             let b = g hasNext.
+             [a, b, g next, g isDone, g nextIfNone: { 42 }] == [False, True, 1, True, 42]"
+        )
+        .boolean(),
+        true
+    );
+}
+
+#[test]
+fn test_generator_next_when_done() {
+    assert_eq!(eval_error("let g = Generator new: { |yield| 1 }. g next"), "Generator is done");
+}
+
+#[test]
+fn test_generator_error() {
+    assert_eq!(
+        eval_error(
+            "let g = Generator new: { |yield| yield value: 1. panic \"oops\" }. g next. g next"
+        ),
+        "oops"
+    );
+}
+
+#[test]
+fn test_generator_is_lazy() {
+    assert_eq!(
+        eval_ok(
+            "let n = 0.
+             let g = Generator new: { |yield| { n = n + 1. yield value: n } loop }.
+             g next. g next. g next.
+             n"
+        )
+        .integer(),
+        3
+    );
+}
+
+#[test]
+fn test_generator_as_iterator() {
+    assert_eq!(
+        eval_ok(
+            "class Squares { limit }
+                 is Iterable
+                 method iterator
+                     Generator new: { |yield| 1 to: limit do: { |i| yield value: i * i } }!
+             end
+             (Squares limit: 5) inject: 0 into: { |sum each| sum + each }"
+        )
+        .integer(),
+        55
+    );
+}
+
+#[test]
+fn test_generator_dropped_unfinished() {
+    assert_eq!(
+        eval_ok(
+            "let log = \"\".
+             { let g = Generator new: { |yield|
+                                        { yield value: 1. yield value: 2 }
+                                            finally: { log = \"{log} cleanup\" } }.
+               log = \"{log} {g next}\" } value.
+             log size.
+             log"
+        )
+        .string_as_str(),
+        " 1 cleanup"
+    );
+}
+
+#[test]
+fn test_generator_cancelled_at_safe_point() {
+    let (log, env) = eval_obj(
+        "class Box { value }
+             method value: x
+                 value = x!
+             direct method generatorLogging: log
+                 Generator new: { |yield|
+                                  { yield value: 1. yield value: 2 }
+                                      finally: { log value: \"cleanup\" } }!
+         end
+         let log = Box value: \"\".
+         { let b = Box value: 0.
+           let g = Box generatorLogging: log.
+           g next.
+           b value: [b, g] } value.
+         log",
+    );
+    assert_eq!(env.foo.heap.collect(), 2);
+    assert!(env.foo.heap.has_pending_cancellations());
+    assert_eq!(log.send("value", &[], &env).unwrap().string_as_str(), "");
+    env.safe_point();
+    assert!(!env.foo.heap.has_pending_cancellations());
+    assert_eq!(log.send("value", &[], &env).unwrap().string_as_str(), "cleanup");
+}
+
+#[test]
+fn test_yield_outside_generator() {
+    assert_eq!(
+        eval_error(
+            "let y = False.
+             let g = Generator new: { |yield| y = yield. 1 }.
+             g isDone.
+             y value: 1"
+        ),
+        "Cannot yield outside the generator"
+    );
+}
@@ -0,0 +1,151 @@
+use std::rc::Rc;
+
+use crate::eval::utils::*;
+use crate::objects::Datum;
+
+const BOX: &str = "class Box { value }
+                       method value: x
+                           value = x!
+                   end
+                   ";
+
+#[test]
+fn test_collect_instance_cycle() {
+    let (obj, env) = eval_obj(&format!("{}{{ let b = Box value: 0. b value: b. b }} value", BOX));
+    let weak = match &obj.datum {
+        Datum::Instance(instance) => Rc::downgrade(instance),
+        _ => panic!("Not an instance: {:?}", obj),
+    };
+    drop(obj);
+    assert_eq!(weak.strong_count(), 1);
+    assert_eq!(env.foo.heap.collect(), 1);
+    assert_eq!(weak.strong_count(), 0);
+}
+
+#[test]
+fn test_collect_closure_cycle() {
+    let (obj, env) = eval_obj("{ let f = False. f = { f }. f } value");
+    let weak = match &obj.datum {
+        Datum::Closure(closure) => Rc::downgrade(closure),
+        _ => panic!("Not a closure: {:?}", obj),
+    };
+    drop(obj);
+    assert_eq!(weak.strong_count(), 1);
+    assert_eq!(env.foo.heap.collect(), 1);
+    assert_eq!(weak.strong_count(), 0);
+}
+
+#[test]
+fn test_collect_keeps_live_cycles() {
+    let (obj, env) = eval_obj(&format!(
+        "{}{{ let a = Box value: 0. let b = Box value: a. a value: b. a }} value",
+        BOX
+    ));
+    assert_eq!(env.foo.heap.collect(), 0);
+    let b = obj.send("value", &[], &env).unwrap();
+    let a = b.send("value", &[], &env).unwrap();
+    assert_eq!(a, obj);
+    drop((a, b, obj));
+    assert_eq!(env.foo.heap.collect(), 2);
+}
+
+#[test]
+fn test_weakref_to_collected_cycle() {
+    let (weak, env) =
+        eval_obj(&format!("{}{{ let b = Box value: 0. b value: b. WeakRef to: b }} value", BOX));
+    assert_eq!(weak.send("isAlive", &[], &env).unwrap(), env.foo.make_boolean(true));
+    assert_eq!(env.foo.heap.collect(), 1);
+    assert_eq!(weak.send("isAlive", &[], &env).unwrap(), env.foo.make_boolean(false));
+    assert_eq!(weak.send("value", &[], &env).unwrap(), env.foo.make_boolean(false));
+}
+
+#[test]
+fn test_weakref_to_live_object() {
+    assert_eq!(
+        eval_ok(&format!("{}let b = Box value: 42. (WeakRef to: b) value value", BOX)).integer(),
+        42
+    );
+    assert_eq!(eval_ok("(WeakRef to: 42) isAlive").boolean(), true);
+}
+
+#[test]
+fn test_finalizer_runs_after_collect() {
+    let (log, env) = eval_obj(&format!(
+        "{}let log = Box value: 0.
+           let action = {{ log value: log value + 1 }}.
+           {{ let b = Box value: 0. b value: b.
+              Finalizer on: b do: action }} value.
+           log",
+        BOX
+    ));
+    assert!(!env.foo.heap.has_pending_finalizers());
+    assert_eq!(env.foo.heap.collect(), 1);
+    assert!(env.foo.heap.has_pending_finalizers());
+    env.run_finalizers();
+    assert_eq!(log.send("value", &[], &env).unwrap().integer(), 1);
+    assert_eq!(env.foo.heap.collect(), 0);
+    env.run_finalizers();
+    assert_eq!(log.send("value", &[], &env).unwrap().integer(), 1);
+}
+
+#[test]
+fn test_finalizer_of_dropped_object() {
+    let (log, env) = eval_obj(&format!(
+        "{}let log = Box value: 0.
+           Finalizer on: (Box value: 0) do: {{ log value: 42 }}.
+           log",
+        BOX
+    ));
+    assert_eq!(env.foo.heap.collect(), 0);
+    env.run_finalizers();
+    assert_eq!(log.send("value", &[], &env).unwrap().integer(), 42);
+}
+
+#[test]
+fn test_finalizer_on_immediate() {
+    assert!(eval_str("Finalizer on: 42 do: {}").is_err());
+}
+
+#[test]
+fn test_heap_stats() {
+    let (_boxes, env) = eval_obj(&format!("{}[Box value: 1, Box value: \"xyzzy\"]", BOX));
+    let stats = env.foo.heap.stats();
+    assert_eq!(stats.instances["Box"], 2);
+    assert!(stats.objects["Instance"] >= 2);
+    assert!(stats.bytes["Array"] >= 2 * std::mem::size_of::<crate::objects::Object>());
+    assert!(!stats.objects.contains_key("String"));
+    assert!(stats.allocations["String"] >= 1);
+    assert!(stats.allocations["Instance"] >= 2);
+    assert!(!env.foo.heap.stats().allocations.contains_key("Instance"));
+}
+
+#[test]
+fn test_all_instances() {
+    assert_eq!(
+        eval_ok(&format!(
+            "{}let a = Box value: 1. let b = Box value: 2.
+               Box allInstances inject: 0 into: {{ |sum box| sum + box value }}",
+            BOX
+        ))
+        .integer(),
+        3
+    );
+}
+
+#[test]
+fn test_collect_only_at_safe_point() {
+    let (obj, env) = eval_obj(&format!("{}{{ let b = Box value: 0. b value: b. b }} value", BOX));
+    let weak = match &obj.datum {
+        Datum::Instance(instance) => Rc::downgrade(instance),
+        _ => panic!("Not an instance: {:?}", obj),
+    };
+    drop(obj);
+    let arrays: Vec<_> = (0..20_000).map(|_| env.foo.make_array(&[])).collect();
+    assert!(env.foo.heap.wants_collection());
+    assert_eq!(weak.strong_count(), 1);
+    env.safe_point();
+    assert!(!env.foo.heap.wants_collection());
+    assert_eq!(weak.strong_count(), 0);
+    assert_eq!(env.foo.heap.collected(), 1);
+    drop(arrays);
+}
@@ -1,4 +1,6 @@
-use crate::eval::utils::{eval_ok, eval_str};
+use std::rc::Rc;
+
+use crate::eval::utils::{eval_obj, eval_ok, eval_str};
 use crate::unwind::Unwind;
 use crate::unwind::{Error, Location, SimpleError};
 
@@ -171,3 +173,15 @@ fn test_integer_prefix_minus() {
     assert_eq!(eval_ok("let x = -42. -x").integer(), 42);
     assert_eq!(eval_ok("let x = 42. -x").integer(), -42);
 }
+
+#[test]
+fn test_integer_is_immediate() {
+    let (obj, env) = eval_obj("40 + 2");
+    assert!(obj.heap_vtable().is_none());
+    assert!(Rc::ptr_eq(obj.vtable(&env.foo), &env.foo.integer_vtable));
+    let count = Rc::strong_count(&env.foo.integer_vtable);
+    let integers: Vec<_> = (0..10).map(|i| env.foo.make_integer(i)).collect();
+    assert_eq!(Rc::strong_count(&env.foo.integer_vtable), count);
+    drop(integers);
+    assert_eq!(eval_ok("Integer includes: 42").boolean(), true);
+}
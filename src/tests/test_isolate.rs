@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::thread;
+
+use crate::debugger::{Console, Debugger};
+use crate::isolate::Isolate;
+
+fn isolate() -> Isolate {
+    let mut roots = HashMap::new();
+    roots.insert(".".to_string(), std::env::current_dir().unwrap());
+    Isolate::new(Path::new("foo/lang/bootstrap_prelude.foo"), roots).unwrap()
+}
+
+#[test]
+fn test_isolates_in_parallel() {
+    let threads: Vec<_> = (1..=4)
+        .map(|n| {
+            let isolate = isolate();
+            isolate.eval(&format!("define N {}!", n)).unwrap();
+            let isolate = isolate.detach().unwrap();
+            thread::spawn(move || {
+                isolate
+                    .attach()
+                    .eval(
+                        "class Fib {}
+                             direct method of: n
+                                 n < 2 ifTrue: { return n }.
+                                 return (self of: n - 1) + (self of: n - 2)!
+                         end
+                         (Fib of: 15) * N",
+                    )
+                    .unwrap()
+            })
+        })
+        .collect();
+    let results: Vec<String> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    assert_eq!(results, vec!["610", "1220", "1830", "2440"]);
+}
+
+#[test]
+fn test_isolate_error() {
+    let isolate = thread::spawn(|| isolate().detach().unwrap()).join().unwrap().attach();
+    assert_eq!(
+        isolate.eval("panic \"oops\""),
+        Err(concat!("ERROR: oops\n", "001 panic \"oops\"\n", "    ^^^^^ oops\n").to_string())
+    );
+}
+
+#[test]
+fn test_isolate_with_debugger_cannot_detach() {
+    let mut roots = HashMap::new();
+    roots.insert(".".to_string(), std::env::current_dir().unwrap());
+    let console = Console::new(Box::new(std::io::empty()), false);
+    let debugger = Debugger::new(Path::new("test.foo"), Box::new(console));
+    let isolate =
+        Isolate::with_debugger(Path::new("foo/lang/bootstrap_prelude.foo"), roots, debugger)
+            .unwrap();
+    assert_eq!(
+        isolate.detach().err(),
+        Some("Cannot move an isolate with a debugger to another thread".to_string())
+    );
+}
@@ -3,7 +3,7 @@ use crate::expr::*;
 use crate::parse::utils::*;
 use crate::parse::*;
 use crate::source_location::SourceLocation;
-use crate::unwind::Unwind;
+use crate::unwind::{Error, Location, SimpleError, Unwind};
 
 fn parse_str(source: &str) -> Parse {
     Parser::new(source, "test/").parse().map_err(|unwind| unwind.with_context(source))
@@ -185,6 +185,105 @@ fn test_parse_let2() {
     );
 }
 
+#[test]
+fn test_parse_let_destructure() {
+    assert_eq!(
+        parse_expr("let [a, {x: _}] = foo. a"),
+        Ok(Destructure::expr(
+            SourceLocation::span(&(4..15)),
+            Pattern::Array(
+                SourceLocation::span(&(4..15)),
+                vec![
+                    Pattern::Var(Var::untyped(
+                        SourceLocation::span(&(5..6)),
+                        "a".to_string(),
+                        false
+                    )),
+                    Pattern::Record(
+                        SourceLocation::span(&(8..14)),
+                        vec![("x".to_string(), Pattern::Wildcard(SourceLocation::span(&(12..13))))]
+                    )
+                ]
+            ),
+            Box::new(var(18..21, "foo")),
+            Some(Box::new(var(23..24, "a")))
+        ))
+    );
+}
+
+#[test]
+fn test_parse_operator_declaration() {
+    let source = "operator <+> binary precedence: 9\na <+> b + c";
+    let mut parser = Parser::new(source, "test/");
+    assert_eq!(
+        parser.parse().map(|syntax| syntax.def()),
+        Ok(Def::OperatorDef(OperatorDef {
+            source_location: SourceLocation::span(&(0..33)),
+            name: "<+>".to_string(),
+            is_prefix: false,
+            is_binary: true,
+            precedence: 9,
+        }))
+    );
+    assert_eq!(
+        parser.parse().map(|syntax| syntax.expr()),
+        Ok(binary(
+            42..45,
+            "+",
+            binary(36..41, "<+>", var(34..35, "a"), var(40..41, "b")),
+            var(44..45, "c")
+        ))
+    );
+}
+
+#[test]
+fn test_parse_operator_as_unary_message() {
+    assert_eq!(parse_expr("x operator"), Ok(unary(2..10, "operator", var(0..1, "x"))));
+}
+
+#[test]
+fn test_parse_quote_and_macro_as_unary_messages() {
+    assert_eq!(parse_expr("x quote"), Ok(unary(2..7, "quote", var(0..1, "x"))));
+    assert_eq!(parse_expr("x macro"), Ok(unary(2..7, "macro", var(0..1, "x"))));
+    assert_eq!(parse_expr("macro"), Ok(var(0..5, "macro")));
+}
+
+#[test]
+fn test_parse_undeclared_operator() {
+    assert_eq!(
+        parse_expr("a <+> b + c"),
+        Ok(binary(
+            2..11,
+            "<+>",
+            var(0..1, "a"),
+            binary(8..11, "+", var(6..7, "b"), var(10..11, "c"))
+        ))
+    );
+}
+
+#[test]
+fn test_parse_prefix_operator_declaration() {
+    let source = "operator ~ prefix\n~a. b ~ c";
+    let mut parser = Parser::new(source, "test/");
+    assert!(parser.parse().is_ok());
+    assert_eq!(
+        parser.parse().map_err(|unwind| unwind.with_context(source)),
+        Err(Unwind::Panic(
+            Error::SimpleError(SimpleError {
+                what: "Not a binary operator: ~".to_string(),
+            }),
+            Location::from(
+                24..25,
+                concat!(
+                    "001 operator ~ prefix\n",
+                    "002 ~a. b ~ c\n",
+                    "          ^ Not a binary operator: ~\n"
+                )
+            )
+        ))
+    );
+}
+
 #[test]
 fn test_keyword1() {
     assert_eq!(
@@ -218,6 +317,14 @@ fn parse_block_no_args() {
     );
 }
 
+#[test]
+fn parse_block_empty_args() {
+    assert_eq!(
+        parse_expr(" { || foo bar } "),
+        Ok(block(1..15, vec![], unary(10..13, "bar", var(6..9, "foo"))))
+    );
+}
+
 #[test]
 fn parse_block_args() {
     assert_eq!(
@@ -235,6 +342,15 @@ fn test_parse_class1() {
     assert_eq!(parse_def("class Point { x y } end"), Ok(class(0..5, "Point", vec!["x", "y"])));
 }
 
+#[test]
+fn test_parse_value_class() {
+    let mut class = class(0..5, "Point", vec!["x", "y"]);
+    if let Def::ClassDef(ref mut def) = class {
+        def.value = true;
+    }
+    assert_eq!(parse_def("class Point { x y } value end"), Ok(class));
+}
+
 #[test]
 fn parse_method1() {
     let mut class = class(0..5, "Foo", vec![]);
@@ -0,0 +1,173 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::dump::syntax_node;
+use crate::parse::Parser;
+use crate::pretty::{def_source, expr_source, module_source};
+use crate::syntax::Syntax;
+use crate::unwind::Unwind;
+
+fn parse_all(source: &str, root: &Path) -> Result<Vec<Syntax>, Unwind> {
+    let mut parser = Parser::new(source, root);
+    let mut syntax = Vec::new();
+    while !parser.at_eof() {
+        syntax.push(parser.parse()?);
+    }
+    Ok(syntax)
+}
+
+/// The tree as dumped, without spans: those aren't preserved by printing.
+fn shape(syntax: &Syntax) -> Value {
+    fn strip(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                map.remove("span");
+                map.values_mut().for_each(strip);
+            }
+            Value::Array(values) => values.iter_mut().for_each(strip),
+            _ => (),
+        }
+    }
+    let mut json = syntax_node(syntax).to_json();
+    strip(&mut json);
+    json
+}
+
+fn print(source: &str) -> String {
+    match Parser::new(source, "test/").parse().unwrap() {
+        Syntax::Expr(expr) => expr_source(&expr),
+        Syntax::Def(def) => def_source(&def, Path::new("test/")),
+    }
+}
+
+fn foo_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> =
+        fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            foo_files(&path, files);
+        } else if path.extension().map_or(false, |ext| ext == "foo") {
+            files.push(path);
+        }
+    }
+}
+
+/// Files which do not parse: on purpose in the case of tests, otherwise
+/// because they have not kept up with the language.
+const BROKEN_FOO_FILES: &[&str] = &[
+    // Lowercase interface names.
+    "foo/lang/test_class.foo",
+    "foo/lang/test_lang.foo",
+    // Let in operator position.
+    "foo/lib/numeric.foo",
+    // Old drafts.
+    "foo/scratch/flying2.foo",
+    "foo/scratch/flying3.foo",
+    "foo/scratch/flying_vis.foo",
+    // Syntax errors on purpose.
+    "foo/tests/test_bad_class.foo",
+    "foo/tests/test_import_error_location.foo",
+];
+
+#[test]
+fn test_pretty_round_trip_foo_files() {
+    let mut files = Vec::new();
+    foo_files(Path::new("foo"), &mut files);
+    for file in &files {
+        let root = file.parent().unwrap();
+        let source = fs::read_to_string(file).unwrap();
+        let broken = BROKEN_FOO_FILES.iter().any(|broken| file == Path::new(broken));
+        let syntax = match parse_all(&source, root) {
+            Ok(_) if broken => {
+                panic!("{}: parses, remove it from BROKEN_FOO_FILES", file.display())
+            }
+            Ok(syntax) => syntax,
+            Err(_) if broken => continue,
+            Err(unwind) => panic!("{}: cannot parse: {:?}", file.display(), unwind),
+        };
+        let printed = module_source(&syntax, root);
+        let reparsed = match parse_all(&printed, root) {
+            Ok(reparsed) => reparsed,
+            Err(unwind) => panic!("{}: cannot parse printed source: {:?}", file.display(), unwind),
+        };
+        assert_eq!(syntax.len(), reparsed.len(), "{}", file.display());
+        for (original, printed) in syntax.iter().zip(&reparsed) {
+            assert_eq!(
+                shape(original),
+                shape(printed),
+                "{}: not equivalent when printed as:\n{}",
+                file.display(),
+                crate::pretty::syntax_source(original, root)
+            );
+        }
+        assert_eq!(printed, module_source(&reparsed, root), "{}", file.display());
+    }
+    for broken in BROKEN_FOO_FILES {
+        assert!(files.iter().any(|file| file == Path::new(broken)), "{}: not found", broken);
+    }
+}
+
+#[test]
+fn test_pretty_precedence() {
+    assert_eq!(print("(a + b) * c - d"), "(a + b) * c - d");
+    assert_eq!(print("a - (b - c)"), "a - (b - c)");
+    assert_eq!(print("(a foo: b) bar: (c quux: d)"), "(a foo: b) bar: (c quux: d)");
+    assert_eq!(print("(x foo + 1) abs"), "(x foo + 1) abs");
+    assert_eq!(print("-(x size)::Integer"), "-(x size)::Integer");
+    assert_eq!(print("x = (y = 1)"), "x = (y = 1)");
+    assert_eq!(print("a is b + 1"), "a is b + 1");
+    assert_eq!(print("a <+> (b <+> c)"), "a <+> (b <+> c)");
+}
+
+#[test]
+fn test_pretty_cascade_and_blocks() {
+    assert_eq!(print("x foo; bar: 1; + 2"), "x foo; bar: 1; + 2");
+    assert_eq!(print("{ |a b::Integer| -> Integer a + b }"), "{ |a b::Integer| -> Integer a + b }");
+    assert_eq!(
+        print("list do: { |x| let y = x * 2. y debug. return y }"),
+        "list do: { |x|\n    let y = x * 2.\n    y debug.\n    return y }"
+    );
+}
+
+#[test]
+fn test_pretty_desugared_literals() {
+    assert_eq!(print(r#""a{x foo}b\n\{""#), r#""a{x foo}b\n\{""#);
+    assert_eq!(print("{x: 1, y: [2, 3]} x"), "{x: 1, y: [2, 3]} x");
+    assert_eq!(print("{1 -> #one, $x -> 2.5}"), "{1 -> #one, $x -> 2.5}");
+    assert_eq!(
+        print("[x * y for x in xs, y in ys where x < y] size"),
+        "[x * y for x in xs for y in ys where x < y] size"
+    );
+}
+
+#[test]
+fn test_pretty_match() {
+    assert_eq!(
+        print("v match: { case [x, _] where x > 0 -> x case {a: -1} -> \"neg\" case _ -> 0 }"),
+        "v match: {\n    case [x, _] where x > 0 -> x\n    case {a: -1} -> \"neg\"\n    case _ -> 0 }"
+    );
+}
+
+#[test]
+fn test_pretty_definitions() {
+    assert_eq!(
+        print("class Point { x y::Integer } is Object method + other -> Point Point x: x + other x y: y! end"),
+        "class Point { x y::Integer }
+    is Object
+
+    method + other -> Point
+        Point x: x + other x y: y!
+end"
+    );
+    assert_eq!(print("import .x.Y"), "import .x.Y");
+    assert_eq!(print("import lang.*"), "import lang.*");
+    assert_eq!(print("operator <+> binary precedence: 4"), "operator <+> binary precedence: 4");
+    assert_eq!(print("define $Limit 10!"), "define $Limit 10!");
+    assert_eq!(
+        print("macro Twice do: x quote { x. x }!"),
+        "macro Twice do: x\n    quote {\n        x.\n        x }!"
+    );
+}
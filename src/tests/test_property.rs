@@ -0,0 +1,110 @@
+use crate::eval::utils::{eval_ok, eval_str};
+
+fn check(property: &str, generators: &str) -> String {
+    let source = format!(
+        "let res = Property check: {} forAll: [{}] seed: 42.
+         res passed ifTrue: {{ \"passed\" }} ifFalse: {{ res input toString }}",
+        property, generators
+    );
+    eval_ok(&source).string_as_str().to_string()
+}
+
+#[test]
+fn test_property_passes() {
+    assert_eq!(check("{ |x| x + 0 == x }", "Gen integer"), "passed");
+    assert_eq!(check("{ |x| x >= -3 and: x <= 3 }", "Gen integerFrom: -3 to: 3"), "passed");
+    assert_eq!(check("{ |b| b is True or: b is False }", "Gen boolean"), "passed");
+}
+
+#[test]
+fn test_property_result() {
+    let res = eval_ok("Property check: { |x| True } forAll: [Gen float] seed: 7");
+    let res = res.as_record("test").unwrap().borrow();
+    assert!(res["passed"].boolean());
+    assert_eq!(res["runs"].integer(), 100);
+    assert_eq!(res["seed"].integer(), 7);
+    assert!(!res["input"].boolean());
+}
+
+#[test]
+fn test_property_shrinks_integers() {
+    assert_eq!(check("{ |x| x < 10 }", "Gen integer"), "[10]");
+    assert_eq!(check("{ |x| x > -10 }", "Gen integer"), "[-10]");
+    assert_eq!(check("{ |x y| x < 100 or: y < 100 }", "Gen integer, Gen integer"), "[100, 100]");
+}
+
+#[test]
+fn test_property_shrinks_ranges() {
+    assert_eq!(check("{ |x| x < 15 }", "Gen integerFrom: 10 to: 20"), "[15]");
+    assert_eq!(check("{ |x| x > -15 }", "Gen integerFrom: -20 to: -10"), "[-15]");
+}
+
+#[test]
+fn test_property_shrinks_collections() {
+    assert_eq!(check("{ |xs| xs size < 3 }", "Gen arrayOf: Gen integer"), "[[0, 0, 0]]");
+    assert_eq!(check("{ |s| s size < 2 }", "Gen string"), "[aa]");
+    let input = eval_ok(
+        "let res = Property check: { |r| r x < 5 }
+                           forAll: [Gen record: { x: Gen integer, y: Gen boolean }]
+                           seed: 42.
+         res input first",
+    );
+    let input = input.as_record("test").unwrap().borrow();
+    assert_eq!(input["x"].integer(), 5);
+    assert!(!input["y"].boolean());
+}
+
+#[test]
+fn test_property_error() {
+    let res = eval_ok(
+        "Property check: { |x| x < 3 ifTrue: { True } ifFalse: { panic \"Too big: {x}\" } }
+                  forAll: [Gen integer]
+                  seed: 1",
+    );
+    let res = res.as_record("test").unwrap().borrow();
+    assert!(!res["passed"].boolean());
+    assert_eq!(res["error"].string_as_str(), "Too big: 3");
+}
+
+#[test]
+fn test_property_not_boolean() {
+    let res = eval_ok("(Property check: { |x| x } forAll: [Gen integer] seed: 1) error");
+    assert_eq!(res.string_as_str(), "Property returned 0, not a Boolean");
+}
+
+#[test]
+fn test_gen_generate_is_seeded() {
+    assert!(eval_ok(
+        "let gen = Gen arrayOf: (Gen dictionaryFrom: Gen string to: Gen float).
+         (gen generate: (Random new: 3)) toString == (gen generate: (Random new: 3)) toString"
+    )
+    .boolean());
+}
+
+#[test]
+fn test_gen_to_string() {
+    assert_eq!(
+        eval_ok("(Gen arrayOf: (Gen integerFrom: 1 to: 6)) toString").string_as_str(),
+        "Gen arrayOf: (Gen integerFrom: 1 to: 6)"
+    );
+}
+
+#[test]
+fn test_gen_bad_range() {
+    assert!(eval_str("Gen integerFrom: 2 to: 1").is_err());
+}
+
+#[test]
+fn test_property_example_database() {
+    let dir = std::env::temp_dir().join(format!("foolang-examples-{}", std::process::id()));
+    std::env::set_var("FOOLANG_EXAMPLES", &dir);
+    let source = "let res = Property check: { |x| x < 1000 } forAll: [Gen integer].
+                  [res runs, res input]";
+    let first = eval_ok(source);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    // The failing input is checked first on the next run.
+    let second = eval_ok(source);
+    assert_eq!(second.to_string(), "[1, [1000]]");
+    assert!(first.to_string().ends_with(", [1000]]"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,158 @@
+use crate::eval::utils::*;
+use crate::expr::*;
+use crate::parse::Parser;
+use crate::resolve::resolve;
+use crate::unwind::Unwind;
+
+fn resolved(params: &[&str], source: &str) -> (SlotNames, Expr) {
+    let mut body = Parser::new(source, "test/").parse().unwrap().expr();
+    let slots = resolve(params.iter().copied(), &mut body);
+    (slots, body)
+}
+
+fn names(slots: &Option<SlotNames>) -> Option<Vec<&str>> {
+    slots.as_ref().map(|slots| slots.iter().map(String::as_str).collect())
+}
+
+fn addresses(expr: &mut Expr, out: &mut Vec<(String, Option<Address>)>) {
+    if let Expr::Var(var) = expr {
+        out.push((var.name.clone(), var.address));
+    }
+    let _: Result<(), ()> = expr.for_each_subexpr_mut(&mut |subexpr| {
+        addresses(subexpr, out);
+        Ok(())
+    });
+}
+
+fn local(depth: usize, index: usize) -> Option<Address> {
+    Some(Address::Local {
+        depth,
+        index,
+    })
+}
+
+#[test]
+fn test_resolve_params_and_lets() {
+    let (slots, mut body) = resolved(&["x"], "let y = x + 1. y * x");
+    assert_eq!(*slots, ["x", "y"]);
+    let mut vars = vec![];
+    addresses(&mut body, &mut vars);
+    assert_eq!(
+        vars,
+        vec![
+            ("x".to_string(), local(0, 0)),
+            ("y".to_string(), local(0, 1)),
+            ("x".to_string(), local(0, 0)),
+        ]
+    );
+}
+
+#[test]
+fn test_resolve_nested_block() {
+    let (slots, mut body) = resolved(&["x"], "{ |z| x + z + Foo }");
+    assert_eq!(*slots, ["x"]);
+    let mut vars = vec![];
+    addresses(&mut body, &mut vars);
+    assert_eq!(
+        vars,
+        vec![
+            ("x".to_string(), local(1, 0)),
+            ("z".to_string(), local(0, 0)),
+            ("Foo".to_string(), Some(Address::Free)),
+        ]
+    );
+    match body {
+        Expr::Block(block) => assert_eq!(names(&block.frame), Some(vec!["z"])),
+        other => panic!("Expected a block, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_resolve_let_after_block_opens_frame() {
+    let (slots, mut body) = resolved(&["x"], "let a = x. let b = { a }. let c = b. c");
+    assert_eq!(*slots, ["x", "a"]);
+    match &body {
+        Expr::Bind(a) => {
+            assert_eq!((a.slot, names(&a.frame)), (Some(1), None));
+            match a.body.as_deref() {
+                Some(Expr::Bind(b)) => {
+                    assert_eq!((b.slot, names(&b.frame)), (Some(0), Some(vec!["b", "c"])))
+                }
+                other => panic!("Expected a let, got: {:?}", other),
+            }
+        }
+        other => panic!("Expected a let, got: {:?}", other),
+    }
+    let mut vars = vec![];
+    addresses(&mut body, &mut vars);
+    assert_eq!(
+        vars,
+        vec![
+            ("x".to_string(), local(0, 0)),
+            ("a".to_string(), local(1, 1)),
+            ("b".to_string(), local(0, 0)),
+            ("c".to_string(), local(0, 1)),
+        ]
+    );
+}
+
+#[test]
+fn test_shadowing_let() {
+    assert_eq!(
+        eval_ok(
+            "class Foo {}
+                 direct method test
+                     let x = 1.
+                     let f = { x }.
+                     let x = 10.
+                     f value + x!
+             end
+             Foo test"
+        )
+        .integer(),
+        11
+    );
+}
+
+#[test]
+fn test_assign_from_nested_block() {
+    assert_eq!(
+        eval_ok(
+            "class Foo {}
+                 direct method sum: array
+                     let n = 0.
+                     array do: { |each| { n = n + each } value }.
+                     n!
+             end
+             Foo sum: [1, 2, 3]"
+        )
+        .integer(),
+        6
+    );
+}
+
+#[test]
+fn test_patterns_in_method() {
+    assert_eq!(
+        eval_ok(
+            "class Foo {}
+                 direct method test: pair
+                     let [a, b] = pair.
+                     let f = { a }.
+                     b match: { case 0 -> f value
+                                case n -> n + f value }!
+             end
+             (Foo test: [1, 0]) + (Foo test: [1, 2])"
+        )
+        .integer(),
+        4
+    );
+}
+
+#[test]
+fn test_let_is_not_recursive() {
+    match eval_str("{ let f = { f }. f value } value") {
+        Err(Unwind::Panic(error, _)) => assert_eq!(error.what(), "Unbound variable: f"),
+        other => panic!("Expected an error, got: {:?}", other),
+    }
+}
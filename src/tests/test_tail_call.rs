@@ -0,0 +1,115 @@
+use crate::eval::utils::*;
+
+const DEPTH: i64 = 100000;
+
+#[test]
+fn test_self_tail_send() {
+    assert_eq!(
+        eval_ok(&format!(
+            "class Foo {{}}
+                 direct method count: n sum: sum
+                     n == 0 ifTrue: {{ return sum }}.
+                     self count: n - 1 sum: sum + 1!
+             end
+             Foo count: {} sum: 0",
+            DEPTH
+        ))
+        .integer(),
+        DEPTH
+    );
+}
+
+#[test]
+fn test_return_tail_send() {
+    assert_eq!(
+        eval_ok(&format!(
+            "class Foo {{}}
+                 direct method count: n sum: sum
+                     n == 0 ifTrue: {{ return sum }}.
+                     let next = n - 1.
+                     return self count: next sum: sum + 1!
+             end
+             Foo count: {} sum: 0",
+            DEPTH
+        ))
+        .integer(),
+        DEPTH
+    );
+}
+
+#[test]
+fn test_mutual_tail_send() {
+    assert_eq!(
+        eval_ok(&format!(
+            "class Parity {{}}
+                 direct method isEven: n
+                     n == 0
+                         ifTrue: {{ True }}
+                         ifFalse: {{ self isOdd: n - 1 }}!
+                 direct method isOdd: n
+                     n == 0
+                         ifTrue: {{ False }}
+                         ifFalse: {{ self isEven: n - 1 }}!
+             end
+             Parity isEven: {}",
+            DEPTH
+        ))
+        .boolean(),
+        true
+    );
+}
+
+#[test]
+fn test_tail_send_in_let_body() {
+    assert_eq!(
+        eval_ok(&format!(
+            "class Foo {{}}
+                 direct method down: n
+                     let m = n - 1.
+                     m < 0
+                         ifTrue: {{ n }}
+                         ifFalse: {{ self down: m }}!
+             end
+             Foo down: {}",
+            DEPTH
+        ))
+        .integer(),
+        0
+    );
+}
+
+#[test]
+fn test_tail_send_with_returning_block() {
+    assert_eq!(
+        eval_ok(
+            "class Foo {}
+                 direct method find: x in: array
+                     array do: { |each| each == x ifTrue: { return each * 10 } }.
+                     return 0!
+                 direct method find: x
+                     self find: x in: [1, 2, 3]!
+                 direct method findLast: x
+                     [1, 2, 3] do: { |each| each == x ifTrue: { return each } }!
+             end
+             (Foo find: 2) + (Foo findLast: 3)"
+        )
+        .integer(),
+        23
+    );
+}
+
+#[test]
+fn test_tail_send_with_return_type() {
+    match eval_str(
+        "class Foo {}
+             direct method wrong -> Integer
+                 self string!
+             direct method string
+                 \"not an integer\"!
+         end
+         Foo wrong",
+    ) {
+        Err(_) => (),
+        Ok(value) => panic!("Expected a type error, got: {:?}", value),
+    }
+}
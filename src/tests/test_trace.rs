@@ -0,0 +1,42 @@
+use crate::eval::Env;
+use crate::trace::{matches, Filter};
+
+#[test]
+fn test_glob() {
+    assert!(matches("add:", "add:"));
+    assert!(!matches("add:", "add:to:"));
+    assert!(matches("add*", "add:to:"));
+    assert!(matches("*:to:", "add:to:"));
+    assert!(matches("a?d:*", "add:to:"));
+    assert!(matches("*a*b*", "xaxxbx"));
+    assert!(!matches("*a*b", "xaxxbx"));
+    assert!(matches("*", ""));
+    assert!(!matches("?", ""));
+}
+
+#[test]
+fn test_parse_filter() {
+    assert!(Filter::parse(&["selector=add*", "class=Counter", "module=lib/*"]).is_ok());
+    assert_eq!(Filter::parse::<&str>(&[]), Ok(Filter::default()));
+    assert!(Filter::parse(&["add*"]).is_err());
+    assert!(Filter::parse(&["method=add*"]).is_err());
+}
+
+#[test]
+fn test_system_trace() {
+    let env = Env::new();
+    let system = env.foo.make_system(None);
+    let filters = |filters: &[&str]| {
+        env.foo.make_array(
+            &filters.iter().map(|filter| env.foo.make_string(filter)).collect::<Vec<_>>(),
+        )
+    };
+    assert!(system.send("trace:", &[filters(&["add*"])], &env).is_err());
+    assert!(!env.foo.trace.is_enabled());
+    system.send("trace:", &[filters(&["selector=add*"])], &env).unwrap();
+    assert!(env.foo.trace.is_enabled());
+    system.send("trace:", &[env.foo.make_boolean(false)], &env).unwrap();
+    assert!(!env.foo.trace.is_enabled());
+    system.send("trace:", &[env.foo.make_boolean(true)], &env).unwrap();
+    assert!(env.foo.trace.is_enabled());
+}
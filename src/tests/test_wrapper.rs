@@ -0,0 +1,118 @@
+use crate::eval::utils::{eval_exception, eval_ok};
+
+const COUNTER: &str = "
+    class Counter { count }
+        method add: n
+            count = count + n.
+            self!
+        method double
+            count * 2!
+    end
+";
+
+#[test]
+fn test_wrap_interpreter_method() {
+    let source = [
+        COUNTER,
+        "
+        Counter wrap: #add: with: { |original args receiver|
+                                    original invoke: [args first * 10] on: receiver }.
+        (Counter count: 1) add: 2; count",
+    ]
+    .concat();
+    assert_eq!(eval_ok(&source).integer(), 21);
+}
+
+#[test]
+fn test_wrap_reader_method() {
+    let source = [
+        COUNTER,
+        "
+        Counter wrap: #count with: { |original args receiver|
+                                     (original invoke: args on: receiver) + 100 }.
+        (Counter count: 1) count",
+    ]
+    .concat();
+    assert_eq!(eval_ok(&source).integer(), 101);
+}
+
+#[test]
+fn test_wrap_primitive_method() {
+    assert_eq!(
+        eval_ok(
+            "String wrap: #toUppercase with: { |original args receiver|
+                                               (original invoke: args on: receiver) append: \"!\" }.
+             let loud = \"hey\" toUppercase.
+             String unwrap: #toUppercase.
+             [loud, \"hey\" toUppercase]"
+        )
+        .to_string(),
+        "[\"HEY!\", \"HEY\"]"
+    );
+}
+
+#[test]
+fn test_wrap_primitive_method_in_tail_position() {
+    assert_eq!(
+        eval_ok(
+            "class Sign {}
+                 direct method of: n
+                     n < 0 ifTrue: { -1 } ifFalse: { 1 }!
+             end
+             Boolean wrap: #ifTrue:ifFalse: with: { |original args receiver|
+                                                    (original invoke: args on: receiver) * 10 }.
+             let signs = [Sign of: -5, Sign of: 5].
+             Boolean unwrap: #ifTrue:ifFalse:.
+             signs"
+        )
+        .to_string(),
+        "[-10, 10]"
+    );
+}
+
+#[test]
+fn test_wrap_nested_and_unwrap() {
+    let source = [
+        COUNTER,
+        "
+        let c = Counter count: 2.
+        Counter wrap: #double with: { |original args receiver|
+                                      (original invoke: args on: receiver) + 1 }.
+        Counter wrap: #double with: { |original args receiver|
+                                      (original invoke: args on: receiver) * 10 }.
+        let both = c double.
+        Counter unwrap: #double.
+        let inner = c double.
+        Counter unwrap: #double.
+        [both, inner, c double]",
+    ]
+    .concat();
+    assert_eq!(eval_ok(&source).to_string(), "[50, 5, 4]");
+}
+
+#[test]
+fn test_wrap_original_name() {
+    let source = [
+        COUNTER,
+        "
+        Counter wrap: #add: with: { |original args receiver| original name }.
+        (Counter count: 0) add: 1",
+    ]
+    .concat();
+    assert_eq!(eval_ok(&source).string_as_str(), "add:");
+}
+
+#[test]
+fn test_wrap_errors() {
+    let (unwind, _) = eval_exception(
+        &[
+            COUNTER,
+            "
+        Counter wrap: #nope with: { |original args receiver| 0 }",
+        ]
+        .concat(),
+    );
+    assert!(unwind.to_string().contains("Cannot wrap Counter#nope, no such method"));
+    let (unwind, _) = eval_exception(&[COUNTER, "\nCounter unwrap: #add:"].concat());
+    assert!(unwind.to_string().contains("Cannot unwrap Counter#add:, method is not wrapped"));
+}
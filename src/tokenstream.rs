@@ -141,7 +141,7 @@ impl<'a> TokenStream<'a> {
         if self.at_str("--") {
             // println!("scan 6: comment");
             let start = self.consume("--");
-            while !self.at_newline() {
+            while !self.at_eof() && !self.at_newline() {
                 self.next();
             }
             // println!("=> comment");
@@ -0,0 +1,210 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+use crate::eval::Env;
+use crate::objects::{Datum, Eval, Method, Object};
+use crate::unwind::Unwind;
+
+/// Which sends to trace: a send is traced if it matches one of the globs of
+/// each kind given. No globs at all traces everything.
+#[derive(Debug, Default, PartialEq)]
+pub struct Filter {
+    /// Globs for selectors.
+    selectors: Vec<String>,
+    /// Globs for the class of the receiver, or the receiver itself if it is
+    /// a class.
+    classes: Vec<String>,
+    /// Globs for the file defining the method.
+    modules: Vec<String>,
+}
+
+impl Filter {
+    /// Parses specs like selector=GLOB, class=GLOB and module=GLOB.
+    pub fn parse<S: AsRef<str>>(specs: &[S]) -> Result<Filter, String> {
+        let mut filter = Filter::default();
+        for spec in specs {
+            let spec = spec.as_ref();
+            let globs = match spec.find('=').map(|i| (&spec[..i], &spec[i + 1..])) {
+                Some(("selector", glob)) => (&mut filter.selectors, glob),
+                Some(("class", glob)) => (&mut filter.classes, glob),
+                Some(("module", glob)) => (&mut filter.modules, glob),
+                _ => {
+                    return Err(format!(
+                        "Bad trace filter '{}', expected selector=GLOB, class=GLOB or module=GLOB",
+                        spec
+                    ))
+                }
+            };
+            globs.0.push(globs.1.to_string());
+        }
+        Ok(filter)
+    }
+
+    fn matches(&self, receiver: &Object, selector: &str, method: Option<&Method>) -> bool {
+        if !self.selectors.is_empty() && !self.selectors.iter().any(|glob| matches(glob, selector))
+        {
+            return false;
+        }
+        if !self.classes.is_empty() {
+            let name = receiver.vtable_name();
+            let class = name.strip_suffix(" classOf").unwrap_or(name);
+            if !self.classes.iter().any(|glob| matches(glob, class)) {
+                return false;
+            }
+        }
+        if !self.modules.is_empty() {
+            let file = match method {
+                Some(Method::Interpreter(closure)) => {
+                    closure.body.source_location().file().cloned()
+                }
+                _ => None,
+            };
+            let path = match &file {
+                Some(file) => file.to_string_lossy(),
+                None => return false,
+            };
+            if !self
+                .modules
+                .iter()
+                .any(|glob| matches(glob, &path) || matches(&format!("*/{}", glob), &path))
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// True if text matches glob, where * matches any sequence of characters and
+/// ? any one character.
+pub fn matches(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // Backtracking to the last star is enough: a later star can match
+    // anything an earlier one could.
+    let (mut g, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, t));
+                g += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_g, star_t)) => {
+                    g = star_g + 1;
+                    t = star_t + 1;
+                    star = Some((star_g, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+/// Writes a line to stderr when a traced send is made, and another when it
+/// returns or a panic or a non-local return passes through it, indented by
+/// the number of traced sends in progress. Traced sends of interpreted
+/// methods are not tail calls.
+///
+/// Enabled by --trace, and by System#trace: at runtime.
+#[derive(Default)]
+pub struct Trace {
+    enabled: Cell<bool>,
+    filter: RefCell<Filter>,
+    depth: Cell<usize>,
+}
+
+impl PartialEq for Trace {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<Trace {:?}>", self.enabled.get())
+    }
+}
+
+/// Longest rendering of a value in a trace line.
+const MAX_VALUE: usize = 60;
+
+fn value(object: &Object) -> String {
+    let value = match &object.datum {
+        Datum::Class(class) => class.instance_vtable.name.clone(),
+        Datum::Instance(_) => format!("{}", object),
+        _ => format!("{:?}", object),
+    };
+    match value.char_indices().nth(MAX_VALUE) {
+        Some((end, _)) => format!("{}...", &value[..end]),
+        None => value,
+    }
+}
+
+fn message(selector: &str, args: &[Object]) -> String {
+    if args.is_empty() {
+        return selector.to_string();
+    }
+    let keywords: Vec<&str> = selector.split_terminator(':').collect();
+    if selector.ends_with(':') && keywords.len() == args.len() {
+        let parts: Vec<String> = keywords
+            .iter()
+            .zip(args)
+            .map(|(keyword, arg)| format!("{}: {}", keyword, value(arg)))
+            .collect();
+        parts.join(" ")
+    } else {
+        let args: Vec<String> = args.iter().map(value).collect();
+        format!("{} {}", selector, args.join(" "))
+    }
+}
+
+impl Trace {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    /// Starts tracing sends matching filter.
+    pub fn enable(&self, filter: Filter) {
+        self.filter.replace(filter);
+        self.enabled.set(true);
+    }
+
+    pub fn disable(&self) {
+        self.enabled.set(false);
+    }
+
+    /// True if the send of selector to receiver, which is to perform method,
+    /// is traced.
+    pub fn traces(&self, receiver: &Object, selector: &str, method: Option<&Method>) -> bool {
+        self.enabled.get() && self.filter.borrow().matches(receiver, selector, method)
+    }
+
+    /// Sends selector to receiver, tracing the send if it matches.
+    pub fn send(&self, receiver: &Object, selector: &str, args: &[Object], env: &Env) -> Eval {
+        let method = receiver.vtable(&env.foo).get(selector);
+        if !self.traces(receiver, selector, method.as_ref()) {
+            return receiver.dispatch(selector, args, env);
+        }
+        let depth = self.depth.get();
+        let indent = "  ".repeat(depth);
+        eprintln!("{}-> {} {}", indent, value(receiver), message(selector, args));
+        self.depth.set(depth + 1);
+        let res = receiver.dispatch(selector, args, env);
+        self.depth.set(depth);
+        match &res {
+            Ok(result) => eprintln!("{}<- {}", indent, value(result)),
+            Err(Unwind::Panic(error, _)) => eprintln!("{}<- panic: {}", indent, error.what()),
+            Err(Unwind::ReturnFrom(_, result)) => {
+                eprintln!("{}<- non-local return {}", indent, value(result))
+            }
+        }
+        res
+    }
+}
@@ -43,6 +43,7 @@ pub enum Unwind {
 
 #[derive(PartialEq, Debug)]
 pub enum Error {
+    BlockContextExpired(BlockContextExpired),
     MessageError(MessageError),
     SimpleError(SimpleError),
     TypeError(TypeError),
@@ -57,6 +58,13 @@ pub struct MessageError {
     pub arguments: Vec<Object>,
 }
 
+/// Non-local return from a block whose method has already returned.
+#[derive(PartialEq, Debug)]
+pub struct BlockContextExpired {
+    /// Start of the method the block tried to return from.
+    pub method: Location,
+}
+
 #[derive(PartialEq, Debug)]
 pub struct SimpleError {
     pub what: String,
@@ -77,10 +85,22 @@ pub struct Location {
 impl fmt::Display for Unwind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Unwind::Panic(error, location) => match &location.context {
-                Some(c) => write!(f, "ERROR: {}\n{}", error.what(), c),
-                None => write!(f, "ERROR: {} (no context)", error.what()),
-            },
+            Unwind::Panic(error, location) => {
+                match &location.context {
+                    Some(c) => write!(f, "ERROR: {}\n{}", error.what(), c)?,
+                    None => write!(f, "ERROR: {} (no context)", error.what())?,
+                }
+                match error {
+                    Error::BlockContextExpired(BlockContextExpired {
+                        method:
+                            Location {
+                                context: Some(c),
+                                ..
+                            },
+                    }) => write!(f, "{}", c),
+                    _ => Ok(()),
+                }
+            }
             Unwind::ReturnFrom(_, object) => write!(f, "#<Return {}>", object),
         }
     }
@@ -168,6 +188,26 @@ impl Unwind {
         }
     }
 
+    pub fn block_context_expired_at<T>(
+        block: SourceLocation,
+        mut method: SourceLocation,
+    ) -> Result<T, Unwind> {
+        let start = method.get_span().start;
+        method.set_span(&(start..start + 1));
+        let mut expired = BlockContextExpired {
+            method: Location::new(method.clone()),
+        };
+        if let Some(code) = method.code() {
+            expired.add_context(&code);
+        }
+        let code = block.code();
+        let unwind = Unwind::Panic(Error::BlockContextExpired(expired), Location::new(block));
+        match code {
+            Some(code) => Err(unwind.with_context(&code)),
+            None => Err(unwind),
+        }
+    }
+
     pub fn return_from<T>(env: EnvRef, value: Object) -> Result<T, Unwind> {
         Err(Unwind::ReturnFrom(env, value))
     }
@@ -209,6 +249,9 @@ impl Unwind {
     pub fn with_context(mut self, source: &str) -> Unwind {
         if let Unwind::Panic(error, location) = &mut self {
             location.add_context(source, error.what());
+            if let Error::BlockContextExpired(expired) = error {
+                expired.add_context(source);
+            }
         }
         self
     }
@@ -217,6 +260,7 @@ impl Unwind {
 impl Error {
     pub fn what(&self) -> String {
         match self {
+            Error::BlockContextExpired(e) => e.what(),
             Error::MessageError(e) => e.what(),
             Error::SimpleError(e) => e.what(),
             Error::TypeError(e) => e.what(),
@@ -234,6 +278,16 @@ impl MessageError {
     }
 }
 
+impl BlockContextExpired {
+    pub fn what(&self) -> String {
+        "Block context expired: cannot return from a method which has already returned".to_string()
+    }
+
+    fn add_context(&mut self, source: &str) {
+        self.method.add_context(source, "Method which already returned".to_string());
+    }
+}
+
 impl SimpleError {
     pub fn what(&self) -> String {
         self.what.clone()
@@ -245,7 +299,7 @@ impl TypeError {
         format!(
             "{} expected, got {}: {:?} (bootstrap evaluator)",
             self.expected,
-            self.value.vtable.name.clone(),
+            self.value.vtable_name(),
             self.value,
         )
     }
@@ -0,0 +1,119 @@
+use assert_cmd::Command;
+use serde_json::{json, Value};
+
+type Test = Result<(), Box<dyn std::error::Error>>;
+
+/// Frames requests as the client would send them.
+fn session(requests: &[(&str, Value)]) -> String {
+    let mut input = String::new();
+    for (seq, (command, arguments)) in requests.iter().enumerate() {
+        let body = json!({
+            "seq": seq + 1,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        input.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+    }
+    input
+}
+
+fn messages(mut output: &str) -> Vec<Value> {
+    let mut messages = Vec::new();
+    while let Some(start) = output.find("\r\n\r\n") {
+        let length: usize = output["Content-Length: ".len()..start].parse().unwrap();
+        let body = &output[start + 4..start + 4 + length];
+        messages.push(serde_json::from_str(body).unwrap());
+        output = &output[start + 4 + length..];
+    }
+    assert_eq!(output, "");
+    messages
+}
+
+fn response<'a>(messages: &'a [Value], request_seq: u64) -> &'a Value {
+    let response = messages
+        .iter()
+        .find(|message| message["type"] == "response" && message["request_seq"] == request_seq)
+        .expect("no response");
+    assert_eq!(response["success"], true, "{}", response);
+    &response["body"]
+}
+
+fn events<'a>(messages: &'a [Value], name: &str) -> Vec<&'a Value> {
+    messages.iter().filter(|message| message["event"] == name).map(|event| &event["body"]).collect()
+}
+
+#[test]
+fn test_dap_session() -> Test {
+    let program = "foo/tests/test_debug.foo";
+    let input = session(&[
+        ("initialize", json!({"adapterID": "foolang"})),
+        ("launch", json!({ "program": program })),
+        ("setBreakpoints", json!({"source": {"path": program}, "breakpoints": [{"line": 5}]})),
+        ("setFunctionBreakpoints", json!({"breakpoints": [{"name": "Counter#addAll:"}]})),
+        ("configurationDone", json!({})),
+        ("threads", json!({})),
+        ("stackTrace", json!({"threadId": 1})),
+        ("scopes", json!({"frameId": 0})),
+        ("variables", json!({"variablesReference": 1})),
+        ("variables", json!({"variablesReference": 2})),
+        ("evaluate", json!({"expression": "n + 41", "frameId": 0})),
+        ("stepOut", json!({"threadId": 1})),
+        ("stackTrace", json!({"threadId": 1})),
+        ("continue", json!({"threadId": 1})),
+        ("stackTrace", json!({"threadId": 1})),
+        ("setBreakpoints", json!({"source": {"path": program}, "breakpoints": []})),
+        ("continue", json!({"threadId": 1})),
+        ("disconnect", json!({})),
+    ]);
+    let output = Command::cargo_bin("foolang-dap")?.write_stdin(input).assert().success();
+    let messages = messages(std::str::from_utf8(&output.get_output().stdout)?);
+
+    assert_eq!(response(&messages, 1)["supportsFunctionBreakpoints"], true);
+    assert_eq!(events(&messages, "initialized").len(), 1);
+    assert_eq!(response(&messages, 3)["breakpoints"], json!([{"verified": true, "line": 5}]));
+    let reasons: Vec<&Value> =
+        events(&messages, "stopped").iter().map(|stopped| &stopped["reason"]).collect();
+    assert_eq!(reasons, vec!["breakpoint", "step", "breakpoint"]);
+    assert_eq!(response(&messages, 6)["threads"], json!([{"id": 1, "name": "Main"}]));
+
+    let frames = &response(&messages, 7)["stackFrames"];
+    assert_eq!(frames[0]["name"], "Counter#add:");
+    assert_eq!(frames[0]["line"], 5);
+    assert_eq!(frames[0]["column"], 9);
+    assert!(frames[0]["source"]["path"].as_str().unwrap().ends_with(program));
+    assert_eq!(frames[1]["name"], "Main##run:in:");
+    assert_eq!(frames[1]["line"], 15);
+
+    let scopes = &response(&messages, 8)["scopes"];
+    assert_eq!(scopes[0]["name"], "Locals");
+    assert_eq!(scopes[0]["variablesReference"], 1);
+    assert_eq!(scopes[1]["name"], "Receiver");
+    assert_eq!(scopes[1]["variablesReference"], 2);
+    assert_eq!(
+        response(&messages, 9)["variables"],
+        json!([{"name": "n", "value": "1", "variablesReference": 0}])
+    );
+    assert_eq!(
+        response(&messages, 10)["variables"],
+        json!([{"name": "total", "value": "0", "variablesReference": 0}])
+    );
+    assert_eq!(response(&messages, 11)["result"], "42");
+
+    let frames = &response(&messages, 13)["stackFrames"];
+    assert_eq!(frames[0]["name"], "Main##run:in:");
+    assert_eq!(frames[0]["line"], 16);
+    let frames = &response(&messages, 15)["stackFrames"];
+    assert_eq!(frames[0]["name"], "Counter#addAll:");
+    assert_eq!(frames[0]["line"], 8);
+
+    let output: Vec<&Value> =
+        events(&messages, "output").iter().map(|body| &body["output"]).collect();
+    let output: String = output.iter().map(|each| each.as_str().unwrap()).collect();
+    assert_eq!(output, "total 6\n");
+    assert_eq!(events(&messages, "exited"), vec![&json!({"exitCode": 0})]);
+    assert_eq!(events(&messages, "terminated").len(), 1);
+    response(&messages, 18);
+    Ok(())
+}
@@ -49,6 +49,314 @@ fn test_test() -> Test {
     Ok(())
 }
 
+#[test]
+fn test_command_tap() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("test").arg("foo/tests/suite_outcomes.foo");
+    cmd.assert()
+        .failure()
+        .code(1)
+        .stdout(predicates::str::starts_with(
+            "TAP version 13\n1..3\nnot ok 1 - TestOutcomes.test_error",
+        ))
+        .stdout(predicates::str::contains(concat!(
+            "  error: |\n",
+            "    ERROR: Oops\n",
+            "    004     method test_error\n",
+            "    005         panic \"Oops\"!\n",
+        )))
+        .stdout(predicates::str::contains(concat!(
+            "  message: assertion failed\n",
+            "  file: foo/tests/suite_outcomes.foo\n",
+            "  output: |\n",
+            "    ! FAILURE in arithmetic, expected 3, got 2.\n",
+            "  ...\n",
+            "ok 3 - TestOutcomes.test_success # time=",
+        )))
+        .stdout(predicates::str::contains("test_ignored").not());
+    Ok(())
+}
+
+#[test]
+fn test_command_discovery() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("test").arg("foo/tests");
+    cmd.assert().success().stdout("TAP version 13\n1..0\n");
+    Ok(())
+}
+
+#[test]
+fn test_command_filter() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("test").arg("foo/tests/suite_outcomes.foo").arg("foo/lang/test_float.foo");
+    cmd.arg("--filter=TestOutcomes.test_success").arg("--filter=floatAdd");
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::starts_with(concat!(
+            "TAP version 13\n",
+            "1..2\n",
+            "ok 1 - TestOutcomes.test_success # time=",
+        )))
+        .stdout(predicates::str::contains("\nok 2 - TestFloat.test_floatAdd # time="));
+    Ok(())
+}
+
+#[test]
+fn test_command_junit() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("test").arg("foo/tests/suite_outcomes.foo").arg("--format=junit");
+    cmd.assert()
+        .failure()
+        .code(1)
+        .stdout(predicates::str::starts_with(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<testsuites tests=\"3\" failures=\"1\" errors=\"1\" time=\""
+        )))
+        .stdout(predicates::str::contains(concat!(
+            "      <failure message=\"assertion failed\">",
+            "! FAILURE in arithmetic, expected 3, got 2.</failure>\n",
+            "    </testcase>\n",
+        )))
+        .stdout(predicates::str::contains("panic &quot;Oops&quot;!"))
+        .stdout(predicates::str::ends_with("  </testsuite>\n</testsuites>\n"));
+    Ok(())
+}
+
+#[test]
+fn test_coverage() -> Test {
+    let report = std::env::temp_dir().join(format!("foolang-lcov-{}.info", std::process::id()));
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_coverage.foo").arg("--coverage").arg(&report);
+    cmd.assert().failure().code(3).stdout("positive\nnegative -1\n");
+    let lcov = std::fs::read_to_string(&report)?;
+    std::fs::remove_file(&report)?;
+    let start = lcov.find("/foo/tests/coverage.foo\n").expect("no record for coverage.foo");
+    let record = &lcov[start..lcov[start..].find("end_of_record").unwrap() + start];
+    assert!(record.contains("FN:2,Branches##sign:\n"));
+    assert!(record.contains("FNDA:1,Branches##sign:\n"));
+    assert!(record.contains("FNDA:0,Branches##unused\n"));
+    assert!(record.contains("BRDA:4,0,0,0\nBRDA:4,0,1,1\n"));
+    assert!(record.contains("BRDA:8,0,0,1\nBRDA:8,0,1,0\n"));
+    assert!(record.contains("DA:4,0\nDA:5,1\n"));
+    assert!(record.contains("DA:12,0\nLF:8\nLH:5\n"));
+    assert!(lcov.contains("/foo/lang/boolean_ext.foo\n"));
+    Ok(())
+}
+
+#[test]
+fn test_debug_script() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_debug.foo")
+        .arg("--debug-script")
+        .arg("foo/tests/test_debug.script")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "(debug) continue\n\
+             Breakpoint in Main##run:in: at foo/tests/test_debug.foo:14\n",
+        ))
+        .stdout(predicate::str::contains(
+            "(debug) next\n\
+             Stopped in Main##run:in: at foo/tests/test_debug.foo:15\n\
+             \x20  15 |       counter add: 1.\n\
+             (debug) step\n\
+             Stopped in Counter#add: at foo/tests/test_debug.foo:5\n",
+        ))
+        .stdout(predicate::str::contains(
+            "(debug) finish\n\
+             Stopped in Main##run:in: at foo/tests/test_debug.foo:16\n",
+        ))
+        .stdout(predicate::str::contains("#0 Counter#add: at foo/tests/test_debug.foo:5:9\n"))
+        .stdout(predicate::str::contains("Counter#addAll: at foo/tests/test_debug.foo:8:"))
+        .stdout(predicate::str::contains(
+            "(debug) env\n\
+             self = Counter\n\
+             \x20 total = 1\n\
+             n = 2\n\
+             (debug) print n * 10\n\
+             20\n",
+        ))
+        .stdout(predicate::str::contains(
+            "Breakpoint in Main##run:in: at foo/tests/test_debug.foo:17\n",
+        ))
+        .stdout(predicate::str::contains("(debug) print counter total\n6\n"))
+        .stdout(predicate::str::ends_with("(debug) continue\ntotal 6\n"));
+    Ok(())
+}
+
+#[test]
+fn test_debug_stdin() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_debug.foo")
+        .arg("--debug")
+        .write_stdin("break test_debug.foo:5\nc\np total\nquit\n")
+        .assert()
+        .success()
+        .stdout(
+            "Debugging foo/tests/test_debug.foo, type help for commands.\n\
+             (debug) Breakpoint 1: test_debug.foo:5\n\
+             (debug) Breakpoint in Counter#add: at foo/tests/test_debug.foo:5\n\
+             \x20   5 |         total = total + n.\n\
+             (debug) 0\n\
+             (debug) ",
+        );
+    Ok(())
+}
+
+#[test]
+fn test_debug_generator() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_debug_generator.foo")
+        .arg("--debug")
+        .write_stdin(concat!(
+            "break test_debug_generator.foo:9\nc\nc\nwhere\n",
+            "delete 1\nbreak test_debug_generator.foo:4\nc\nwhere\nc\n"
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "(debug) #0 Main##next: at foo/tests/test_debug_generator.foo:9:9\n\
+             #1 Main##run:in: at foo/tests/test_debug_generator.foo:13:28\n\
+             (debug) Deleted breakpoint 1\n",
+        ))
+        .stdout(predicate::str::contains(
+            "(debug) #0 block at foo/tests/test_debug_generator.foo:4:44\n",
+        ))
+        .stdout(predicate::str::contains(
+            "Number#to:do: at foo/lang/number.foo:85:24\n\
+             #4 Main##run:in: at foo/tests/test_debug_generator.foo:13:28\n\
+             (debug) sum 3\n",
+        ));
+    Ok(())
+}
+
+#[test]
+fn test_trace_filters() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_debug.foo")
+        .arg("--trace=class=Counter")
+        .arg("--trace=selector=new")
+        .assert()
+        .success()
+        .stdout("total 6\n")
+        .stderr("-> Counter new\n<- #<instance Counter>\n");
+    Ok(())
+}
+
+#[test]
+fn test_trace_tail_send() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_trace_tail.foo")
+        .arg("--trace=selector=ifTrue:ifFalse:")
+        .assert()
+        .success()
+        .stdout("positive\n")
+        .stderr("-> False ifTrue: #<Closure []> ifFalse: #<Closure []>\n<- \"positive\"\n");
+    Ok(())
+}
+
+#[test]
+fn test_dump_tokens() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("dump-tokens")
+        .arg("foo/tests/test_dump.foo")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "0..5 WORD \"class\"\n\
+             6..11 WORD \"Point\"\n\
+             12..13 SIGIL \"{\"\n",
+        ))
+        .stdout(predicate::str::ends_with("123..123 EOF \"\"\n"));
+    let output = Command::cargo_bin("bootstrap-interpreter")?
+        .arg("dump-tokens")
+        .arg("--json")
+        .arg("foo/tests/test_dump.foo")
+        .output()?;
+    let tokens: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(
+        tokens[6],
+        serde_json::json!({"token": "WORD", "span": [24, 30], "slice": "method"})
+    );
+    Ok(())
+}
+
+#[test]
+fn test_dump_ast() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("dump-ast")
+        .arg("foo/tests/test_dump.foo")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "ClassDef 0..5 name=\"Point\" value=false\n\
+             \x20 instance_variables:\n\
+             \x20   Var 14..15 name=\"x\" dynamic=false\n",
+        ))
+        .stdout(predicate::str::contains(
+            "DefineDef 95..101 name=\"Origin\"\n\
+             \x20 init: Chain 106..121\n\
+             \x20   receiver: Var 106..111 name=\"Point\" dynamic=false\n\
+             \x20   messages:\n\
+             \x20     Message 112..121 selector=\"x:y:\"\n\
+             \x20       args:\n\
+             \x20         Const 115..116 literal=\"Integer\" value=0\n",
+        ));
+    let output = Command::cargo_bin("bootstrap-interpreter")?
+        .arg("dump-ast")
+        .arg("--json")
+        .arg("foo/tests/test_dump.foo")
+        .output()?;
+    let ast: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(ast[0]["kind"], "ClassDef");
+    assert_eq!(ast[0]["instance_methods"][0]["selector"], "+");
+    assert_eq!(ast[0]["instance_methods"][0]["body"]["span"], serde_json::json!([47, 82]));
+    assert_eq!(ast[1]["kind"], "DefineDef");
+    Ok(())
+}
+
+#[test]
+fn test_dump_ast_source() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("dump-ast").arg("--source").arg("foo/tests/test_dump.foo").assert().success().stdout(
+        "class Point { x y }\n\
+             \n\
+             \x20   method + other\n\
+             \x20       Point x: x + other x y: y + other y!\n\
+             end\n\
+             \n\
+             define Origin Point x: 0 y: 0!\n",
+    );
+    Ok(())
+}
+
+#[test]
+fn test_dump_ast_parse_error() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("dump-ast")
+        .arg("foo/tests/test_bad_class.foo")
+        .assert()
+        .failure()
+        .stdout(predicate::str::starts_with("FATAL - "));
+    Ok(())
+}
+
+#[test]
+fn test_trace_at_runtime() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_trace.foo")
+        .assert()
+        .failure()
+        .stdout(predicate::str::starts_with("total 6\nFATAL - ERROR: too big\n"))
+        .stderr(
+            "-> #<instance Counter> add: 2\n\
+             <- #<instance Counter>\n\
+             -> #<instance Counter> check\n\
+             <- panic: too big\n",
+        );
+    Ok(())
+}
+
 #[test]
 fn test_abort() -> Test {
     let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
@@ -445,6 +753,89 @@ fn test_import_x_star() -> Test {
     Ok(())
 }
 
+#[test]
+fn test_operator_scope() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_operator_scope.foo");
+    cmd.assert().success().stdout("10\n14\n");
+    Ok(())
+}
+
+#[test]
+fn test_process_message_order() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_process_order.foo");
+    cmd.assert().success().stdout("10\n20\n30\n40\n50\n");
+    Ok(())
+}
+
+#[test]
+fn test_process_restart() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_process_restart.foo");
+    cmd.assert()
+        .success()
+        .stdout(concat!(
+            // One for one: the second counter keeps its count.
+            "Counter 1\n",
+            "Counter 2\n",
+            "Counter 1\n",
+            "Counter 1\n",
+            "Counter 2\n",
+            // One for all: both counters restart.
+            "Counter 1\n",
+            "Counter 2\n",
+            "Counter 1\n",
+            "Counter 1\n",
+            "Counter 1\n",
+        ))
+        .stderr(predicates::str::contains("ERROR in process Counter: ERROR: Crash requested"));
+    Ok(())
+}
+
+#[test]
+fn test_process_busy_sibling() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_process_busy_sibling.foo");
+    cmd.assert()
+        .success()
+        // The counter restarts, even though Busy never dies.
+        .stdout("Counter 1\nCounter 1\n")
+        .stderr(predicates::str::contains(
+            "Supervisor could not kill process Busy, not restarting it",
+        ));
+    Ok(())
+}
+
+#[test]
+fn test_gc() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_gc.foo");
+    cmd.assert().success().stdout("1\n6\n0\n");
+    Ok(())
+}
+
+#[test]
+fn test_heap_report() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_gc.foo");
+    cmd.arg("--heap-report");
+    cmd.assert()
+        .success()
+        .stdout("1\n6\n0\n")
+        .stderr(predicates::str::contains("Heap report:"))
+        .stderr(predicates::str::contains("Instance"));
+    Ok(())
+}
+
+#[test]
+fn test_finalizer() -> Test {
+    let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
+    cmd.arg("foo/tests/test_finalizer.foo");
+    cmd.assert().success().stdout("True\nfinalized box\nfinalized cycle\nFalse\n");
+    Ok(())
+}
+
 #[test]
 fn test_import_bar_y() -> Test {
     let mut cmd = Command::cargo_bin("bootstrap-interpreter")?;
@@ -566,6 +957,36 @@ fn test_benchmarks() -> Test {
     Ok(())
 }
 
+#[test]
+fn test_bench_baseline() -> Test {
+    let baseline = std::env::temp_dir().join(format!("foolang-bench-{}.json", std::process::id()));
+    Command::cargo_bin("bench")?
+        .args(&["--iterations", "2", "--filter", "Fib", "--save"])
+        .arg(&baseline)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("benchmark "))
+        .stdout(predicate::str::contains("\nFibonacci "))
+        .stdout(predicate::str::contains("Ackermann").not());
+    let json = std::fs::read_to_string(&baseline)?;
+    assert!(json.contains("\"name\": \"Fibonacci\""));
+    assert!(json.contains("\"iterations\": 2"));
+    // Nothing can run in no time at all.
+    let mut value: serde_json::Value = serde_json::from_str(&json)?;
+    value["benchmarks"][0]["median"] = serde_json::json!(1e-12);
+    std::fs::write(&baseline, value.to_string())?;
+    let res = Command::cargo_bin("bench")?
+        .args(&["--iterations", "1", "--filter", "Fib", "--baseline"])
+        .arg(&baseline)
+        .assert();
+    std::fs::remove_file(&baseline)?;
+    res.failure()
+        .code(1)
+        .stdout(predicate::str::contains("REGRESSION"))
+        .stdout(predicate::str::contains("1 benchmark(s) regressed by more than 10%"));
+    Ok(())
+}
+
 #[test]
 #[ignore]
 fn example_flying() -> Test {